@@ -0,0 +1,122 @@
+use std::collections::BTreeSet;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use meteen_model::{Database, Operation, Priority, Project, Task};
+
+const PROJECTS: usize = 100;
+const TASKS_PER_PROJECT: usize = 200;
+const TASKS_IN_LARGE_PROJECT: usize = 10_000;
+
+fn task(task_id: String) -> Task {
+    Task {
+        summary: format!("Task {}", task_id),
+        task_id,
+        done: false,
+        scheduled: None,
+        deadline: None,
+        priority: Priority::Standard,
+        recurrence: None,
+        parent_task_id: None,
+        sort_key: String::new(),
+        labels: BTreeSet::new(),
+        description: None,
+        created_at: None,
+        updated_at: None,
+        completed_at: None,
+    }
+}
+
+/// A vault with 20 000 tasks spread over 100 projects.
+fn large_vault() -> Database {
+    let mut db = Database::new();
+    for p in 0..PROJECTS {
+        let project_id = format!("project-{}", p);
+        db.apply_operation(Operation::CreateProject {
+            project: Project {
+                name: project_id.clone(),
+                project_id: project_id.clone(),
+                parent_id: None,
+                tasks: vec![],
+                color: None,
+                icon: None,
+                description: None,
+                sort_key: String::new(),
+                created_at: None,
+                updated_at: None,
+            },
+            at: None,
+        });
+        for t in 0..TASKS_PER_PROJECT {
+            db.apply_operation(Operation::CreateTask {
+                task: task(format!("task-{}-{}", p, t)),
+                project_id: Some(project_id.clone()),
+                at: None,
+            });
+        }
+    }
+    db
+}
+
+/// A vault with 10 000 tasks, all in the inbox.
+fn large_project() -> Database {
+    let mut db = Database::new();
+    for t in 0..TASKS_IN_LARGE_PROJECT {
+        db.apply_operation(Operation::CreateTask {
+            task: task(format!("task-{}", t)),
+            project_id: None,
+            at: None,
+        });
+    }
+    db
+}
+
+fn lookups(c: &mut Criterion) {
+    let db = large_vault();
+
+    c.bench_function("get_task", |b| {
+        b.iter(|| db.get_task(black_box("task-99-199")))
+    });
+    c.bench_function("project_of", |b| {
+        b.iter(|| db.project_of(black_box("task-50-100")).is_ok())
+    });
+
+    // Syncing a batch of edits spread over the whole vault
+    let ops: Vec<Operation> = (0..1000)
+        .map(|i| Operation::UpdateTaskSummary {
+            task_id: format!("task-{}-{}", i % PROJECTS, i % TASKS_PER_PROJECT),
+            summary: format!("Edited {}", i),
+            at: None,
+        })
+        .collect();
+    c.bench_function("sync 1000 edits", |b| {
+        b.iter_batched(
+            || (db.clone(), ops.clone()),
+            |(mut db, ops)| db.batch_operations(ops),
+            BatchSize::LargeInput,
+        )
+    });
+
+    let db = large_project();
+
+    c.bench_function("get_task in large project", |b| {
+        b.iter(|| db.get_task(black_box("task-9999")))
+    });
+
+    let ops: Vec<Operation> = (0..1000)
+        .map(|i| Operation::UpdateTaskSummary {
+            task_id: format!("task-{}", i * 10),
+            summary: format!("Edited {}", i),
+            at: None,
+        })
+        .collect();
+    c.bench_function("sync 1000 edits in large project", |b| {
+        b.iter_batched(
+            || (db.clone(), ops.clone()),
+            |(mut db, ops)| db.batch_operations(ops),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
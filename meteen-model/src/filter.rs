@@ -0,0 +1,872 @@
+//! A small language for filtering tasks, like `overdue & priority >= high & !done` or
+//! `scheduled:this-week in:work/*`.
+//!
+//! Terms are combined with `&`, or by just putting them next to each other, `|` and `!`, and
+//! grouped with parentheses. `&` binds tighter than `|`. The terms are:
+//!
+//! - `done`, `open`, `overdue`, `recurring` and `subtask`, also written as `is:done`
+//! - `priority` compared to `low`, `standard`, `high` or `urgent`, as in `priority >= high`
+//! - `scheduled`, `deadline`, `created`, `updated` and `completed` compared to `today`,
+//!   `tomorrow`, `yesterday`, `this-week`, `next-week`, `last-week`, `this-month`, `next-month`,
+//!   `last-month` or a date like `2024-10-01`. `scheduled:this-week` means within that range,
+//!   `deadline < today` before it. `deadline:none` and `deadline:any` test whether there is one.
+//! - `in:work` for the tasks in the project named Work and `in:work/*` for those in Work and its
+//!   subprojects. Nested projects are written as their path, `in:work/clients`.
+//! - `label:phone`, or `@phone` and `#waiting`, for the tasks with such a label
+//! - any other word, or text in quotes, searches the summary and description
+//!
+//! Names are matched ignoring case. Dates are taken relative to a given now, in the timezone of
+//! that now, and weeks start on Monday.
+
+use std::{cmp::Ordering, str::FromStr};
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, NaiveTime, Utc};
+use thiserror::Error;
+
+use crate::{Database, DateOrDateTime, Priority, Project, Task};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Filter {
+    /// Matches every task, the filter of an empty expression
+    All,
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Done,
+    /// Open tasks whose deadline has passed
+    Overdue,
+    Recurring,
+    Subtask,
+    Priority(Comparison, Priority),
+    Date(DateField, Comparison, DateValue),
+    /// Tasks in the project with this path of lowercase names, or also in its subprojects
+    In {
+        path: Vec<String>,
+        nested: bool,
+    },
+    /// Tasks with a label of this name, ignoring case and a leading `@` or `#`
+    Label(String),
+    /// Tasks containing this text in their summary or description, ignoring case
+    Text(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DateField {
+    Scheduled,
+    Deadline,
+    Created,
+    Updated,
+    Completed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DateValue {
+    /// The task has no such date
+    None,
+    /// The task has such a date
+    Any,
+    /// The day this many days from today
+    Day(i64),
+    /// The week this many weeks from this week
+    Week(i32),
+    /// The month this many months from this month
+    Month(i32),
+    Date(NaiveDate),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FilterParseError {
+    #[error("The filter ends unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("Unexpected {0} in the filter")]
+    UnexpectedToken(String),
+
+    #[error("A quote in the filter isn't closed")]
+    UnclosedQuote,
+
+    #[error("{0} is not a known field")]
+    UnknownField(String),
+
+    #[error("{0} can't be compared")]
+    NotComparable(String),
+
+    #[error("{value} is not a valid value for {field}")]
+    InvalidValue { field: String, value: String },
+
+    #[error("The filter is nested too deeply")]
+    TooDeep,
+}
+
+fn invalid(field: &str, value: &str) -> FilterParseError {
+    FilterParseError::InvalidValue {
+        field: field.into(),
+        value: value.into(),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    Not,
+    Compare(Comparison),
+    /// A word, which is text to search for when it started with a quote
+    Word {
+        text: String,
+        quoted: bool,
+    },
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::OpenParen => "(".into(),
+            Token::CloseParen => ")".into(),
+            Token::And => "&".into(),
+            Token::Or => "|".into(),
+            Token::Not => "!".into(),
+            Token::Compare(_) => "comparison".into(),
+            Token::Word { text, .. } => text.clone(),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterParseError> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '=' => Token::Compare(Comparison::Equal),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            c => {
+                let quoted = c == '"';
+                let mut text = String::new();
+                let mut next = Some(c);
+                while let Some(c) = next {
+                    if c == '"' {
+                        // Quotes group everything up to the closing quote into the word
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some(c) => text.push(c),
+                                None => return Err(FilterParseError::UnclosedQuote),
+                            }
+                        }
+                    } else {
+                        text.push(c);
+                    }
+                    next = chars.next_if(|c| !c.is_whitespace() && !"()&|!=<>".contains(*c));
+                }
+                Token::Word { text, quoted }
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// How deeply parentheses, negations and chains of `&` and `|` may nest, so parsing and
+/// matching a filter can't run out of stack
+const MAX_DEPTH: usize = 100;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, FilterParseError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or(FilterParseError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Goes one level deeper into the filter.
+    fn nest(&mut self) -> Result<(), FilterParseError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(FilterParseError::TooDeep);
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterParseError> {
+        let depth = self.depth;
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            self.nest()?;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterParseError> {
+        let depth = self.depth;
+        let mut filter = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.position += 1,
+                // Terms next to each other must all match
+                Some(Token::OpenParen | Token::Not | Token::Word { .. }) => {}
+                _ => {
+                    self.depth = depth;
+                    return Ok(filter);
+                }
+            }
+            self.nest()?;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Filter, FilterParseError> {
+        let depth = self.depth;
+        let filter = match self.next()? {
+            Token::Not => {
+                self.nest()?;
+                Filter::Not(Box::new(self.unary()?))
+            }
+            Token::OpenParen => {
+                self.nest()?;
+                let filter = self.or()?;
+                match self.next()? {
+                    Token::CloseParen => filter,
+                    token => return Err(FilterParseError::UnexpectedToken(token.describe())),
+                }
+            }
+            Token::Word { text, quoted: true } => Filter::Text(text.to_lowercase()),
+            Token::Word { text, .. } => self.term(text)?,
+            token => return Err(FilterParseError::UnexpectedToken(token.describe())),
+        };
+        self.depth = depth;
+        Ok(filter)
+    }
+
+    fn term(&mut self, word: String) -> Result<Filter, FilterParseError> {
+        if let Some(Token::Compare(comparison)) = self.peek() {
+            let comparison = *comparison;
+            self.position += 1;
+            return match self.next()? {
+                Token::Word { text, .. } => field(&word, comparison, &text),
+                token => Err(FilterParseError::UnexpectedToken(token.describe())),
+            };
+        }
+
+        if let Some((name, value)) = word.split_once(':') {
+            return field(name, Comparison::Equal, value);
+        }
+        if word.starts_with(['@', '#']) {
+            return Ok(Filter::Label(label_name(&word)));
+        }
+        Ok(keyword(&word).unwrap_or_else(|| Filter::Text(word.to_lowercase())))
+    }
+}
+
+fn keyword(word: &str) -> Option<Filter> {
+    Some(match word.to_lowercase().as_str() {
+        "done" => Filter::Done,
+        "open" => Filter::Not(Box::new(Filter::Done)),
+        "overdue" => Filter::Overdue,
+        "recurring" => Filter::Recurring,
+        "subtask" => Filter::Subtask,
+        _ => return None,
+    })
+}
+
+/// A term comparing a field with a value, like `priority >= high` or `in:work`.
+fn field(name: &str, comparison: Comparison, value: &str) -> Result<Filter, FilterParseError> {
+    let date_field = match name.to_lowercase().as_str() {
+        "priority" => return Ok(Filter::Priority(comparison, parse_priority(value)?)),
+        "scheduled" => DateField::Scheduled,
+        "deadline" => DateField::Deadline,
+        "created" => DateField::Created,
+        "updated" => DateField::Updated,
+        "completed" => DateField::Completed,
+        "in" | "label" | "is" | "text" if comparison != Comparison::Equal => {
+            return Err(FilterParseError::NotComparable(name.into()))
+        }
+        "in" => {
+            let (path, nested) = match value.strip_suffix("/*") {
+                Some(path) => (path, true),
+                None => (value, false),
+            };
+            let path: Vec<String> = path
+                .split('/')
+                .map(|name| name.trim().to_lowercase())
+                .collect();
+            if path.iter().any(String::is_empty) {
+                return Err(invalid(name, value));
+            }
+            return Ok(Filter::In { path, nested });
+        }
+        "label" => return Ok(Filter::Label(label_name(value))),
+        "is" => return keyword(value).ok_or_else(|| invalid(name, value)),
+        "text" => return Ok(Filter::Text(value.to_lowercase())),
+        _ => return Err(FilterParseError::UnknownField(name.into())),
+    };
+
+    let date = parse_date_value(name, value)?;
+    let tests_presence = matches!(date, DateValue::None | DateValue::Any);
+    if tests_presence && !matches!(comparison, Comparison::Equal | Comparison::NotEqual) {
+        return Err(invalid(name, value));
+    }
+    Ok(Filter::Date(date_field, comparison, date))
+}
+
+fn parse_priority(value: &str) -> Result<Priority, FilterParseError> {
+    Ok(match value.to_lowercase().as_str() {
+        "low" => Priority::Low,
+        "standard" => Priority::Standard,
+        "high" => Priority::High,
+        "urgent" => Priority::Urgent,
+        _ => return Err(invalid("priority", value)),
+    })
+}
+
+fn parse_date_value(field: &str, value: &str) -> Result<DateValue, FilterParseError> {
+    Ok(match value.to_lowercase().as_str() {
+        "none" => DateValue::None,
+        "any" => DateValue::Any,
+        "today" => DateValue::Day(0),
+        "tomorrow" => DateValue::Day(1),
+        "yesterday" => DateValue::Day(-1),
+        "this-week" => DateValue::Week(0),
+        "next-week" => DateValue::Week(1),
+        "last-week" => DateValue::Week(-1),
+        "this-month" => DateValue::Month(0),
+        "next-month" => DateValue::Month(1),
+        "last-month" => DateValue::Month(-1),
+        date => DateValue::Date(
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid(field, value))?,
+        ),
+    })
+}
+
+/// Label names are compared without case and without the `@` or `#` they often start with.
+fn label_name(name: &str) -> String {
+    name.trim_start_matches(['@', '#']).to_lowercase()
+}
+
+impl FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            depth: 0,
+        };
+        if parser.peek().is_none() {
+            return Ok(Filter::All);
+        }
+
+        let filter = parser.or()?;
+        match parser.peek() {
+            Some(token) => Err(FilterParseError::UnexpectedToken(token.describe())),
+            None => Ok(filter),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+impl DateValue {
+    /// The days `start..end` the value stands for, relative to `today`.
+    fn range(self, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        let shift_days = |date: NaiveDate, days: i64| match days {
+            0.. => date.checked_add_days(Days::new(days.unsigned_abs())),
+            _ => date.checked_sub_days(Days::new(days.unsigned_abs())),
+        };
+        let shift_months = |date: NaiveDate, months: i32| match months {
+            0.. => date.checked_add_months(Months::new(months.unsigned_abs())),
+            _ => date.checked_sub_months(Months::new(months.unsigned_abs())),
+        };
+
+        let (start, end) = match self {
+            DateValue::None | DateValue::Any => return None,
+            DateValue::Day(days) => {
+                let start = shift_days(today, days)?;
+                (start, shift_days(start, 1)?)
+            }
+            DateValue::Week(weeks) => {
+                let monday = shift_days(today, -i64::from(today.weekday().num_days_from_monday()))?;
+                let start = shift_days(monday, i64::from(weeks) * 7)?;
+                (start, shift_days(start, 7)?)
+            }
+            DateValue::Month(months) => {
+                let first = today.with_day(1)?;
+                let start = shift_months(first, months)?;
+                (start, shift_months(start, 1)?)
+            }
+            DateValue::Date(date) => (date, shift_days(date, 1)?),
+        };
+        Some((start, end))
+    }
+
+    fn matches(self, date: Option<NaiveDate>, comparison: Comparison, today: NaiveDate) -> bool {
+        let is_equal = match (self, date) {
+            (DateValue::None, date) => date.is_none(),
+            (DateValue::Any, date) => date.is_some(),
+            (_, None) => return comparison == Comparison::NotEqual,
+            (value, Some(date)) => {
+                let Some((start, end)) = value.range(today) else {
+                    return false;
+                };
+                match comparison {
+                    Comparison::Equal | Comparison::NotEqual => start <= date && date < end,
+                    Comparison::Less => return date < start,
+                    Comparison::LessOrEqual => return date < end,
+                    Comparison::Greater => return date >= end,
+                    Comparison::GreaterOrEqual => return date >= start,
+                }
+            }
+        };
+        is_equal == (comparison == Comparison::Equal)
+    }
+}
+
+fn local_date(date: &DateOrDateTime, now: &DateTime<FixedOffset>) -> NaiveDate {
+    match date {
+        DateOrDateTime::Date(date) => *date,
+        DateOrDateTime::DateTime(datetime) => datetime.with_timezone(&now.timezone()).date_naive(),
+    }
+}
+
+fn date_field(task: &Task, field: DateField) -> Option<DateOrDateTime> {
+    match field {
+        DateField::Scheduled => task.scheduled.clone(),
+        DateField::Deadline => task.deadline.clone(),
+        DateField::Created => task.created_at.map(DateOrDateTime::DateTime),
+        DateField::Updated => task.updated_at.map(DateOrDateTime::DateTime),
+        DateField::Completed => task.completed_at.map(DateOrDateTime::DateTime),
+    }
+}
+
+impl Filter {
+    /// Whether `task`, which is in `project`, matches the filter at `now`.
+    pub fn matches(
+        &self,
+        db: &Database,
+        project: &Project,
+        task: &Task,
+        now: &DateTime<FixedOffset>,
+    ) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::And(a, b) => {
+                a.matches(db, project, task, now) && b.matches(db, project, task, now)
+            }
+            Filter::Or(a, b) => {
+                a.matches(db, project, task, now) || b.matches(db, project, task, now)
+            }
+            Filter::Not(filter) => !filter.matches(db, project, task, now),
+            Filter::Done => task.done,
+            Filter::Overdue => {
+                !task.done
+                    && match &task.deadline {
+                        Some(DateOrDateTime::Date(date)) => *date < now.date_naive(),
+                        Some(DateOrDateTime::DateTime(datetime)) => *datetime < *now,
+                        None => false,
+                    }
+            }
+            Filter::Recurring => task.recurrence.is_some(),
+            Filter::Subtask => task.parent_task_id.is_some(),
+            Filter::Priority(comparison, priority) => comparison.holds(task.priority.cmp(priority)),
+            Filter::Date(field, comparison, value) => {
+                let date = date_field(task, *field).map(|date| local_date(&date, now));
+                value.matches(date, *comparison, now.date_naive())
+            }
+            Filter::In { path, nested } => {
+                let project_path = db.project_path(&project.project_id);
+                let within = project_path.len() >= path.len()
+                    && path
+                        .iter()
+                        .zip(&project_path)
+                        .all(|(name, project_name)| project_name.to_lowercase() == *name);
+                within && (*nested || project_path.len() == path.len())
+            }
+            Filter::Label(name) => task.labels.iter().any(|label_id| {
+                db.labels
+                    .get(label_id)
+                    .is_some_and(|label| label_name(&label.name) == *name)
+            }),
+            Filter::Text(text) => {
+                task.summary.to_lowercase().contains(text)
+                    || task
+                        .description
+                        .as_ref()
+                        .is_some_and(|description| description.to_lowercase().contains(text))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortKey {
+    Priority,
+    Scheduled,
+    Deadline,
+    Created,
+    Updated,
+    Completed,
+    Summary,
+}
+
+/// One level of sorting, like `priority` or `-priority` for the highest first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    /// Parses a comma separated list of sort keys, like `-priority,deadline`.
+    pub fn parse_list(s: &str) -> Result<Vec<Sort>, FilterParseError> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|sort| !sort.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    fn compare(&self, a: &Task, b: &Task, now: &DateTime<FixedOffset>) -> Ordering {
+        let date = |task: &Task| {
+            let field = match self.key {
+                SortKey::Scheduled => DateField::Scheduled,
+                SortKey::Deadline => DateField::Deadline,
+                SortKey::Created => DateField::Created,
+                SortKey::Updated => DateField::Updated,
+                SortKey::Completed => DateField::Completed,
+                SortKey::Priority | SortKey::Summary => return None,
+            };
+            date_field(task, field).map(|date| sort_instant(&date, now))
+        };
+
+        let ordering = match self.key {
+            SortKey::Priority => a.priority.cmp(&b.priority),
+            SortKey::Summary => a.summary.to_lowercase().cmp(&b.summary.to_lowercase()),
+            _ => match (date(a), date(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                // Tasks without the date go last either way
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Dates without a time sort at the start of their day.
+fn sort_instant(date: &DateOrDateTime, now: &DateTime<FixedOffset>) -> DateTime<Utc> {
+    match date {
+        DateOrDateTime::Date(date) => date
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(now.timezone())
+            .single()
+            .map_or_else(
+                || date.and_time(NaiveTime::MIN).and_utc(),
+                |dt| dt.with_timezone(&Utc),
+            ),
+        DateOrDateTime::DateTime(datetime) => *datetime,
+    }
+}
+
+impl FromStr for Sort {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, descending) = match s.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let key = match name.to_lowercase().as_str() {
+            "priority" => SortKey::Priority,
+            "scheduled" => SortKey::Scheduled,
+            "deadline" => SortKey::Deadline,
+            "created" => SortKey::Created,
+            "updated" => SortKey::Updated,
+            "completed" => SortKey::Completed,
+            "summary" => SortKey::Summary,
+            _ => return Err(invalid("sort", s)),
+        };
+        Ok(Sort { key, descending })
+    }
+}
+
+impl Database {
+    /// The tasks matching `filter` at `now`, sorted by `sort` and otherwise in the order of the
+    /// sidebar and the manual order within each project.
+    pub fn query(&self, filter: &Filter, sort: &[Sort], now: &DateTime<FixedOffset>) -> Vec<&Task> {
+        let mut projects = vec![];
+        let mut nodes: Vec<_> = self.project_tree().into_iter().rev().collect();
+        while let Some(node) = nodes.pop() {
+            projects.push(node.project);
+            nodes.extend(node.children.into_iter().rev());
+        }
+
+        let mut tasks: Vec<&Task> = projects
+            .into_iter()
+            .flat_map(|project| {
+                project
+                    .ordered_tasks()
+                    .into_iter()
+                    .filter(move |task| filter.matches(self, project, task, now))
+            })
+            .collect();
+
+        // Stable, so tasks that sort equal keep their manual order
+        tasks.sort_by(|a, b| {
+            sort.iter()
+                .map(|sort| sort.compare(a, b, now))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+
+    use super::{Comparison, DateValue, Filter, FilterParseError, Sort};
+    use crate::tests::{project, task};
+    use crate::{Database, DateOrDateTime, Label, Operation, Priority, Project, Task};
+
+    fn date(day: u32) -> Option<DateOrDateTime> {
+        Some(DateOrDateTime::Date(
+            NaiveDate::from_ymd_opt(2024, 10, day).unwrap(),
+        ))
+    }
+
+    /// Wednesday 2024-10-16, 01:00 in Amsterdam, which is still the 15th in UTC.
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 10, 16, 1, 0, 0)
+            .unwrap()
+    }
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("clients", "Clients")
+                },
+                at: None,
+            },
+            Operation::CreateLabel {
+                label: Label {
+                    label_id: "phone".into(),
+                    name: "@phone".into(),
+                    color: None,
+                },
+            },
+            Operation::CreateTask {
+                task: Task {
+                    deadline: date(14),
+                    priority: Priority::Urgent,
+                    ..task("report", "Write report")
+                },
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    scheduled: date(18),
+                    labels: BTreeSet::from(["phone".into()]),
+                    ..task("call", "Call Acme")
+                },
+                project_id: Some("clients".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    deadline: date(10),
+                    priority: Priority::High,
+                    done: true,
+                    description: Some("Semi-skimmed".into()),
+                    ..task("milk", "Buy milk")
+                },
+                project_id: None,
+                at: None,
+            },
+        ]);
+        state
+    }
+
+    fn query(state: &Database, filter: &str, sort: &str) -> Vec<String> {
+        state
+            .query(
+                &filter.parse().unwrap(),
+                &Sort::parse_list(sort).unwrap(),
+                &now(),
+            )
+            .iter()
+            .map(|task| task.task_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parses_expressions() {
+        let filter: Filter = "overdue & priority >= high | !done".parse().unwrap();
+        assert_eq!(
+            filter,
+            Filter::Or(
+                Box::new(Filter::And(
+                    Box::new(Filter::Overdue),
+                    Box::new(Filter::Priority(Comparison::GreaterOrEqual, Priority::High)),
+                )),
+                Box::new(Filter::Not(Box::new(Filter::Done))),
+            )
+        );
+        assert_eq!(
+            "in:\"Side projects\"/*".parse(),
+            Ok(Filter::In {
+                path: vec!["side projects".into()],
+                nested: true
+            })
+        );
+        assert_eq!("".parse(), Ok(Filter::All));
+
+        assert_eq!(
+            "(done".parse::<Filter>(),
+            Err(FilterParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "priority:highest".parse::<Filter>(),
+            Err(FilterParseError::InvalidValue {
+                field: "priority".into(),
+                value: "highest".into()
+            })
+        );
+        assert_eq!(
+            "deadline < none".parse::<Filter>(),
+            Err(FilterParseError::InvalidValue {
+                field: "deadline".into(),
+                value: "none".into()
+            })
+        );
+        assert_eq!(
+            "size:big".parse::<Filter>(),
+            Err(FilterParseError::UnknownField("size".into()))
+        );
+        assert_eq!(
+            "\"milk".parse::<Filter>(),
+            Err(FilterParseError::UnclosedQuote)
+        );
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth: usize| format!("{}done{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(nested(50).parse(), Ok(Filter::Done));
+
+        for filter in [
+            nested(5000),
+            format!("{}done", "!".repeat(5000)),
+            vec!["done"; 5000].join(" | "),
+            vec!["done"; 5000].join(" "),
+        ] {
+            assert_eq!(filter.parse::<Filter>(), Err(FilterParseError::TooDeep));
+        }
+    }
+
+    #[test]
+    fn filters_tasks() {
+        let state = filled();
+
+        assert_eq!(query(&state, "overdue & priority >= high", ""), ["report"]);
+        assert_eq!(query(&state, "in:work", ""), ["report"]);
+        assert_eq!(query(&state, "in:WORK/*", ""), ["report", "call"]);
+        assert_eq!(query(&state, "in:work/clients @phone", ""), ["call"]);
+        assert_eq!(query(&state, "scheduled:this-week", ""), ["call"]);
+        assert_eq!(query(&state, "deadline < today", ""), ["milk", "report"]);
+        assert_eq!(query(&state, "deadline:none | milk", ""), ["milk", "call"]);
+        assert_eq!(query(&state, "\"semi-skimmed\" done", ""), ["milk"]);
+        assert_eq!(query(&state, "!(done | label:phone)", ""), ["report"]);
+    }
+
+    #[test]
+    fn dates_are_relative_to_now() {
+        let today = now().date_naive();
+        let day = |day| NaiveDate::from_ymd_opt(2024, 10, day);
+
+        assert_eq!(DateValue::Day(0).range(today), day(16).zip(day(17)));
+        assert_eq!(DateValue::Week(-1).range(today), day(7).zip(day(14)));
+        assert_eq!(
+            DateValue::Month(-1).range(today),
+            NaiveDate::from_ymd_opt(2024, 9, 1).zip(day(1))
+        );
+    }
+
+    #[test]
+    fn sorts_tasks() {
+        let state = filled();
+
+        assert_eq!(query(&state, "", "-priority"), ["report", "milk", "call"]);
+        // Tasks without a deadline go last, also when sorting the latest first
+        assert_eq!(query(&state, "", "-deadline"), ["report", "milk", "call"]);
+        assert_eq!(query(&state, "", "summary"), ["milk", "call", "report"]);
+        assert!(Sort::parse_list("size").is_err());
+    }
+}
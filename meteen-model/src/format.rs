@@ -0,0 +1,212 @@
+//! The versioned format vaults are stored and sent in.
+//!
+//! An encoded vault starts with the bytes `MTVAULT` and the format version as a little endian
+//! `u32`, followed by the vault as JSON. JSON names its fields, so fields added with
+//! `#[serde(default)]` can be read from older vaults as they are. Changes old data has to be
+//! converted for, like a renamed or restructured field, add a migration to [`MIGRATIONS`] that
+//! rewrites the JSON of the previous version.
+//!
+//! Every change to the stored types bumps [`FORMAT_VERSION`], also when no migration is needed.
+//! A client reading a vault from a newer version refuses it instead of silently dropping the
+//! fields it doesn't know about and writing the result back.
+//!
+//! Vaults from before this format are plain bincode of the model as it was then. They are read
+//! with the frozen copies of those types in [`legacy`] and converted into the current ones.
+
+pub mod legacy;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::Database;
+
+pub const FORMAT_VERSION: u32 = 2;
+
+const MAGIC: &[u8] = b"MTVAULT";
+
+/// Rewrites the JSON of a database from one version to the next.
+type Migration = fn(&mut Value);
+
+/// `MIGRATIONS[i]` turns a database of version `i + 1` into one of version `i + 2`.
+const MIGRATIONS: &[Migration] = &[
+    // 2: deletions may come without a time, which older versions can't read, and completions
+    // carry the occurrence they complete
+    |_| {},
+];
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("The vault was written by a newer version of meteen (format {0})")]
+    NewerVersion(u32),
+
+    #[error("The vault can't be read: {0}")]
+    Unreadable(String),
+}
+
+/// Encodes a database, or something containing one, in the current version of the format.
+pub fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+    // Unwrap is safe because all maps in the model have string keys, which is all JSON needs
+    serde_json::to_writer(&mut bytes, value).unwrap();
+    bytes
+}
+
+/// Decodes a database encoded by [`encode`] in this or an older version, or a legacy vault.
+pub fn decode(bytes: &[u8]) -> Result<Database, FormatError> {
+    decode_with::<_, legacy::Database>(bytes, migrate)
+}
+
+/// Like [`decode`] for something containing a database, where `upgrade` migrates the JSON of
+/// the database inside from the given version, using [`migrate`]. Legacy vaults are read as
+/// `Legacy`, which has to be built from the types in [`legacy`].
+pub fn decode_with<T: DeserializeOwned, Legacy: DeserializeOwned + Into<T>>(
+    bytes: &[u8],
+    upgrade: impl FnOnce(&mut Value, u32),
+) -> Result<T, FormatError> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        return bincode::deserialize::<Legacy>(bytes)
+            .map(Into::into)
+            .map_err(|e| FormatError::Unreadable(e.to_string()));
+    };
+    let (version, json) = rest
+        .split_first_chunk::<4>()
+        .ok_or_else(|| FormatError::Unreadable("The version is missing".into()))?;
+    let version = u32::from_le_bytes(*version);
+    if version > FORMAT_VERSION {
+        return Err(FormatError::NewerVersion(version));
+    }
+
+    let mut value: Value =
+        serde_json::from_slice(json).map_err(|e| FormatError::Unreadable(e.to_string()))?;
+    upgrade(&mut value, version);
+    serde_json::from_value(value).map_err(|e| FormatError::Unreadable(e.to_string()))
+}
+
+/// Brings the JSON of a database from `version` up to [`FORMAT_VERSION`].
+pub fn migrate(database: &mut Value, version: u32) {
+    migrate_with(database, version, MIGRATIONS);
+}
+
+fn migrate_with(database: &mut Value, version: u32, migrations: &[Migration]) {
+    // JSON from before versions were recorded has the shape of version 1
+    let applied = usize::try_from(version.max(1) - 1).unwrap_or(usize::MAX);
+    for migration in migrations.iter().skip(applied) {
+        migration(database);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use std::collections::HashMap;
+
+    use chrono::NaiveDate;
+
+    use super::{decode, encode, legacy, migrate_with, FormatError, FORMAT_VERSION, MAGIC};
+    use crate::tests::{project, task};
+    use crate::{Database, DateOrDateTime, Operation, Priority, Task, INBOX_ID};
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.apply_operation(Operation::CreateTask {
+            task: task("report", "Write report"),
+            project_id: None,
+            at: None,
+        });
+        state
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = encode(&filled());
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(decode(&bytes).unwrap(), filled());
+    }
+
+    #[test]
+    fn reads_legacy_vaults() {
+        let inbox = legacy::Project {
+            name: "Inbox".into(),
+            project_id: INBOX_ID.into(),
+            parent_id: None,
+            tasks: vec![legacy::Task {
+                task_id: "report".into(),
+                summary: "Write report".into(),
+                done: false,
+                scheduled: Some(legacy::DateOrDateTime::Date(
+                    NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+                )),
+                deadline: None,
+                priority: legacy::Priority::High,
+            }],
+        };
+        let work = legacy::Project {
+            name: "Work".into(),
+            project_id: "work".into(),
+            parent_id: None,
+            tasks: vec![],
+        };
+        let bytes = bincode::serialize(&legacy::Database {
+            projects: HashMap::from([(INBOX_ID.into(), inbox), ("work".into(), work)]),
+        })
+        .unwrap();
+
+        let mut expected = Database::new();
+        expected
+            .projects
+            .get_mut(INBOX_ID)
+            .unwrap()
+            .tasks
+            .push(Task {
+                scheduled: Some(DateOrDateTime::Date(
+                    NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+                )),
+                priority: Priority::High,
+                ..task("report", "Write report")
+            });
+        expected
+            .projects
+            .insert("work".into(), project("work", "Work"));
+
+        let state = decode(&bytes).unwrap();
+        assert_eq!(state, expected);
+        // The index is built for the converted database too
+        assert!(state.get_task("report").is_some());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((FORMAT_VERSION + 1).to_le_bytes());
+        bytes.extend(br#"{"projects": {}, "fields_from_the_future": true}"#);
+
+        assert!(matches!(
+            decode(&bytes),
+            Err(FormatError::NewerVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn migrations_run_from_the_stored_version() {
+        fn rename(value: &mut Value) {
+            value["name"] = value["old_name"].take();
+        }
+        fn count(value: &mut Value) {
+            value["migrated"] = json!(value["migrated"].as_u64().unwrap_or(0) + 1);
+        }
+        let migrations: &[fn(&mut Value)] = &[rename, count];
+
+        let mut value = json!({ "old_name": "Work" });
+        migrate_with(&mut value, 1, migrations);
+        assert_eq!(value["name"], "Work");
+        assert_eq!(value["migrated"], 1);
+
+        let mut value = json!({ "name": "Work" });
+        migrate_with(&mut value, 2, migrations);
+        assert_eq!(value["name"], "Work");
+        assert_eq!(value["migrated"], 1);
+    }
+}
@@ -0,0 +1,223 @@
+//! The model as it was before vaults had a format, only used to read vaults from back then.
+//!
+//! Those vaults are plain bincode, which has no field names and numbers enum variants by their
+//! position, so they can only be read with exactly the types that wrote them. These are frozen
+//! copies of those types and must never change, not even the order of variants. They are
+//! converted into the current types once read.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::DeleteMode;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Project {
+    pub name: String,
+    pub project_id: String,
+    pub parent_id: Option<String>,
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Task {
+    pub task_id: String,
+    pub summary: String,
+    pub done: bool,
+    pub scheduled: Option<DateOrDateTime>,
+    pub deadline: Option<DateOrDateTime>,
+    pub priority: Priority,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum Priority {
+    Low,
+    Standard,
+    High,
+    Urgent,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Operation {
+    CreateTask {
+        task: Task,
+        project_id: Option<String>,
+    },
+    DeleteTask {
+        task_id: String,
+    },
+    UpdateTaskSummary {
+        task_id: String,
+        summary: String,
+    },
+    UpdateTaskDone {
+        task_id: String,
+        done: bool,
+    },
+    UpdateTaskScheduled {
+        task_id: String,
+        scheduled: Option<DateOrDateTime>,
+    },
+    UpdateTaskDeadline {
+        task_id: String,
+        deadline: Option<DateOrDateTime>,
+    },
+    MoveTask {
+        task_id: String,
+        project_id_to: String,
+    },
+    CreateProject {
+        project: Project,
+    },
+    DeleteProject {
+        project_id: String,
+    },
+    MoveProject {
+        project_id: String,
+        parent_id_to: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum DateOrDateTime {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Database {
+    pub projects: HashMap<String, Project>,
+}
+
+impl From<Project> for crate::Project {
+    fn from(project: Project) -> Self {
+        Self {
+            name: project.name,
+            project_id: project.project_id,
+            parent_id: project.parent_id,
+            tasks: project.tasks.into_iter().map(Into::into).collect(),
+            color: None,
+            icon: None,
+            description: None,
+            sort_key: String::new(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl From<Task> for crate::Task {
+    fn from(task: Task) -> Self {
+        Self {
+            task_id: task.task_id,
+            summary: task.summary,
+            done: task.done,
+            scheduled: task.scheduled.map(Into::into),
+            deadline: task.deadline.map(Into::into),
+            priority: task.priority.into(),
+            recurrence: None,
+            parent_task_id: None,
+            sort_key: String::new(),
+            labels: Default::default(),
+            description: None,
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+        }
+    }
+}
+
+impl From<Priority> for crate::Priority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Low => Self::Low,
+            Priority::Standard => Self::Standard,
+            Priority::High => Self::High,
+            Priority::Urgent => Self::Urgent,
+        }
+    }
+}
+
+impl From<DateOrDateTime> for crate::DateOrDateTime {
+    fn from(date: DateOrDateTime) -> Self {
+        match date {
+            DateOrDateTime::Date(date) => Self::Date(date),
+            DateOrDateTime::DateTime(date_time) => Self::DateTime(date_time),
+        }
+    }
+}
+
+/// Operations from back then carry no time and are read like those of other older clients.
+impl From<Operation> for crate::Operation {
+    fn from(operation: Operation) -> Self {
+        match operation {
+            Operation::CreateTask { task, project_id } => Self::CreateTask {
+                task: task.into(),
+                project_id,
+                at: None,
+            },
+            Operation::DeleteTask { task_id } => Self::DeleteTask { task_id, at: None },
+            Operation::UpdateTaskSummary { task_id, summary } => Self::UpdateTaskSummary {
+                task_id,
+                summary,
+                at: None,
+            },
+            Operation::UpdateTaskDone { task_id, done } => Self::UpdateTaskDone {
+                task_id,
+                done,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
+            },
+            Operation::UpdateTaskScheduled { task_id, scheduled } => Self::UpdateTaskScheduled {
+                task_id,
+                scheduled: scheduled.map(Into::into),
+                at: None,
+            },
+            Operation::UpdateTaskDeadline { task_id, deadline } => Self::UpdateTaskDeadline {
+                task_id,
+                deadline: deadline.map(Into::into),
+                at: None,
+            },
+            Operation::MoveTask {
+                task_id,
+                project_id_to,
+            } => Self::MoveTask {
+                task_id,
+                project_id_to,
+                at: None,
+            },
+            Operation::CreateProject { project } => Self::CreateProject {
+                project: project.into(),
+                at: None,
+            },
+            Operation::DeleteProject { project_id } => Self::DeleteProject {
+                project_id,
+                mode: DeleteMode::default(),
+                at: None,
+            },
+            Operation::MoveProject {
+                project_id,
+                parent_id_to,
+            } => Self::MoveProject {
+                project_id,
+                parent_id_to,
+                at: None,
+            },
+        }
+    }
+}
+
+impl From<Database> for crate::Database {
+    fn from(database: Database) -> Self {
+        let mut converted = Self::new();
+        converted.projects = database
+            .projects
+            .into_iter()
+            .map(|(project_id, project)| (project_id, project.into()))
+            .collect();
+        converted.reindex();
+        converted
+    }
+}
@@ -0,0 +1,366 @@
+//! Checking a database for broken invariants and repairing them.
+//!
+//! Operations keep every invariant, but vaults written by older versions or damaged on disk may
+//! not. Repairs only depend on the contents of the database, never on the order a map happens to
+//! be iterated in, so repairing copies of a vault on different machines gives the same result.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{Database, InvariantError, Project, INBOX_ID};
+
+impl Database {
+    /// Every invariant the database breaks, see [`Database::check_invariants`]. Projects are
+    /// visited in the order of their ids, so the same database always gives the same list.
+    pub fn check(&self) -> Vec<InvariantError> {
+        let mut errors = vec![];
+
+        match self.projects.get(INBOX_ID) {
+            None => errors.push(InvariantError::MissingInbox),
+            Some(inbox) => {
+                if let Some(ref parent_id) = inbox.parent_id {
+                    errors.push(InvariantError::NestedInbox(parent_id.clone()));
+                }
+            }
+        }
+
+        let mut seen_tasks = HashSet::new();
+        for (key, project) in self.sorted_projects() {
+            if *key != project.project_id {
+                errors.push(InvariantError::MismatchedKey {
+                    key: key.clone(),
+                    project_id: project.project_id.clone(),
+                });
+            }
+
+            if let Some(ref parent_id) = project.parent_id {
+                if !self.projects.contains_key(parent_id) {
+                    errors.push(InvariantError::DanglingParent {
+                        project_id: project.project_id.clone(),
+                        parent_id: parent_id.clone(),
+                    });
+                }
+            }
+
+            // A chain of parents longer than the number of projects must go around in circles
+            let mut current = project;
+            for _ in 0..=self.projects.len() {
+                match current
+                    .parent_id
+                    .as_ref()
+                    .and_then(|id| self.projects.get(id))
+                {
+                    Some(parent) => current = parent,
+                    None => break,
+                }
+            }
+            if current
+                .parent_id
+                .as_ref()
+                .is_some_and(|id| self.projects.contains_key(id))
+            {
+                errors.push(InvariantError::Cycle(project.project_id.clone()));
+            }
+
+            let parents: HashMap<&str, Option<&str>> = project
+                .tasks
+                .iter()
+                .map(|task| (task.task_id.as_str(), task.parent_task_id.as_deref()))
+                .collect();
+            for task in &project.tasks {
+                if !seen_tasks.insert(task.task_id.as_str()) {
+                    errors.push(InvariantError::DuplicateTask(task.task_id.clone()));
+                }
+
+                if let Some(ref parent_task_id) = task.parent_task_id {
+                    if !parents.contains_key(parent_task_id.as_str()) {
+                        errors.push(InvariantError::DanglingParentTask {
+                            task_id: task.task_id.clone(),
+                            parent_task_id: parent_task_id.clone(),
+                        });
+                    }
+                }
+
+                let mut current = task.parent_task_id.as_deref();
+                for _ in 0..=parents.len() {
+                    match current.and_then(|id| parents.get(id)) {
+                        Some(parent) => current = *parent,
+                        None => break,
+                    }
+                }
+                if current.is_some_and(|id| parents.contains_key(id)) {
+                    errors.push(InvariantError::TaskCycle(task.task_id.clone()));
+                }
+
+                for label_id in &task.labels {
+                    if !self.labels.contains_key(label_id) {
+                        errors.push(InvariantError::UnknownLabel {
+                            task_id: task.task_id.clone(),
+                            label_id: label_id.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = self.check_index() {
+            errors.push(e);
+        }
+
+        errors
+    }
+
+    /// Repairs every broken invariant and returns what was wrong. Nothing is deleted: a missing
+    /// inbox is recreated, projects and tasks with a missing parent or caught in a cycle move to
+    /// the top level, and duplicate tasks get a new id.
+    pub fn repair(&mut self) -> Vec<InvariantError> {
+        let mut repaired = vec![];
+
+        // Some repairs only become possible after others, like a task whose parent turns out to
+        // be missing once a duplicate of it got a new id
+        for _ in 0..8 {
+            let errors = self.check();
+            if errors.is_empty() {
+                break;
+            }
+            for error in &errors {
+                self.fix(error);
+            }
+            repaired.extend(errors);
+        }
+
+        if let Err(e) = self.check_invariants() {
+            tracing::error!("Database is still inconsistent after repairing: {}", e);
+        }
+        repaired
+    }
+
+    fn sorted_projects(&self) -> Vec<(&String, &Project)> {
+        let mut projects: Vec<(&String, &Project)> = self.projects.iter().collect();
+        projects.sort_by_key(|(key, _)| *key);
+        projects
+    }
+
+    fn fix(&mut self, error: &InvariantError) {
+        match error {
+            InvariantError::MissingInbox => {
+                // Unwrap is safe because a new database has an inbox
+                let inbox = Database::new().projects.remove(INBOX_ID).unwrap();
+                self.projects.insert(INBOX_ID.into(), inbox);
+            }
+            InvariantError::NestedInbox(_) => {
+                if let Some(inbox) = self.projects.get_mut(INBOX_ID) {
+                    inbox.parent_id = None;
+                }
+            }
+            InvariantError::MismatchedKey { key, .. } => {
+                // Keys are unique, ids might not be
+                if let Some(project) = self.projects.get_mut(key) {
+                    project.project_id = key.clone();
+                }
+                self.reindex();
+            }
+            InvariantError::DanglingParent { project_id, .. } => {
+                let dangling = self
+                    .projects
+                    .get(project_id)
+                    .and_then(|project| project.parent_id.as_ref())
+                    .is_some_and(|parent_id| !self.projects.contains_key(parent_id));
+                if dangling {
+                    // Unwrap is safe because we looked the project up above
+                    self.projects.get_mut(project_id).unwrap().parent_id = None;
+                }
+            }
+            InvariantError::Cycle(project_id) => {
+                // Follow the parents until one comes round again, everything from there on is
+                // the cycle. It is cut above the project with the lowest id.
+                let mut chain: Vec<&str> = vec![];
+                let mut current = self.projects.get(project_id);
+                while let Some(project) = current {
+                    if let Some(start) = chain.iter().position(|id| *id == project.project_id) {
+                        // Unwraps are safe because the cycle holds at least one existing project
+                        let lowest = chain[start..].iter().min().unwrap().to_string();
+                        self.projects.get_mut(&lowest).unwrap().parent_id = None;
+                        return;
+                    }
+                    chain.push(&project.project_id);
+                    current = project
+                        .parent_id
+                        .as_ref()
+                        .and_then(|id| self.projects.get(id));
+                }
+            }
+            InvariantError::DuplicateTask(task_id) => self.rename_duplicates(task_id),
+            InvariantError::DanglingParentTask { task_id, .. } => {
+                for project in self.projects.values_mut() {
+                    let ids: HashSet<String> =
+                        project.tasks.iter().map(|t| t.task_id.clone()).collect();
+                    for task in project.tasks.iter_mut() {
+                        let dangling = task
+                            .parent_task_id
+                            .as_ref()
+                            .is_some_and(|id| !ids.contains(id));
+                        if task.task_id == *task_id && dangling {
+                            task.parent_task_id = None;
+                        }
+                    }
+                }
+            }
+            InvariantError::TaskCycle(task_id) => {
+                for project in self.projects.values_mut() {
+                    let parents: HashMap<String, Option<String>> = project
+                        .tasks
+                        .iter()
+                        .map(|task| (task.task_id.clone(), task.parent_task_id.clone()))
+                        .collect();
+                    let mut chain: Vec<&str> = vec![];
+                    let mut current = parents.get_key_value(task_id.as_str());
+                    while let Some((id, parent)) = current {
+                        if let Some(start) = chain.iter().position(|seen| seen == id) {
+                            // Unwrap is safe because the cycle holds at least one task
+                            let lowest = *chain[start..].iter().min().unwrap();
+                            for task in project.tasks.iter_mut() {
+                                if task.task_id == lowest {
+                                    task.parent_task_id = None;
+                                }
+                            }
+                            break;
+                        }
+                        chain.push(id);
+                        current = parent
+                            .as_ref()
+                            .and_then(|parent| parents.get_key_value(parent.as_str()));
+                    }
+                }
+            }
+            InvariantError::UnknownLabel { label_id, .. } => {
+                if !self.labels.contains_key(label_id) {
+                    for task in self.all_editable_tasks_mut() {
+                        task.labels.remove(label_id);
+                    }
+                }
+            }
+            InvariantError::StaleIndex(_) => self.reindex(),
+        }
+    }
+
+    /// Keeps the first task with this id, in the order of the project ids, and gives the others
+    /// the id with `-2`, `-3` and so on appended.
+    fn rename_duplicates(&mut self, task_id: &str) {
+        let mut keys: Vec<String> = self.projects.keys().cloned().collect();
+        keys.sort();
+
+        let mut taken: HashSet<String> = self
+            .all_editable_tasks()
+            .into_iter()
+            .map(|task| task.task_id.clone())
+            .collect();
+        let mut first = true;
+        let mut suffix = 2;
+
+        for key in keys {
+            // Unwrap is safe because the keys were collected from the projects
+            let project = self.projects.get_mut(&key).unwrap();
+            for task in project
+                .tasks
+                .iter_mut()
+                .filter(|task| task.task_id == task_id)
+            {
+                if first {
+                    first = false;
+                    continue;
+                }
+                while taken.contains(&format!("{}-{}", task_id, suffix)) {
+                    suffix += 1;
+                }
+                task.task_id = format!("{}-{}", task_id, suffix);
+                taken.insert(task.task_id.clone());
+            }
+        }
+        self.reindex();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::tests::{project, task};
+    use crate::{Database, InvariantError, Project, Task, INBOX_ID};
+
+    /// A vault with about everything that can go wrong.
+    fn broken() -> Database {
+        let mut state = Database::new();
+        state.projects.remove(INBOX_ID);
+        for (id, parent_id) in [("a", "b"), ("b", "a"), ("c", "missing")] {
+            state.projects.insert(
+                id.into(),
+                Project {
+                    parent_id: Some(parent_id.into()),
+                    ..project(id, id)
+                },
+            );
+        }
+        state.projects.get_mut("a").unwrap().tasks = vec![
+            task("t", "First"),
+            Task {
+                parent_task_id: Some("gone".into()),
+                labels: BTreeSet::from(["unknown".into()]),
+                ..task("s", "Orphan")
+            },
+        ];
+        state.projects.get_mut("c").unwrap().tasks = vec![
+            task("t", "Second"),
+            Task {
+                parent_task_id: Some("u".into()),
+                ..task("u", "Own parent")
+            },
+        ];
+        state.reindex();
+        state
+    }
+
+    #[test]
+    fn check_reports_everything() {
+        let errors = broken().check();
+
+        assert_eq!(errors[0], InvariantError::MissingInbox);
+        assert!(errors.contains(&InvariantError::Cycle("a".into())));
+        assert!(errors.contains(&InvariantError::DanglingParent {
+            project_id: "c".into(),
+            parent_id: "missing".into()
+        }));
+        assert!(errors.contains(&InvariantError::DuplicateTask("t".into())));
+        assert!(errors.contains(&InvariantError::DanglingParentTask {
+            task_id: "s".into(),
+            parent_task_id: "gone".into()
+        }));
+        assert!(errors.contains(&InvariantError::TaskCycle("u".into())));
+        assert!(errors.contains(&InvariantError::UnknownLabel {
+            task_id: "s".into(),
+            label_id: "unknown".into()
+        }));
+    }
+
+    #[test]
+    fn repair_is_deterministic() {
+        let mut state = broken();
+        assert!(!state.repair().is_empty());
+        assert_eq!(state.check(), []);
+
+        assert_eq!(state.projects["a"].parent_id, None);
+        assert_eq!(state.projects["b"].parent_id.as_deref(), Some("a"));
+        assert_eq!(state.projects["c"].parent_id, None);
+        assert_eq!(state.get_task("t").unwrap().summary, "First");
+        assert_eq!(state.get_task("t-2").unwrap().summary, "Second");
+        assert!(state.get_task("s").unwrap().labels.is_empty());
+        assert_eq!(state.get_task("u").unwrap().parent_task_id, None);
+
+        // A copy with its maps in another order ends up the same
+        let json = serde_json::to_string(&broken()).unwrap();
+        let mut copy: Database = serde_json::from_str(&json).unwrap();
+        copy.repair();
+        assert_eq!(copy, state);
+
+        assert_eq!(state.repair(), []);
+    }
+}
@@ -0,0 +1,255 @@
+//! Queries over the tree formed by the `parent_id` of projects.
+//!
+//! Everything here stops at projects it has already seen, so a vault that somehow contains a
+//! cycle still can't send these into an infinite loop.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{Database, Project, INBOX_ID};
+
+/// A project with its subprojects, as shown in the sidebar.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct ProjectNode<'a> {
+    pub project: &'a Project,
+    pub children: Vec<ProjectNode<'a>>,
+}
+
+/// The order projects are shown in: the inbox first, then in their manual order and then by
+/// name. The id breaks ties so every client shows the same order.
+fn display_order(a: &&Project, b: &&Project) -> std::cmp::Ordering {
+    (a.project_id != INBOX_ID)
+        .cmp(&(b.project_id != INBOX_ID))
+        .then_with(|| a.sort_key.cmp(&b.sort_key))
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.project_id.cmp(&b.project_id))
+}
+
+impl Database {
+    /// The direct subprojects of a project, or the top-level projects for `None`.
+    pub fn children_of(&self, project_id: Option<&str>) -> Vec<&Project> {
+        let mut children: Vec<&Project> = self
+            .projects
+            .values()
+            .filter(|project| project.parent_id.as_deref() == project_id)
+            .collect();
+        children.sort_by(display_order);
+        children
+    }
+
+    /// All projects nested in a project however deep, parents before their children.
+    pub fn descendants_of(&self, project_id: &str) -> Vec<&Project> {
+        let Some(project) = self.projects.get(project_id) else {
+            return vec![];
+        };
+
+        let mut seen = HashSet::from([project.project_id.as_str()]);
+        let mut descendants = vec![];
+        self.collect_descendants(project_id, &mut seen, &mut descendants);
+        descendants
+    }
+
+    fn collect_descendants<'a>(
+        &'a self,
+        project_id: &str,
+        seen: &mut HashSet<&'a str>,
+        descendants: &mut Vec<&'a Project>,
+    ) {
+        for child in self.children_of(Some(project_id)) {
+            if seen.insert(&child.project_id) {
+                descendants.push(child);
+                self.collect_descendants(&child.project_id, seen, descendants);
+            }
+        }
+    }
+
+    /// The projects containing a project, starting with its parent and ending at the top level.
+    pub fn ancestors_of(&self, project_id: &str) -> Vec<&Project> {
+        let mut seen = HashSet::from([project_id]);
+        let mut ancestors = vec![];
+        let mut current = self.projects.get(project_id);
+
+        while let Some(parent_id) = current.and_then(|project| project.parent_id.as_deref()) {
+            if !seen.insert(parent_id) {
+                break;
+            }
+            current = self.projects.get(parent_id);
+            ancestors.extend(current);
+        }
+
+        ancestors
+    }
+
+    /// The names of a project and its ancestors, outermost first. Empty for unknown projects.
+    pub fn project_path(&self, project_id: &str) -> Vec<&str> {
+        let Some(project) = self.projects.get(project_id) else {
+            return vec![];
+        };
+
+        let mut path: Vec<&str> = self
+            .ancestors_of(project_id)
+            .into_iter()
+            .map(|ancestor| ancestor.name.as_str())
+            .collect();
+        path.reverse();
+        path.push(&project.name);
+        path
+    }
+
+    /// Whether `ancestor_id` is the project itself or one of the projects containing it.
+    pub fn is_within(&self, project_id: &str, ancestor_id: &str) -> bool {
+        project_id == ancestor_id
+            || self
+                .ancestors_of(project_id)
+                .iter()
+                .any(|ancestor| ancestor.project_id == ancestor_id)
+    }
+
+    /// All projects as a tree, each level in display order.
+    pub fn project_tree(&self) -> Vec<ProjectNode<'_>> {
+        let mut seen = HashSet::new();
+        self.subtree(None, &mut seen)
+    }
+
+    fn subtree<'a>(
+        &'a self,
+        project_id: Option<&str>,
+        seen: &mut HashSet<&'a str>,
+    ) -> Vec<ProjectNode<'a>> {
+        let mut nodes = vec![];
+        for project in self.children_of(project_id) {
+            if seen.insert(&project.project_id) {
+                nodes.push(ProjectNode {
+                    project,
+                    children: self.subtree(Some(&project.project_id), seen),
+                });
+            }
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::project;
+    use crate::{Database, Operation, Project, INBOX_ID};
+
+    fn nested(project_id: &str, name: &str, parent_id: &str) -> Operation {
+        Operation::CreateProject {
+            project: Project {
+                parent_id: Some(parent_id.into()),
+                ..project(project_id, name)
+            },
+            at: None,
+        }
+    }
+
+    fn ids(projects: Vec<&Project>) -> Vec<&str> {
+        projects.iter().map(|p| p.project_id.as_str()).collect()
+    }
+
+    fn hierarchy() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: project("home", "Home"),
+                at: None,
+            },
+            nested("meetings", "Meetings", "work"),
+            nested("clients", "Clients", "work"),
+            nested("acme", "Acme", "clients"),
+        ]);
+        state
+    }
+
+    #[test]
+    fn queries() {
+        let state = hierarchy();
+
+        // Projects are kept in the order they were created in
+        assert_eq!(ids(state.children_of(None)), [INBOX_ID, "work", "home"]);
+        assert_eq!(
+            ids(state.children_of(Some("work"))),
+            ["meetings", "clients"]
+        );
+        assert_eq!(
+            ids(state.descendants_of("work")),
+            ["meetings", "clients", "acme"]
+        );
+        assert_eq!(ids(state.ancestors_of("acme")), ["clients", "work"]);
+        assert_eq!(state.project_path("acme"), ["Work", "Clients", "Acme"]);
+        assert!(state.project_path("missing").is_empty());
+        assert!(state.is_within("acme", "work"));
+        assert!(!state.is_within("work", "acme"));
+
+        let tree = state.project_tree();
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree[1].project.project_id, "work");
+        assert_eq!(tree[1].children[1].children[0].project.project_id, "acme");
+    }
+
+    #[test]
+    fn moves_creating_cycles_are_refused() {
+        let mut state = hierarchy();
+        state.batch_operations(vec![
+            Operation::MoveProject {
+                project_id: "work".into(),
+                parent_id_to: Some("acme".into()),
+                at: None,
+            },
+            Operation::MoveProject {
+                project_id: "work".into(),
+                parent_id_to: Some("work".into()),
+                at: None,
+            },
+        ]);
+        assert_eq!(state, hierarchy());
+
+        // Moving within its own subtree is fine as long as no cycle appears
+        state.apply_operation(Operation::MoveProject {
+            project_id: "acme".into(),
+            parent_id_to: Some("work".into()),
+            at: None,
+        });
+        assert_eq!(ids(state.ancestors_of("acme")), ["work"]);
+    }
+
+    #[test]
+    fn concurrent_moves_never_form_a_cycle() {
+        let a = Operation::MoveProject {
+            project_id: "work".into(),
+            parent_id_to: Some("home".into()),
+            at: None,
+        };
+        let b = Operation::MoveProject {
+            project_id: "home".into(),
+            parent_id_to: Some("acme".into()),
+            at: None,
+        };
+
+        // Whichever move reaches the server second is refused
+        for ops in [vec![a.clone(), b.clone()], vec![b, a]] {
+            let mut state = hierarchy();
+            state.batch_operations(ops);
+            assert_eq!(state.check_invariants(), Ok(()));
+            assert_eq!(state.project_tree().len(), 2);
+        }
+    }
+
+    #[test]
+    fn queries_survive_cycles() {
+        let mut state = hierarchy();
+        // Only possible in vaults written before moves were checked
+        state.projects.get_mut("work").unwrap().parent_id = Some("acme".into());
+
+        assert_eq!(ids(state.ancestors_of("acme")), ["clients", "work"]);
+        assert_eq!(state.descendants_of("work").len(), 3);
+        assert_eq!(state.project_tree().len(), 2);
+        assert!(state.check_invariants().is_err());
+    }
+}
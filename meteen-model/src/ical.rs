@@ -0,0 +1,669 @@
+//! iCalendar (RFC 5545) export and import of a [`Database`], one `VTODO` per task.
+//!
+//! The project of a task is written as the first of its categories, as a path like
+//! "Work / Clients", and its labels as the other categories. Subtasks refer to their parent
+//! through `RELATED-TO`.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use thiserror::Error;
+
+use crate::{
+    ids,
+    import::{self, Import, ImportedTask, Importer},
+    Database, DateOrDateTime, Priority, Project, Recurrence, Task,
+};
+
+const PRODID: &str = "-//meteen//meteen//EN";
+
+/// Properties of a todo that only matter to the calendar it came from, these aren't reported as
+/// unmapped.
+const BOOKKEEPING: &[&str] = &["DTSTAMP", "SEQUENCE"];
+
+/// Renders every task in the database as a `VCALENDAR` containing one `VTODO` per task.
+///
+/// `now` is used for the mandatory `DTSTAMP` property. Projects and tasks are written in a
+/// stable order so exporting the same database twice gives the same output.
+pub fn export(db: &Database, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, &format!("PRODID:{}", PRODID));
+
+    let mut projects: Vec<&Project> = db.projects.values().collect();
+    projects.sort_by(|a, b| a.project_id.cmp(&b.project_id));
+
+    for project in projects {
+        // Nested projects are exported with their full path, "Work / Clients" rather than "Clients"
+        let path = db.project_path(&project.project_id).join(" / ");
+        for task in &project.tasks {
+            // Labels become extra categories next to the project
+            let mut categories = vec![escape_text(&path)];
+            categories.extend(
+                task.labels
+                    .iter()
+                    .filter_map(|label_id| db.labels.get(label_id))
+                    .map(|label| escape_text(&label.name)),
+            );
+            write_todo(&mut out, task, &categories.join(","), now);
+        }
+    }
+
+    write_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// `categories` is the already escaped, comma separated list of categories.
+fn write_todo(out: &mut String, task: &Task, categories: &str, now: DateTime<Utc>) {
+    write_line(out, "BEGIN:VTODO");
+    write_line(out, &format!("UID:{}", escape_text(&task.task_id)));
+    write_line(out, &format!("DTSTAMP:{}", format_datetime(&now)));
+    write_line(out, &format!("SUMMARY:{}", escape_text(&task.summary)));
+
+    if let Some(description) = &task.description {
+        write_line(out, &format!("DESCRIPTION:{}", escape_text(description)));
+    }
+    write_line(out, &format!("CATEGORIES:{}", categories));
+
+    if let Some(parent_task_id) = &task.parent_task_id {
+        write_line(
+            out,
+            &format!("RELATED-TO;RELTYPE=PARENT:{}", escape_text(parent_task_id)),
+        );
+    }
+
+    if let Some(scheduled) = &task.scheduled {
+        write_line(out, &format!("DTSTART{}", format_date_value(scheduled)));
+    }
+
+    if let Some(deadline) = &task.deadline {
+        write_line(out, &format!("DUE{}", format_date_value(deadline)));
+    }
+
+    // A rule needs DTSTART to start from, and repeating after completion has no RRULE equivalent
+    if let (Some(_), Some(Recurrence::Rule(rule))) = (&task.scheduled, &task.recurrence) {
+        write_line(out, &format!("RRULE:{}", rule));
+    }
+
+    write_line(
+        out,
+        &format!("PRIORITY:{}", priority_to_ical(&task.priority)),
+    );
+
+    if task.done {
+        write_line(out, "STATUS:COMPLETED");
+        if let Some(completed_at) = &task.completed_at {
+            write_line(out, &format!("COMPLETED:{}", format_datetime(completed_at)));
+        }
+    } else {
+        write_line(out, "STATUS:NEEDS-ACTION");
+    }
+
+    if let Some(created_at) = &task.created_at {
+        write_line(out, &format!("CREATED:{}", format_datetime(created_at)));
+    }
+    if let Some(updated_at) = &task.updated_at {
+        write_line(
+            out,
+            &format!("LAST-MODIFIED:{}", format_datetime(updated_at)),
+        );
+    }
+
+    write_line(out, "END:VTODO");
+}
+
+/// Maps our priorities onto the 1 (highest) to 9 (lowest) scale of RFC 5545.
+fn priority_to_ical(priority: &Priority) -> u8 {
+    match priority {
+        Priority::Urgent => 1,
+        Priority::High => 3,
+        Priority::Standard => 5,
+        Priority::Low => 9,
+    }
+}
+
+/// Formats the parameters and value of a date property, including the leading `;` or `:`.
+fn format_date_value(date: &DateOrDateTime) -> String {
+    match date {
+        DateOrDateTime::Date(date) => format!(";VALUE=DATE:{}", date.format("%Y%m%d")),
+        DateOrDateTime::DateTime(datetime) => format!(":{}", format_datetime(datetime)),
+    }
+}
+
+fn format_datetime(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folding it at 75 octets as required by RFC 5545.
+fn write_line(out: &mut String, line: &str) {
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            line_len = 1;
+        }
+        out.push(c);
+        line_len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum IcalParseError {
+    #[error("Line {0} is not a valid content line")]
+    InvalidLine(usize),
+
+    #[error("The calendar ends inside a {0}")]
+    Unterminated(String),
+
+    #[error("{value} is not a valid value for {property}")]
+    InvalidValue { property: String, value: String },
+}
+
+/// A content line, with its parameter names uppercased.
+#[derive(Debug)]
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn invalid(&self) -> IcalParseError {
+        IcalParseError::InvalidValue {
+            property: self.name.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+/// What importing an iCalendar document adds to `db`: the tasks, together with the projects and
+/// labels they need that don't exist yet.
+///
+/// Tasks keep their `UID` as id, so importing the same file twice only creates them once. The
+/// first category picks the project, by its path, and the other categories are labels, matched
+/// by name. Tasks without categories go to the inbox. Times without a timezone are taken as
+/// UTC, and so are times with a `TZID`, which are reported as unmapped together with the
+/// properties, rules and components like alarms that are left out. `now` is when new projects
+/// are created.
+pub fn import(db: &Database, ics: &str, now: DateTime<Utc>) -> Result<Import, IcalParseError> {
+    let mut importer = Importer::new(db, now);
+    let mut todos: Vec<Vec<Property>> = vec![];
+    let mut components: Vec<String> = vec![];
+
+    for (number, line) in unfold(ics) {
+        let property = parse_line(&line).ok_or(IcalParseError::InvalidLine(number))?;
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.to_ascii_uppercase();
+                match component.as_str() {
+                    "VTODO" => todos.push(vec![]),
+                    // Timezones only matter to the times using them, which are reported
+                    "VCALENDAR" | "VTIMEZONE" => {}
+                    _ if components.iter().any(|outer| outer == "VTIMEZONE") => {}
+                    _ => importer.unmapped(component.clone()),
+                }
+                components.push(component);
+            }
+            "END" => {
+                let ended = components.pop();
+                if ended.as_deref() != Some(&property.value.to_ascii_uppercase()) {
+                    return Err(IcalParseError::InvalidLine(number));
+                }
+            }
+            // Properties of alarms and other components nested in the todo were reported above
+            _ if components.last().map(String::as_str) == Some("VTODO") => {
+                // Unwrap is safe because a todo was started when VTODO was entered
+                todos.last_mut().unwrap().push(property);
+            }
+            _ => {}
+        }
+    }
+    if let Some(component) = components.pop() {
+        return Err(IcalParseError::Unterminated(component));
+    }
+
+    let mut tasks = vec![];
+    for todo in &todos {
+        tasks.push(read_todo(&mut importer, todo)?);
+    }
+    importer.create_tasks(tasks);
+    Ok(importer.finish())
+}
+
+/// Joins folded lines, numbering each unfolded line by the line it started on.
+fn unfold(ics: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (index, line) in ics.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, previous))) => previous.push_str(rest),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push((index + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+fn parse_line(line: &str) -> Option<Property> {
+    let name_end = line.find([';', ':'])?;
+    let name = line[..name_end].trim().to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params = vec![];
+    let mut rest = &line[name_end..];
+    while let Some(param) = rest.strip_prefix(';') {
+        let (param_name, after_name) = param.split_once('=')?;
+        // Quoted parameter values may contain the separators
+        let (value, after_value) = match after_name.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                (value, after)
+            }
+            None => {
+                let end = after_name.find([';', ':'])?;
+                after_name.split_at(end)
+            }
+        };
+        params.push((param_name.to_ascii_uppercase(), value.to_string()));
+        rest = after_value;
+    }
+
+    Some(Property {
+        name,
+        params,
+        value: rest.strip_prefix(':')?.to_string(),
+    })
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Splits a list of values on the commas that aren't escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                // Unwrap is safe because the list starts with an item
+                let item = items.last_mut().unwrap();
+                item.push(c);
+                item.extend(chars.next());
+            }
+            ',' => items.push(String::new()),
+            // Unwrap is safe because the list starts with an item
+            c => items.last_mut().unwrap().push(c),
+        }
+    }
+    items
+        .iter()
+        .map(|item| unescape_text(item.trim()))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn parse_datetime(property: &Property) -> Result<DateTime<Utc>, IcalParseError> {
+    let value = property.value.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map(|datetime| datetime.and_utc())
+        .map_err(|_| property.invalid())
+}
+
+fn parse_date_value(property: &Property) -> Result<DateOrDateTime, IcalParseError> {
+    if property.param("VALUE") == Some("DATE") || property.value.len() == 8 {
+        return NaiveDate::parse_from_str(&property.value, "%Y%m%d")
+            .map(DateOrDateTime::Date)
+            .map_err(|_| property.invalid());
+    }
+    parse_datetime(property).map(DateOrDateTime::DateTime)
+}
+
+/// The inverse of [`priority_to_ical`], where 0 means no priority.
+fn priority_from_ical(priority: u8) -> Priority {
+    match priority {
+        1..=2 => Priority::Urgent,
+        3..=4 => Priority::High,
+        6..=9 => Priority::Low,
+        _ => Priority::Standard,
+    }
+}
+
+/// Reads the properties of a todo into a task, with the project path from its categories.
+fn read_todo(importer: &mut Importer, todo: &[Property]) -> Result<ImportedTask, IcalParseError> {
+    let mut task = import::new_task(String::new());
+    let mut categories = vec![];
+
+    for property in todo {
+        // Converting to UTC needs the rules of the timezone, which the time is read without
+        if let Some(tzid) = property.param("TZID") {
+            importer.unmapped(format!("{}: {} {}", property.name, property.value, tzid));
+        }
+
+        match property.name.as_str() {
+            "UID" => task.task_id = unescape_text(&property.value),
+            "SUMMARY" => task.summary = unescape_text(&property.value),
+            "DESCRIPTION" => task.description = Some(unescape_text(&property.value)),
+            "CATEGORIES" => categories.extend(split_list(&property.value)),
+            // Without a type the relation is to the parent
+            "RELATED-TO"
+                if property
+                    .param("RELTYPE")
+                    .is_none_or(|reltype| reltype.eq_ignore_ascii_case("PARENT")) =>
+            {
+                task.parent_task_id = Some(unescape_text(&property.value));
+            }
+            "DTSTART" => task.scheduled = Some(parse_date_value(property)?),
+            "DUE" => task.deadline = Some(parse_date_value(property)?),
+            "RRULE" => match property.value.parse() {
+                Ok(rule) => task.recurrence = Some(Recurrence::Rule(rule)),
+                Err(_) => importer.unmapped(format!("RRULE: {}", property.value)),
+            },
+            "PRIORITY" => {
+                let priority = property
+                    .value
+                    .trim()
+                    .parse()
+                    .map_err(|_| property.invalid())?;
+                task.priority = priority_from_ical(priority);
+            }
+            "STATUS" => task.done = property.value.eq_ignore_ascii_case("COMPLETED"),
+            "COMPLETED" => task.completed_at = Some(parse_datetime(property)?),
+            "CREATED" => task.created_at = Some(parse_datetime(property)?),
+            "LAST-MODIFIED" => task.updated_at = Some(parse_datetime(property)?),
+            name if BOOKKEEPING.contains(&name) => {}
+            name => importer.unmapped(name.into()),
+        }
+    }
+
+    if task.task_id.is_empty() {
+        task.task_id = ids::new_id();
+    }
+    let mut categories = categories.into_iter();
+    let project_path = categories
+        .next()
+        .map(|path| path.split(" / ").map(Into::into).collect());
+    task.labels = categories.map(|name| importer.label(name)).collect();
+
+    Ok(ImportedTask { task, project_path })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::tests::{project, task};
+    use crate::{Database, DateOrDateTime, Label, Operation, Priority, Project, Task};
+
+    #[test]
+    pub fn export_task() {
+        let mut db = Database::new();
+        db.apply_operation(Operation::CreateTask {
+            task: Task {
+                done: true,
+                scheduled: Some(DateOrDateTime::Date(
+                    NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+                )),
+                deadline: Some(DateOrDateTime::DateTime(
+                    Utc.with_ymd_and_hms(2024, 10, 2, 17, 30, 0).unwrap(),
+                )),
+                priority: Priority::High,
+                description: Some("Skimmed\nOrganic".into()),
+                completed_at: Some(Utc.with_ymd_and_hms(2024, 10, 1, 9, 0, 0).unwrap()),
+                recurrence: Some("FREQ=WEEKLY;BYDAY=TU".parse().unwrap()),
+                ..task("mytask", "Buy milk, eggs; bread")
+            },
+            project_id: None,
+            at: None,
+        });
+        db.batch_operations(vec![
+            Operation::CreateLabel {
+                label: Label {
+                    label_id: "shop".into(),
+                    name: "@shop".into(),
+                    color: None,
+                },
+            },
+            Operation::CreateTask {
+                task: Task {
+                    parent_task_id: Some("mytask".into()),
+                    labels: BTreeSet::from(["shop".into()]),
+                    ..task("subtask", "Milk")
+                },
+                project_id: None,
+                at: None,
+            },
+        ]);
+
+        let ics = super::export(&db, Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap());
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:mytask\r\n"));
+        assert!(ics.contains("SUMMARY:Buy milk\\, eggs\\; bread\r\n"));
+        assert!(ics.contains("DESCRIPTION:Skimmed\\nOrganic\r\n"));
+        assert!(ics.contains("CATEGORIES:Inbox\r\n"));
+        assert!(ics.contains("CATEGORIES:Inbox,@shop\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20241001\r\n"));
+        assert!(ics.contains("DUE:20241002T173000Z\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=TU\r\n"));
+        assert!(ics.contains("PRIORITY:3\r\n"));
+        assert!(ics.contains("RELATED-TO;RELTYPE=PARENT:mytask\r\n"));
+        assert!(ics.contains("STATUS:COMPLETED\r\n"));
+        assert!(ics.contains("COMPLETED:20241001T090000Z\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    pub fn fold_long_lines() {
+        let mut out = String::new();
+        super::write_line(&mut out, &"a".repeat(100));
+
+        let lines: Vec<&str> = out.split("\r\n").collect();
+        assert_eq!(lines[0].len(), 75);
+        assert_eq!(lines[1], format!(" {}", "a".repeat(25)));
+    }
+
+    #[test]
+    pub fn import_exported_tasks() {
+        let mut db = Database::new();
+        db.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("clients", "Clients")
+                },
+                at: None,
+            },
+            Operation::CreateLabel {
+                label: Label {
+                    label_id: "call".into(),
+                    name: "@call".into(),
+                    color: None,
+                },
+            },
+            Operation::CreateTask {
+                task: Task {
+                    done: true,
+                    deadline: Some(DateOrDateTime::DateTime(
+                        Utc.with_ymd_and_hms(2024, 10, 2, 17, 30, 0).unwrap(),
+                    )),
+                    priority: Priority::Urgent,
+                    completed_at: Some(Utc.with_ymd_and_hms(2024, 10, 1, 9, 0, 0).unwrap()),
+                    ..task("invoice", "Send invoice")
+                },
+                project_id: Some("clients".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    parent_task_id: Some("invoice".into()),
+                    scheduled: Some(DateOrDateTime::Date(
+                        NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+                    )),
+                    labels: BTreeSet::from(["call".into()]),
+                    ..task("hours", "Ask for the hours")
+                },
+                project_id: Some("clients".into()),
+                at: None,
+            },
+        ]);
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+
+        let mut imported = Database::new();
+        let import = super::import(&imported, &super::export(&db, now), now).unwrap();
+        assert_eq!(import.unmapped, Vec::<String>::new());
+        imported.batch_operations(import.operations);
+
+        let clients = imported.project_of("invoice").unwrap();
+        assert_eq!(
+            imported.project_path(&clients.project_id),
+            ["Work", "Clients"]
+        );
+        assert_eq!(
+            imported.project_of("hours").unwrap().project_id,
+            clients.project_id
+        );
+        for task_id in ["invoice", "hours"] {
+            let mut expected = db.get_task(task_id).unwrap().clone();
+            let mut task = imported.get_task(task_id).unwrap().clone();
+            (expected.created_at, expected.updated_at) = (None, None);
+            (task.created_at, task.updated_at) = (None, None);
+            task.sort_key = expected.sort_key.clone();
+            task.labels = task
+                .labels
+                .iter()
+                .map(|label_id| imported.labels[label_id].name.clone())
+                .collect();
+            expected.labels = expected
+                .labels
+                .iter()
+                .map(|label_id| db.labels[label_id].name.clone())
+                .collect();
+            assert_eq!(task, expected);
+        }
+
+        // Importing the same file again doesn't duplicate the tasks
+        let ops = super::import(&imported, &super::export(&db, now), now)
+            .unwrap()
+            .operations;
+        let before = imported.clone();
+        imported.batch_operations(ops);
+        assert_eq!(imported.all_tasks().len(), before.all_tasks().len());
+        assert_eq!(imported.projects.len(), before.projects.len());
+    }
+
+    #[test]
+    pub fn import_foreign_calendar() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            PRODID:-//Example//Tasks//EN\r\n\
+            BEGIN:VTODO\r\n\
+            UID:child@example.com\r\n\
+            SUMMARY:Book the\r\n\
+             \x20 venue\r\n\
+            RELATED-TO:parent@example.com\r\n\
+            DUE;TZID=\"Europe/Amsterdam\":20241005T120000\r\n\
+            RRULE:FREQ=HOURLY\r\n\
+            LOCATION:Town hall\r\n\
+            DTSTAMP:20241001T000000Z\r\n\
+            BEGIN:VALARM\r\n\
+            ACTION:DISPLAY\r\n\
+            DESCRIPTION:Reminder\r\n\
+            END:VALARM\r\n\
+            END:VTODO\r\n\
+            BEGIN:VTODO\r\n\
+            UID:parent@example.com\r\n\
+            SUMMARY:Party\r\n\
+            PRIORITY:2\r\n\
+            CATEGORIES:Home\r\n\
+            END:VTODO\r\n\
+            END:VCALENDAR\r\n";
+        let db = Database::new();
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+
+        let import = super::import(&db, ics, now).unwrap();
+        assert_eq!(
+            import.unmapped,
+            [
+                "DUE: 20241005T120000 Europe/Amsterdam",
+                "LOCATION",
+                "RRULE: FREQ=HOURLY",
+                "VALARM"
+            ]
+        );
+
+        let ops = import.operations;
+        let [Operation::CreateProject { project, .. }, Operation::CreateTask {
+            task: parent,
+            project_id: Some(parent_project),
+            ..
+        }, Operation::CreateTask {
+            task: child,
+            project_id: None,
+            ..
+        }] = ops.as_slice()
+        else {
+            panic!("Expected a project, a parent and a subtask");
+        };
+        assert_eq!(project.name, "Home");
+        assert_eq!(parent_project, &project.project_id);
+        assert_eq!(parent.task_id, "parent@example.com");
+        assert_eq!(parent.priority, Priority::Urgent);
+        assert_eq!(child.summary, "Book the venue");
+        assert_eq!(child.description, None);
+        assert_eq!(child.parent_task_id.as_deref(), Some("parent@example.com"));
+        assert_eq!(
+            child.deadline,
+            Some(DateOrDateTime::DateTime(
+                Utc.with_ymd_and_hms(2024, 10, 5, 12, 0, 0).unwrap()
+            ))
+        );
+
+        assert_eq!(
+            super::import(&db, "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\n", now).err(),
+            Some(super::IcalParseError::Unterminated("VTODO".into()))
+        );
+    }
+}
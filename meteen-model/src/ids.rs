@@ -0,0 +1,59 @@
+//! Ids for new tasks and projects.
+//!
+//! Ids are ULIDs: the creation time in milliseconds followed by 80 random bits, written as 26
+//! characters of Crockford's base 32. Devices generate them without talking to each other, the
+//! random part makes a collision practically impossible and the time part makes ids sort in the
+//! order they were created.
+//!
+//! Should an id be created twice anyway, the creation applied first wins and the later one is
+//! ignored. That also makes sending the same creation again harmless. The server applies
+//! operations in one order for every device, so all replicas keep the same item.
+
+use chrono::{DateTime, Utc};
+
+const ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A new id, unique across devices.
+pub fn new_id() -> String {
+    let mut random = [0u8; 10];
+    // Without a source of randomness ids could collide, which is worse than failing
+    getrandom::getrandom(&mut random).expect("No source of randomness available");
+    encode(Utc::now(), random)
+}
+
+fn encode(at: DateTime<Utc>, random: [u8; 10]) -> String {
+    // Times before 1970 don't occur, and the 48 bits of the time last until the year 10889
+    let millis = u128::try_from(at.timestamp_millis()).unwrap_or(0) & ((1 << 48) - 1);
+    let value = random
+        .iter()
+        .fold(millis, |value, byte| (value << 8) | u128::from(*byte));
+
+    (0..26)
+        .rev()
+        .map(|digit| char::from(ALPHABET[((value >> (digit * 5)) & 0x1f) as usize]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{encode, new_id};
+
+    #[test]
+    fn ids_sort_by_creation_time() {
+        let early = encode(Utc.timestamp_millis_opt(1).unwrap(), [0xff; 10]);
+        let late = encode(Utc.timestamp_millis_opt(2).unwrap(), [0; 10]);
+
+        assert_eq!(early, "0000000001ZZZZZZZZZZZZZZZZ");
+        assert_eq!(late, "00000000020000000000000000");
+        assert!(early < late);
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        let ids: std::collections::HashSet<String> = (0..1000).map(|_| new_id()).collect();
+        assert_eq!(ids.len(), 1000);
+        assert!(ids.iter().all(|id| id.len() == 26));
+    }
+}
@@ -0,0 +1,742 @@
+//! Importing tasks from other tools as [`Operation`]s, which create the tasks together with the
+//! projects and labels they need that don't exist yet.
+//!
+//! Projects are matched by their path and labels by their name, so importing into a database
+//! that already has them reuses them. Whatever an import can't express is reported rather than
+//! silently dropped.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ical, ids, markdown, todoist, todotxt, Database, DateOrDateTime, Label, Operation, Priority,
+    Project, Task, INBOX_ID,
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ImportError {
+    #[error("The quote on line {0} is never closed")]
+    UnclosedQuote(usize),
+
+    #[error("There is no {0} column")]
+    MissingColumn(String),
+
+    #[error("Invalid Todoist export: {0}")]
+    InvalidJson(String),
+
+    #[error(transparent)]
+    Ical(#[from] ical::IcalParseError),
+}
+
+/// What an import adds to a database.
+pub struct Import {
+    pub operations: Vec<Operation>,
+    /// The fields that were left out because they have no counterpart here, and the values that
+    /// couldn't be read, like `DATE: every other day`
+    pub unmapped: Vec<String>,
+}
+
+/// The formats that can be imported, tagged by `format` when deserialized, so
+/// `format=todoist-csv&project=Work` selects a Todoist CSV export of the Work project.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "format", rename_all = "kebab-case")]
+pub enum ImportFormat {
+    Ical,
+    /// A Todoist backup as returned by its sync API, with every project
+    Todoist,
+    /// The CSV export of a single Todoist project
+    TodoistCsv {
+        project: String,
+    },
+    Csv(Box<CsvLayout>),
+    /// Tasks that were exported are updated rather than created again
+    TodoTxt,
+    /// Tasks that were exported are updated rather than created again
+    Markdown,
+}
+
+/// Reads `text` in `format` into the operations that add it to `db`. `now` is when new
+/// projects are created.
+pub fn import(
+    db: &Database,
+    format: &ImportFormat,
+    text: &str,
+    now: DateTime<Utc>,
+) -> Result<Import, ImportError> {
+    match format {
+        ImportFormat::Ical => Ok(ical::import(db, text, now)?),
+        ImportFormat::Todoist => todoist::import_json(db, text, now),
+        ImportFormat::TodoistCsv { project } => todoist::import_csv(db, text, project, now),
+        ImportFormat::Csv(layout) => import_csv(db, text, layout, now),
+        ImportFormat::TodoTxt => Ok(Import {
+            operations: todotxt::import(db, text, now),
+            unmapped: vec![],
+        }),
+        ImportFormat::Markdown => Ok(Import {
+            operations: markdown::import(db, text, now),
+            unmapped: vec![],
+        }),
+    }
+}
+
+/// Which columns of a CSV file hold what. Columns that are missing from the file are left
+/// empty, except for the summary.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct CsvLayout {
+    pub summary: String,
+    pub description: Option<String>,
+    /// The path of the project, like `Work/Clients`, tasks without one go to the inbox
+    pub project: Option<String>,
+    pub project_separator: String,
+    pub labels: Option<String>,
+    pub label_separator: String,
+    /// Either a name like `high`, or 1 (urgent) to 4 (low) as in `p1` to `p4`
+    pub priority: Option<String>,
+    pub scheduled: Option<String>,
+    pub deadline: Option<String>,
+    /// Anything but empty, `false`, `no` and `0` means done
+    pub done: Option<String>,
+    pub delimiter: char,
+}
+
+impl Default for CsvLayout {
+    fn default() -> Self {
+        CsvLayout {
+            summary: "summary".into(),
+            description: Some("description".into()),
+            project: Some("project".into()),
+            project_separator: "/".into(),
+            labels: Some("labels".into()),
+            label_separator: ",".into(),
+            priority: Some("priority".into()),
+            scheduled: Some("scheduled".into()),
+            deadline: Some("deadline".into()),
+            done: Some("done".into()),
+            delimiter: ',',
+        }
+    }
+}
+
+/// Reads a CSV file with a header row, laid out as described by `layout`.
+pub fn import_csv(
+    db: &Database,
+    csv: &str,
+    layout: &CsvLayout,
+    now: DateTime<Utc>,
+) -> Result<Import, ImportError> {
+    let mut rows = parse_csv(csv, layout.delimiter)?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |name: &Option<String>| {
+        name.as_ref()
+            .and_then(|name| header.iter().position(|column| column == name))
+    };
+
+    let summary = header
+        .iter()
+        .position(|column| column == &layout.summary)
+        .ok_or_else(|| ImportError::MissingColumn(layout.summary.clone()))?;
+    let description = column(&layout.description);
+    let project = column(&layout.project);
+    let labels = column(&layout.labels);
+    let priority = column(&layout.priority);
+    let scheduled = column(&layout.scheduled);
+    let deadline = column(&layout.deadline);
+    let done = column(&layout.done);
+    let mapped = [
+        Some(summary),
+        description,
+        project,
+        labels,
+        priority,
+        scheduled,
+        deadline,
+        done,
+    ];
+
+    let mut importer = Importer::new(db, now);
+    let mut tasks = vec![];
+    for row in rows {
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|column| row.get(column))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        for (index, (column, value)) in header.iter().zip(&row).enumerate() {
+            if !value.trim().is_empty() && !mapped.contains(&Some(index)) {
+                importer.unmapped(column.clone());
+            }
+        }
+
+        let mut task = new_task(ids::new_id());
+        task.summary = value(Some(summary)).unwrap_or_default().into();
+        task.description = value(description).map(Into::into);
+        if let Some(value) = value(labels) {
+            task.labels = value
+                .split(layout.label_separator.as_str())
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| importer.label(name.into()))
+                .collect();
+        }
+        if let Some(value) = value(priority) {
+            match parse_priority(value) {
+                Some(parsed) => task.priority = parsed,
+                // Unwrap is safe because there is a value in the column
+                None => importer.unmapped(format!("{}: {}", header[priority.unwrap()], value)),
+            }
+        }
+        for (column, date) in [
+            (scheduled, &mut task.scheduled),
+            (deadline, &mut task.deadline),
+        ] {
+            if let Some(value) = value(column) {
+                *date = parse_date(value);
+                if date.is_none() {
+                    // Unwrap is safe because there is a value in the column
+                    importer.unmapped(format!("{}: {}", header[column.unwrap()], value));
+                }
+            }
+        }
+        task.done = value(done)
+            .is_some_and(|value| !["false", "no", "0"].contains(&value.to_lowercase().as_str()));
+
+        let project_path = value(project).map(|path| {
+            path.split(layout.project_separator.as_str())
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect::<Vec<_>>()
+        });
+        tasks.push(ImportedTask {
+            task,
+            project_path: project_path.filter(|path| !path.is_empty()),
+        });
+    }
+
+    importer.create_tasks(tasks);
+    Ok(importer.finish())
+}
+
+/// Splits CSV text as described in RFC 4180 into rows of fields, skipping empty lines.
+pub(crate) fn parse_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, ImportError> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut line = 1;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err(ImportError::UnclosedQuote(start)),
+                    }
+                }
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|field| !field.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|field| !field.is_empty()) {
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Reads `p1` to `p4`, `1` (urgent) to `4` (low), or the name of a priority.
+fn parse_priority(value: &str) -> Option<Priority> {
+    let value = value.to_lowercase();
+    Some(match value.trim_start_matches('p') {
+        "1" | "urgent" => Priority::Urgent,
+        "2" | "high" => Priority::High,
+        "3" | "standard" => Priority::Standard,
+        "4" | "low" => Priority::Low,
+        _ => return None,
+    })
+}
+
+/// Reads a date like `2024-10-01`, or a time like `2024-10-01 17:30` or `2024-10-01T17:30:00Z`.
+/// Times without a timezone are taken as UTC.
+pub(crate) fn parse_date(value: &str) -> Option<DateOrDateTime> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(DateOrDateTime::Date(date));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(DateOrDateTime::DateTime(datetime.with_timezone(&Utc)));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|datetime| DateOrDateTime::DateTime(datetime.and_utc()))
+}
+
+/// How a name is written in a tag like `+project` or `@label`, which ends at a space.
+pub(crate) fn to_tag(name: &str) -> String {
+    name.replace(' ', "_")
+}
+
+/// A task with nothing filled in but its id.
+pub(crate) fn new_task(task_id: String) -> Task {
+    Task {
+        task_id,
+        summary: String::new(),
+        done: false,
+        scheduled: None,
+        deadline: None,
+        priority: Priority::Standard,
+        recurrence: None,
+        parent_task_id: None,
+        sort_key: String::new(),
+        labels: BTreeSet::new(),
+        description: None,
+        created_at: None,
+        updated_at: None,
+        completed_at: None,
+    }
+}
+
+/// A task that is read, with the path of the project it goes to, or `None` for the inbox.
+pub(crate) struct ImportedTask {
+    pub task: Task,
+    pub project_path: Option<Vec<String>>,
+}
+
+/// Collects the operations of an import, creating projects and labels as they are needed.
+pub(crate) struct Importer {
+    /// The ids of projects by their path, also of those created by this import
+    projects: HashMap<Vec<String>, String>,
+    /// The ids of labels by their name
+    labels: HashMap<String, String>,
+    existing_tasks: HashSet<String>,
+    unmapped: BTreeSet<String>,
+    now: DateTime<Utc>,
+    ops: Vec<Operation>,
+}
+
+impl Importer {
+    pub fn new(db: &Database, now: DateTime<Utc>) -> Self {
+        Importer {
+            projects: db
+                .projects
+                .keys()
+                .map(|project_id| {
+                    let path = db.project_path(project_id);
+                    (
+                        path.into_iter().map(Into::into).collect(),
+                        project_id.clone(),
+                    )
+                })
+                .collect(),
+            labels: db
+                .labels
+                .values()
+                .map(|label| (label.name.clone(), label.label_id.clone()))
+                .collect(),
+            existing_tasks: db
+                .all_tasks()
+                .into_iter()
+                .map(|task| task.task_id.clone())
+                .collect(),
+            unmapped: BTreeSet::new(),
+            now,
+            ops: vec![],
+        }
+    }
+
+    /// Reports a field or value that isn't imported, each is reported once.
+    pub fn unmapped(&mut self, field: String) {
+        self.unmapped.insert(field);
+    }
+
+    /// The id of the label with this name, creating it if it's missing.
+    pub fn label(&mut self, name: String) -> String {
+        if let Some(label_id) = self.labels.get(&name) {
+            return label_id.clone();
+        }
+
+        let label_id = ids::new_id();
+        self.labels.insert(name.clone(), label_id.clone());
+        self.ops.push(Operation::CreateLabel {
+            label: Label {
+                label_id: label_id.clone(),
+                name,
+                color: None,
+            },
+        });
+        label_id
+    }
+
+    /// The path of the project a tag like `+Work/Side_projects` refers to, in which spaces are
+    /// written as underscores. Names that don't match an existing project are kept as written.
+    pub fn tag_path(&self, tag: &str) -> Vec<String> {
+        let mut path: Vec<String> = vec![];
+        for name in tag.split('/').filter(|name| !name.is_empty()) {
+            let existing = self.projects.keys().find(|existing| {
+                existing.len() == path.len() + 1
+                    && existing.starts_with(&path)
+                    && to_tag(existing.last().unwrap()) == name
+            });
+            path.push(match existing {
+                // Unwrap is safe because the existing path is one longer than the path so far
+                Some(existing) => existing.last().unwrap().clone(),
+                None => name.into(),
+            });
+        }
+        path
+    }
+
+    /// The id of the label a tag like `@waiting_for` refers to, see [`Importer::tag_path`].
+    pub fn tag_label(&mut self, tag: &str) -> String {
+        let existing = self.labels.keys().find(|name| to_tag(name) == tag).cloned();
+        self.label(existing.unwrap_or_else(|| tag.into()))
+    }
+
+    /// The id of the project with this path, creating the projects along it that are missing.
+    ///
+    /// # Panics
+    /// Panics if the path is empty
+    pub fn project(&mut self, path: &[String]) -> String {
+        let mut parent_id = None;
+
+        for depth in 1..=path.len() {
+            let project_id = match self.projects.get(&path[..depth]) {
+                Some(project_id) => project_id.clone(),
+                None => {
+                    let project_id = ids::new_id();
+                    self.projects
+                        .insert(path[..depth].to_vec(), project_id.clone());
+                    self.ops.push(Operation::CreateProject {
+                        project: Project {
+                            name: path[depth - 1].clone(),
+                            project_id: project_id.clone(),
+                            parent_id: parent_id.clone(),
+                            tasks: vec![],
+                            color: None,
+                            icon: None,
+                            description: None,
+                            sort_key: String::new(),
+                            created_at: None,
+                            updated_at: None,
+                        },
+                        at: Some(self.now),
+                    });
+                    project_id
+                }
+            };
+            parent_id = Some(project_id);
+        }
+
+        parent_id.expect("Tried to import into a project without a path")
+    }
+
+    /// Creates the tasks in the order they were read, except that parents come before their
+    /// subtasks.
+    pub fn create_tasks(&mut self, tasks: Vec<ImportedTask>) {
+        let imported: HashSet<String> = tasks.iter().map(|t| t.task.task_id.clone()).collect();
+        let positions: HashMap<String, usize> = tasks
+            .iter()
+            .enumerate()
+            .map(|(position, t)| (t.task.task_id.clone(), position))
+            .collect();
+        let mut pending: Vec<Option<ImportedTask>> = tasks.into_iter().map(Some).collect();
+
+        for position in 0..pending.len() {
+            // Walk up to the first ancestor that isn't created yet and create from there down
+            let mut chain = vec![];
+            let mut current = Some(position);
+            while let Some(position) = current {
+                let Some(imported_task) = &pending[position] else {
+                    break;
+                };
+                if chain.contains(&position) {
+                    break;
+                }
+                chain.push(position);
+                current = imported_task
+                    .task
+                    .parent_task_id
+                    .as_ref()
+                    .and_then(|parent_task_id| positions.get(parent_task_id))
+                    .copied();
+            }
+
+            for position in chain.into_iter().rev() {
+                if let Some(imported_task) = pending[position].take() {
+                    self.create_task(imported_task, &imported);
+                }
+            }
+        }
+    }
+
+    fn create_task(&mut self, imported_task: ImportedTask, imported: &HashSet<String>) {
+        let ImportedTask {
+            mut task,
+            project_path,
+        } = imported_task;
+
+        // A parent that is neither imported nor already there would make the task be refused
+        let parent_known = |parent_task_id: &String| {
+            imported.contains(parent_task_id) || self.existing_tasks.contains(parent_task_id)
+        };
+        if !task.parent_task_id.as_ref().is_some_and(parent_known) {
+            task.parent_task_id = None;
+        }
+
+        let project_id = project_path.map(|path| self.project(&path));
+        let at = task.created_at;
+        self.ops.push(Operation::CreateTask {
+            task,
+            project_id,
+            at,
+        });
+    }
+
+    /// Like [`Importer::create_tasks`], but the tasks that already exist in `db` are updated to
+    /// match instead, which makes a file that was exported, edited and imported again apply
+    /// the edits. The order of existing tasks and their notes are left as they are.
+    pub fn create_or_update_tasks(&mut self, db: &Database, tasks: Vec<ImportedTask>) {
+        let (existing, new): (Vec<_>, Vec<_>) = tasks
+            .into_iter()
+            .partition(|imported| self.existing_tasks.contains(&imported.task.task_id));
+
+        // Created first, so existing tasks can be moved into new ones
+        let created: HashSet<String> = new.iter().map(|t| t.task.task_id.clone()).collect();
+        self.create_tasks(new);
+        self.existing_tasks.extend(created);
+
+        for imported in existing {
+            self.update_task(db, imported);
+        }
+    }
+
+    fn update_task(&mut self, db: &Database, imported_task: ImportedTask) {
+        let ImportedTask { task, project_path } = imported_task;
+        let Some(existing) = db.get_task(&task.task_id) else {
+            return;
+        };
+        let task_id = task.task_id.clone();
+        let at = Some(self.now);
+
+        if task.summary != existing.summary {
+            self.ops.push(Operation::UpdateTaskSummary {
+                task_id: task_id.clone(),
+                summary: task.summary,
+                at,
+            });
+        }
+        if task.done != existing.done {
+            self.ops.push(Operation::UpdateTaskDone {
+                task_id: task_id.clone(),
+                done: task.done,
+                at,
+                with_subtasks: false,
+                occurrence: None,
+            });
+        }
+        if task.scheduled != existing.scheduled {
+            self.ops.push(Operation::UpdateTaskScheduled {
+                task_id: task_id.clone(),
+                scheduled: task.scheduled,
+                at,
+            });
+        }
+        if task.deadline != existing.deadline {
+            self.ops.push(Operation::UpdateTaskDeadline {
+                task_id: task_id.clone(),
+                deadline: task.deadline,
+                at,
+            });
+        }
+        if task.priority != existing.priority {
+            self.ops.push(Operation::UpdateTaskPriority {
+                task_id: task_id.clone(),
+                priority: task.priority,
+                at,
+            });
+        }
+        if task.recurrence != existing.recurrence {
+            self.ops.push(Operation::UpdateTaskRecurrence {
+                task_id: task_id.clone(),
+                recurrence: task.recurrence,
+                at,
+            });
+        }
+        for label_id in task.labels.difference(&existing.labels) {
+            self.ops.push(Operation::AddTaskLabel {
+                task_id: task_id.clone(),
+                label_id: label_id.clone(),
+                at,
+            });
+        }
+        for label_id in existing.labels.difference(&task.labels) {
+            self.ops.push(Operation::RemoveTaskLabel {
+                task_id: task_id.clone(),
+                label_id: label_id.clone(),
+                at,
+            });
+        }
+
+        let project_id = match project_path {
+            Some(path) => self.project(&path),
+            None => INBOX_ID.into(),
+        };
+        let moved = db
+            .project_of(&task_id)
+            .is_ok_and(|project| project.project_id != project_id);
+        if moved {
+            self.ops.push(Operation::MoveTask {
+                task_id: task_id.clone(),
+                project_id_to: project_id,
+                at,
+            });
+        }
+
+        let parent_task_id = task
+            .parent_task_id
+            .filter(|parent_task_id| self.existing_tasks.contains(parent_task_id));
+        // Moving detaches the task from its parent
+        if parent_task_id != existing.parent_task_id || (moved && parent_task_id.is_some()) {
+            self.ops.push(Operation::SetTaskParent {
+                task_id,
+                parent_task_id,
+                at,
+            });
+        }
+    }
+
+    pub fn finish(self) -> Import {
+        Import {
+            operations: self.ops,
+            unmapped: self.unmapped.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{CsvLayout, ImportError, ImportFormat};
+    use crate::{Database, DateOrDateTime, Priority};
+
+    #[test]
+    pub fn parse_quoted_fields() {
+        let rows = super::parse_csv("a,\"b, \"\"c\"\"\"\r\n\r\n\"multi\nline\",d", ',').unwrap();
+        assert_eq!(rows, [["a", "b, \"c\""], ["multi\nline", "d"]]);
+
+        assert_eq!(
+            super::parse_csv("a\n\"b", ','),
+            Err(ImportError::UnclosedQuote(2))
+        );
+    }
+
+    #[test]
+    pub fn import_generic_csv() {
+        let layout = CsvLayout {
+            summary: "Title".into(),
+            project: Some("List".into()),
+            priority: Some("Importance".into()),
+            deadline: Some("Due".into()),
+            delimiter: ';',
+            ..Default::default()
+        };
+        let csv = "Title;List;Importance;Due;labels;Notes;Color\n\
+                   Call mom;Home/Family;p1;2024-10-01;phone, evening;Before dinner;\n\
+                   Fix bike;Home;whenever;someday;;;\n\
+                   Water plants;;low;2024-10-02 18:00;;;green\n";
+        let mut db = Database::new();
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+
+        let import = super::import_csv(&db, csv, &layout, now).unwrap();
+        db.batch_operations(import.operations);
+
+        assert_eq!(
+            import.unmapped,
+            ["Color", "Due: someday", "Importance: whenever", "Notes"]
+        );
+        let tasks = db.all_tasks();
+        assert_eq!(tasks.len(), 3);
+
+        let call = tasks.iter().find(|t| t.summary == "Call mom").unwrap();
+        let family = db.project_of(&call.task_id).unwrap();
+        assert_eq!(db.project_path(&family.project_id), ["Home", "Family"]);
+        assert_eq!(call.priority, Priority::Urgent);
+        assert_eq!(
+            call.deadline,
+            Some(DateOrDateTime::Date(
+                NaiveDate::from_ymd_opt(2024, 10, 1).unwrap()
+            ))
+        );
+        let mut labels: Vec<&str> = call
+            .labels
+            .iter()
+            .map(|label_id| db.labels[label_id].name.as_str())
+            .collect();
+        labels.sort();
+        assert_eq!(labels, ["evening", "phone"]);
+
+        let plants = tasks.iter().find(|t| t.summary == "Water plants").unwrap();
+        assert_eq!(db.project_of(&plants.task_id).unwrap().name, "Inbox");
+        assert_eq!(plants.priority, Priority::Low);
+        assert_eq!(
+            plants.deadline,
+            Some(DateOrDateTime::DateTime(
+                Utc.with_ymd_and_hms(2024, 10, 2, 18, 0, 0).unwrap()
+            ))
+        );
+
+        // The existing Home project is reused
+        let import = super::import_csv(&db, "Title;List\nMore;Home\n", &layout, now).unwrap();
+        assert_eq!(import.operations.len(), 1);
+    }
+
+    #[test]
+    pub fn deserialize_format() {
+        let format: ImportFormat =
+            serde_json::from_str(r#"{"format": "csv", "summary": "Title", "delimiter": ";"}"#)
+                .unwrap();
+        assert_eq!(
+            format,
+            ImportFormat::Csv(Box::new(CsvLayout {
+                summary: "Title".into(),
+                delimiter: ';',
+                ..Default::default()
+            }))
+        );
+
+        let format: ImportFormat =
+            serde_json::from_str(r#"{"format": "todoist-csv", "project": "Work"}"#).unwrap();
+        assert_eq!(
+            format,
+            ImportFormat::TodoistCsv {
+                project: "Work".into()
+            }
+        );
+    }
+}
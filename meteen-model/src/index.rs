@@ -0,0 +1,355 @@
+//! The index from task ids to where each task is.
+//!
+//! Looking tasks up by id is what nearly every operation starts with, so instead of searching
+//! every project the database keeps track of the project each live task is in and its position
+//! there. The index is updated wherever tasks enter, leave or move within or between projects,
+//! and rebuilt when a database is loaded. Code changing `projects` directly has to call
+//! [`Database::reindex`] afterwards.
+//!
+//! Edits reach trashed items too, so the trash is indexed the same way, by where each item sits
+//! in it. Trashing adds to the end of the trash, restoring and purging take entries out and
+//! shift the others, so those rebuild the trash part of the index.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::{Database, InvariantError, Label, Project, Task, Trash};
+
+/// Where a live task is.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct TaskLocation {
+    pub(crate) project_id: String,
+    /// The position of the task in the tasks of the project
+    pub(crate) position: usize,
+}
+
+/// Where a trashed task is, by positions in [`Trash`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum TrashedAt {
+    /// In `trash.tasks[entry]`, position 0 being the task itself and the rest its subtasks
+    Task { entry: usize, position: usize },
+    /// In `trash.projects[entry].projects[project].tasks[position]`
+    ProjectTask {
+        entry: usize,
+        project: usize,
+        position: usize,
+    },
+}
+
+/// The ids of trashed tasks and projects, the most recently trashed copy winning like in
+/// [`Database::trashed_task`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) struct TrashIndex {
+    tasks: HashMap<String, TrashedAt>,
+    /// Projects by the entry in `trash.projects` and their position in it
+    projects: HashMap<String, (usize, usize)>,
+}
+
+impl TrashIndex {
+    fn of(trash: &Trash) -> TrashIndex {
+        let mut index = TrashIndex::default();
+        for entry in 0..trash.tasks.len() {
+            index.add_task_entry(trash, entry);
+        }
+        for entry in 0..trash.projects.len() {
+            index.add_project_entry(trash, entry);
+        }
+        index
+    }
+
+    /// Records the items of `trash.tasks[entry]`.
+    pub(crate) fn add_task_entry(&mut self, trash: &Trash, entry: usize) {
+        let trashed = &trash.tasks[entry];
+        for (position, task) in std::iter::once(&trashed.task)
+            .chain(&trashed.subtasks)
+            .enumerate()
+        {
+            self.tasks
+                .insert(task.task_id.clone(), TrashedAt::Task { entry, position });
+        }
+    }
+
+    /// Records the items of `trash.projects[entry]`.
+    pub(crate) fn add_project_entry(&mut self, trash: &Trash, entry: usize) {
+        for (project_position, project) in trash.projects[entry].projects.iter().enumerate() {
+            self.projects
+                .insert(project.project_id.clone(), (entry, project_position));
+            for (position, task) in project.tasks.iter().enumerate() {
+                self.tasks.insert(
+                    task.task_id.clone(),
+                    TrashedAt::ProjectTask {
+                        entry,
+                        project: project_position,
+                        position,
+                    },
+                );
+            }
+        }
+    }
+
+    pub(crate) fn task(&self, task_id: &str) -> Option<TrashedAt> {
+        self.tasks.get(task_id).copied()
+    }
+
+    pub(crate) fn project(&self, project_id: &str) -> Option<(usize, usize)> {
+        self.projects.get(project_id).copied()
+    }
+}
+
+/// A database as it is stored, without the index.
+#[derive(Deserialize)]
+pub(crate) struct StoredDatabase {
+    projects: HashMap<String, Project>,
+    #[serde(default)]
+    trash: Trash,
+    #[serde(default)]
+    labels: HashMap<String, Label>,
+}
+
+impl From<StoredDatabase> for Database {
+    fn from(stored: StoredDatabase) -> Self {
+        let mut db = Database {
+            projects: stored.projects,
+            trash: stored.trash,
+            labels: stored.labels,
+            task_index: HashMap::new(),
+            trash_index: TrashIndex::default(),
+        };
+        db.reindex();
+        db
+    }
+}
+
+/// The index follows from the rest, so it is left out of comparisons.
+impl PartialEq for Database {
+    fn eq(&self, other: &Self) -> bool {
+        self.projects == other.projects && self.trash == other.trash && self.labels == other.labels
+    }
+}
+
+impl Eq for Database {}
+
+impl Database {
+    /// Rebuilds the index of tasks and the trash from scratch.
+    pub fn reindex(&mut self) {
+        self.reindex_trash();
+        self.task_index = self
+            .projects
+            .values()
+            .flat_map(|project| {
+                project.tasks.iter().enumerate().map(|(position, task)| {
+                    let location = TaskLocation {
+                        project_id: project.project_id.clone(),
+                        position,
+                    };
+                    (task.task_id.clone(), location)
+                })
+            })
+            .collect();
+    }
+
+    /// Rebuilds the index of the trash, after entries were taken out of it.
+    pub(crate) fn reindex_trash(&mut self) {
+        self.trash_index = TrashIndex::of(&self.trash);
+    }
+
+    /// Records where the tasks of a project are from position `start` on, after tasks were
+    /// added there or taken out before them.
+    pub(crate) fn index_tasks_from(&mut self, project_id: &str, start: usize) {
+        let Some(project) = self.projects.get(project_id) else {
+            return;
+        };
+        for (position, task) in project.tasks.iter().enumerate().skip(start) {
+            let location = TaskLocation {
+                project_id: project_id.into(),
+                position,
+            };
+            self.task_index.insert(task.task_id.clone(), location);
+        }
+    }
+
+    /// Forgets `tasks`, which are no longer in any project.
+    pub(crate) fn unindex_tasks(&mut self, tasks: &[Task]) {
+        for task in tasks {
+            self.task_index.remove(&task.task_id);
+        }
+    }
+
+    /// Whether the index lists exactly the live tasks, each where it is.
+    pub(crate) fn check_index(&self) -> Result<(), InvariantError> {
+        let mut live = HashSet::new();
+        for project in self.projects.values() {
+            for (position, task) in project.tasks.iter().enumerate() {
+                let location = TaskLocation {
+                    project_id: project.project_id.clone(),
+                    position,
+                };
+                if self.task_index.get(&task.task_id) != Some(&location) {
+                    return Err(InvariantError::StaleIndex(task.task_id.clone()));
+                }
+                live.insert(task.task_id.as_str());
+            }
+        }
+
+        if let Some(task_id) = self
+            .task_index
+            .keys()
+            .find(|task_id| !live.contains(task_id.as_str()))
+        {
+            return Err(InvariantError::StaleIndex(task_id.clone()));
+        }
+
+        let trash_index = TrashIndex::of(&self.trash);
+        let stale_task = (trash_index.tasks.keys())
+            .chain(self.trash_index.tasks.keys())
+            .find(|id| trash_index.task(id) != self.trash_index.task(id));
+        let stale_project = (trash_index.projects.keys())
+            .chain(self.trash_index.projects.keys())
+            .find(|id| trash_index.project(id) != self.trash_index.project(id));
+        match stale_task.or(stale_project) {
+            Some(id) => Err(InvariantError::StaleIndex(id.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{project, task};
+    use crate::{Database, DeleteMode, Operation, INBOX_ID};
+
+    #[test]
+    fn index_follows_operations() {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("report", "Write report"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::MoveTask {
+                task_id: "report".into(),
+                project_id_to: INBOX_ID.into(),
+                at: None,
+            },
+        ]);
+        assert_eq!(state.project_of("report").unwrap().project_id, INBOX_ID);
+
+        state.batch_operations(vec![
+            Operation::MoveTask {
+                task_id: "report".into(),
+                project_id_to: "work".into(),
+                at: None,
+            },
+            Operation::DeleteProject {
+                project_id: "work".into(),
+                mode: DeleteMode::Cascade,
+                at: Some(chrono::Utc::now()),
+            },
+        ]);
+        assert!(state.get_task("report").is_none());
+
+        state.apply_operation(Operation::RestoreProject {
+            project_id: "work".into(),
+        });
+        assert_eq!(state.project_of("report").unwrap().project_id, "work");
+        assert_eq!(state.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn positions_follow_removals() {
+        let mut state = Database::new();
+        for task_id in ["a", "b", "c"] {
+            state.apply_operation(Operation::CreateTask {
+                task: task(task_id, task_id),
+                project_id: None,
+                at: None,
+            });
+        }
+        state.apply_operation(Operation::DeleteTask {
+            task_id: "a".into(),
+            at: Some(chrono::Utc::now()),
+        });
+
+        assert_eq!(state.get_task("c").unwrap().task_id, "c");
+        assert_eq!(state.check_invariants(), Ok(()));
+
+        state.apply_operation(Operation::RestoreTask {
+            task_id: "a".into(),
+        });
+        assert_eq!(state.get_task("b").unwrap().task_id, "b");
+        assert_eq!(state.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn trash_index_follows_operations() {
+        let at = Some(chrono::Utc::now());
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("report", "Write report"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("call", "Call client"),
+                project_id: None,
+                at: None,
+            },
+            Operation::DeleteTask {
+                task_id: "call".into(),
+                at,
+            },
+            Operation::DeleteProject {
+                project_id: "work".into(),
+                mode: DeleteMode::Cascade,
+                at,
+            },
+            // Shifts the trashed project to the front of the trash
+            Operation::RestoreTask {
+                task_id: "call".into(),
+            },
+            Operation::UpdateTaskSummary {
+                task_id: "report".into(),
+                summary: "Write the report".into(),
+                at: None,
+            },
+            Operation::RenameProject {
+                project_id: "work".into(),
+                name: "Job".into(),
+                at: None,
+            },
+        ]);
+        assert_eq!(state.check_invariants(), Ok(()));
+
+        let trashed = state.trashed_project("work").unwrap();
+        assert_eq!(trashed.project().name, "Job");
+        assert_eq!(trashed.project().tasks[0].summary, "Write the report");
+    }
+
+    #[test]
+    fn index_is_rebuilt_on_load() {
+        let mut state = Database::new();
+        state.apply_operation(Operation::CreateTask {
+            task: task("report", "Write report"),
+            project_id: None,
+            at: None,
+        });
+
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: Database = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded, state);
+        assert!(loaded.get_task("report").is_some());
+        assert_eq!(loaded.check_invariants(), Ok(()));
+    }
+}
@@ -0,0 +1,593 @@
+//! Inverse operations, the building block for undo and redo.
+//!
+//! An inverse is computed against the state *before* the operation is applied and consists of
+//! ordinary operations, so undoing something syncs to other devices like any other change.
+
+use chrono::{DateTime, Utc};
+
+use crate::{Database, DeleteMode, Operation, Project, Task, INBOX_ID};
+
+/// Completing a recurring task moves its dates, those are put back first so the done status is
+/// restored on the right occurrence.
+fn revert_done(task: &Task, now: DateTime<Utc>) -> Vec<Operation> {
+    let done = Operation::UpdateTaskDone {
+        task_id: task.task_id.clone(),
+        done: task.done,
+        // Completing it again restores when it was completed
+        at: task.completed_at,
+        with_subtasks: false,
+        occurrence: None,
+    };
+    if task.recurrence.is_none() {
+        return vec![done];
+    }
+
+    vec![
+        Operation::UpdateTaskRecurrence {
+            task_id: task.task_id.clone(),
+            recurrence: task.recurrence.clone(),
+            at: Some(now),
+        },
+        Operation::UpdateTaskScheduled {
+            task_id: task.task_id.clone(),
+            scheduled: task.scheduled.clone(),
+            at: Some(now),
+        },
+        Operation::UpdateTaskDeadline {
+            task_id: task.task_id.clone(),
+            deadline: task.deadline.clone(),
+            at: Some(now),
+        },
+        done,
+    ]
+}
+
+impl Database {
+    /// The operations that undo `operation`, to be applied in order after it.
+    ///
+    /// Operations that would be ignored get no inverse, and neither does [`Operation::PurgeTrash`]
+    /// since purged items are gone for good. `now` dates the deletions that undo a creation, they
+    /// go to the trash like any other deletion.
+    pub fn inverse_of(&self, operation: &Operation, now: DateTime<Utc>) -> Vec<Operation> {
+        match operation {
+            Operation::CreateTask {
+                task, project_id, ..
+            } => {
+                let project_id = project_id.as_deref().unwrap_or(INBOX_ID);
+                if !self.projects.contains_key(project_id) || self.get_task(&task.task_id).is_some()
+                {
+                    return vec![];
+                }
+
+                vec![Operation::DeleteTask {
+                    task_id: task.task_id.clone(),
+                    at: Some(now),
+                }]
+            }
+            Operation::DeleteTask { task_id, .. } => match self.get_task(task_id) {
+                Some(_) => vec![Operation::RestoreTask {
+                    task_id: task_id.clone(),
+                }],
+                None => vec![],
+            },
+            Operation::UpdateTaskSummary { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::UpdateTaskSummary {
+                    task_id: task_id.clone(),
+                    summary: task.summary.clone(),
+                    at: Some(now),
+                })
+            }
+            // Undoing merges too, so only this edit is reverted when others edited since
+            Operation::UpdateTaskDescription {
+                task_id,
+                description,
+                ..
+            } => self.revert_task(task_id, |task| Operation::UpdateTaskDescription {
+                task_id: task_id.clone(),
+                description: task.description.clone(),
+                base: Some(description.clone().unwrap_or_default()),
+                at: Some(now),
+            }),
+            Operation::UpdateTaskDone {
+                task_id,
+                done,
+                with_subtasks,
+                ..
+            } => {
+                if *done && *with_subtasks && self.get_task(task_id).is_some() {
+                    return self
+                        .task_subtree(task_id)
+                        .into_iter()
+                        .flat_map(|task| revert_done(task, now))
+                        .collect();
+                }
+
+                self.editable_task(task_id)
+                    .map(|task| revert_done(task, now))
+                    .unwrap_or_default()
+            }
+            Operation::UpdateTaskScheduled { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::UpdateTaskScheduled {
+                    task_id: task_id.clone(),
+                    scheduled: task.scheduled.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateTaskDeadline { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::UpdateTaskDeadline {
+                    task_id: task_id.clone(),
+                    deadline: task.deadline.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateTaskPriority { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::UpdateTaskPriority {
+                    task_id: task_id.clone(),
+                    priority: task.priority.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateTaskRecurrence { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::UpdateTaskRecurrence {
+                    task_id: task_id.clone(),
+                    recurrence: task.recurrence.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::MoveTask {
+                task_id,
+                project_id_to,
+                ..
+            } => match self.project_of(task_id) {
+                Ok(project) if project.project_id != *project_id_to => {
+                    self.revert_task_position(task_id, now)
+                }
+                _ => vec![],
+            },
+            Operation::ReorderTask { task_id, .. } => {
+                self.revert_task(task_id, |task| Operation::ReorderTask {
+                    task_id: task_id.clone(),
+                    sort_key: task.sort_key.clone(),
+                })
+            }
+            Operation::IndentTask { task_id, .. }
+            | Operation::OutdentTask { task_id, .. }
+            | Operation::SetTaskParent { task_id, .. } => self.revert_task_position(task_id, now),
+            Operation::CreateProject { project, .. } => {
+                let parent_missing = project
+                    .parent_id
+                    .as_ref()
+                    .is_some_and(|parent_id| !self.projects.contains_key(parent_id));
+                // Creating a project with an existing id changes nothing, see [`crate::ids`]
+                if project.project_id == INBOX_ID
+                    || parent_missing
+                    || self.editable_project(&project.project_id).is_some()
+                {
+                    return vec![];
+                }
+
+                vec![Operation::DeleteProject {
+                    project_id: project.project_id.clone(),
+                    mode: DeleteMode::Cascade,
+                    at: Some(now),
+                }]
+            }
+            Operation::DeleteProject {
+                project_id, mode, ..
+            } => self.revert_delete_project(project_id, *mode, now),
+            Operation::MoveProject { project_id, .. } => match self.projects.get(project_id) {
+                Some(project) => vec![Operation::MoveProject {
+                    project_id: project_id.clone(),
+                    parent_id_to: project.parent_id.clone(),
+                    at: Some(now),
+                }],
+                None => vec![],
+            },
+            Operation::RenameProject { project_id, .. } => {
+                self.revert_project(project_id, |project| Operation::RenameProject {
+                    project_id: project_id.clone(),
+                    name: project.name.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateProjectColor { project_id, .. } => {
+                self.revert_project(project_id, |project| Operation::UpdateProjectColor {
+                    project_id: project_id.clone(),
+                    color: project.color.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateProjectIcon { project_id, .. } => {
+                self.revert_project(project_id, |project| Operation::UpdateProjectIcon {
+                    project_id: project_id.clone(),
+                    icon: project.icon.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::UpdateProjectDescription { project_id, .. } => {
+                self.revert_project(project_id, |project| Operation::UpdateProjectDescription {
+                    project_id: project_id.clone(),
+                    description: project.description.clone(),
+                    at: Some(now),
+                })
+            }
+            Operation::ReorderProject { project_id, .. } => {
+                self.revert_project(project_id, |project| Operation::ReorderProject {
+                    project_id: project_id.clone(),
+                    sort_key: project.sort_key.clone(),
+                })
+            }
+            Operation::RestoreTask { task_id } => {
+                match (self.trashed_task(task_id), self.get_task(task_id)) {
+                    // Deleting it again with the original date keeps it on the same purge schedule
+                    (Some(trashed), None) => vec![Operation::DeleteTask {
+                        task_id: task_id.clone(),
+                        at: Some(trashed.deleted_at),
+                    }],
+                    _ => vec![],
+                }
+            }
+            Operation::RestoreProject { project_id } => {
+                match (
+                    self.trashed_project(project_id),
+                    self.projects.contains_key(project_id),
+                ) {
+                    (Some(trashed), false) => vec![Operation::DeleteProject {
+                        project_id: project_id.clone(),
+                        mode: DeleteMode::Cascade,
+                        at: Some(trashed.deleted_at),
+                    }],
+                    _ => vec![],
+                }
+            }
+            Operation::PurgeTrash { .. } => vec![],
+            Operation::CreateLabel { label } => match self.labels.get(&label.label_id) {
+                Some(old) => vec![Operation::CreateLabel { label: old.clone() }],
+                None => vec![Operation::DeleteLabel {
+                    label_id: label.label_id.clone(),
+                }],
+            },
+            Operation::RenameLabel { label_id, .. } => match self.labels.get(label_id) {
+                Some(label) => vec![Operation::RenameLabel {
+                    label_id: label_id.clone(),
+                    name: label.name.clone(),
+                }],
+                None => vec![],
+            },
+            Operation::UpdateLabelColor { label_id, .. } => match self.labels.get(label_id) {
+                Some(label) => vec![Operation::UpdateLabelColor {
+                    label_id: label_id.clone(),
+                    color: label.color.clone(),
+                }],
+                None => vec![],
+            },
+            Operation::DeleteLabel { label_id } => {
+                let Some(label) = self.labels.get(label_id) else {
+                    return vec![];
+                };
+
+                let mut inverse = vec![Operation::CreateLabel {
+                    label: label.clone(),
+                }];
+                inverse.extend(
+                    self.all_editable_tasks()
+                        .into_iter()
+                        .filter(|task| task.labels.contains(label_id))
+                        .map(|task| Operation::AddTaskLabel {
+                            task_id: task.task_id.clone(),
+                            label_id: label_id.clone(),
+                            at: Some(now),
+                        }),
+                );
+                inverse
+            }
+            Operation::AddTaskLabel {
+                task_id, label_id, ..
+            } => match self.editable_task(task_id) {
+                Some(task)
+                    if self.labels.contains_key(label_id) && !task.labels.contains(label_id) =>
+                {
+                    vec![Operation::RemoveTaskLabel {
+                        task_id: task_id.clone(),
+                        label_id: label_id.clone(),
+                        at: Some(now),
+                    }]
+                }
+                _ => vec![],
+            },
+            Operation::RemoveTaskLabel {
+                task_id, label_id, ..
+            } => match self.editable_task(task_id) {
+                Some(task) if task.labels.contains(label_id) => vec![Operation::AddTaskLabel {
+                    task_id: task_id.clone(),
+                    label_id: label_id.clone(),
+                    at: Some(now),
+                }],
+                _ => vec![],
+            },
+        }
+    }
+
+    fn revert_task(
+        &self,
+        task_id: &str,
+        revert: impl FnOnce(&Task) -> Operation,
+    ) -> Vec<Operation> {
+        self.editable_task(task_id)
+            .map(revert)
+            .into_iter()
+            .collect()
+    }
+
+    /// Puts a task back in its current project under its current parent.
+    fn revert_task_position(&self, task_id: &str, now: DateTime<Utc>) -> Vec<Operation> {
+        let (Ok(project), Some(task)) = (self.project_of(task_id), self.get_task(task_id)) else {
+            return vec![];
+        };
+
+        // Only moving between projects detaches a task from its parent, so without one there
+        // the task has to be moved back explicitly
+        let mut inverse = match &task.parent_task_id {
+            Some(_) => vec![Operation::SetTaskParent {
+                task_id: task_id.into(),
+                parent_task_id: task.parent_task_id.clone(),
+                at: Some(now),
+            }],
+            None => vec![
+                Operation::MoveTask {
+                    task_id: task_id.into(),
+                    project_id_to: project.project_id.clone(),
+                    at: Some(now),
+                },
+                Operation::SetTaskParent {
+                    task_id: task_id.into(),
+                    parent_task_id: None,
+                    at: Some(now),
+                },
+            ],
+        };
+
+        // Moving gives the task a place at the end of its new project
+        inverse.push(Operation::ReorderTask {
+            task_id: task_id.into(),
+            sort_key: task.sort_key.clone(),
+        });
+        inverse
+    }
+
+    fn revert_project(
+        &self,
+        project_id: &str,
+        revert: impl FnOnce(&Project) -> Operation,
+    ) -> Vec<Operation> {
+        self.editable_project(project_id)
+            .map(revert)
+            .into_iter()
+            .collect()
+    }
+
+    fn revert_delete_project(
+        &self,
+        project_id: &str,
+        mode: DeleteMode,
+        now: DateTime<Utc>,
+    ) -> Vec<Operation> {
+        let Some(project) = self.projects.get(project_id) else {
+            return vec![];
+        };
+        let children = self.children_of(Some(project_id));
+
+        let refused = mode == DeleteMode::RefuseNonEmpty
+            && (!project.tasks.is_empty() || !children.is_empty());
+        if project_id == INBOX_ID || refused {
+            return vec![];
+        }
+
+        let mut inverse = vec![Operation::RestoreProject {
+            project_id: project_id.into(),
+        }];
+
+        // Cascading keeps the contents inside the trashed project, the other modes move them out
+        if mode != DeleteMode::Cascade {
+            inverse.extend(project.tasks.iter().map(|task| Operation::MoveTask {
+                task_id: task.task_id.clone(),
+                project_id_to: project_id.into(),
+                at: Some(now),
+            }));
+            inverse.extend(children.iter().map(|child| Operation::MoveProject {
+                project_id: child.project_id.clone(),
+                parent_id_to: Some(project_id.into()),
+                at: Some(now),
+            }));
+        }
+
+        inverse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::tests::{project, task, without_updated_at};
+    use crate::{Database, DeleteMode, Operation, Priority, Project};
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("clients", "Clients")
+                },
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("first", "First"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("second", "Second"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("third", "Third"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+        ]);
+        state
+    }
+
+    /// Applies the operation followed by its inverse and checks that nothing changed, apart
+    /// from what ended up in the trash.
+    fn assert_undoes(operation: Operation) {
+        let before = filled();
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 12, 0, 0).unwrap();
+        let inverse = before.inverse_of(&operation, now);
+
+        let mut state = before.clone();
+        state.apply_operation(operation);
+        assert_ne!(state.projects, before.projects);
+
+        state.batch_operations(inverse);
+        assert_eq!(without_updated_at(state).projects, before.projects);
+    }
+
+    #[test]
+    fn inverses_restore_the_previous_state() {
+        assert_undoes(Operation::CreateTask {
+            task: task("new", "New"),
+            project_id: None,
+            at: None,
+        });
+        assert_undoes(Operation::DeleteTask {
+            task_id: "second".into(),
+            at: Some(Utc::now()),
+        });
+        assert_undoes(Operation::UpdateTaskDone {
+            task_id: "first".into(),
+            done: true,
+            at: None,
+            with_subtasks: false,
+            occurrence: None,
+        });
+        assert_undoes(Operation::UpdateTaskDescription {
+            task_id: "first".into(),
+            description: Some("Notes".into()),
+            base: Some("".into()),
+            at: None,
+        });
+        assert_undoes(Operation::UpdateTaskRecurrence {
+            task_id: "first".into(),
+            recurrence: Some("FREQ=DAILY".parse().unwrap()),
+            at: None,
+        });
+        assert_undoes(Operation::UpdateTaskPriority {
+            task_id: "first".into(),
+            priority: Priority::Urgent,
+            at: None,
+        });
+        assert_undoes(Operation::MoveTask {
+            task_id: "third".into(),
+            project_id_to: "clients".into(),
+            at: None,
+        });
+        assert_undoes(Operation::ReorderTask {
+            task_id: "first".into(),
+            sort_key: "0V".into(),
+        });
+        assert_undoes(Operation::IndentTask {
+            task_id: "second".into(),
+            at: None,
+        });
+        assert_undoes(Operation::SetTaskParent {
+            task_id: "first".into(),
+            parent_task_id: Some("third".into()),
+            at: None,
+        });
+        assert_undoes(Operation::MoveProject {
+            project_id: "clients".into(),
+            parent_id_to: None,
+            at: None,
+        });
+        assert_undoes(Operation::RenameProject {
+            project_id: "work".into(),
+            name: "Job".into(),
+            at: None,
+        });
+        assert_undoes(Operation::CreateProject {
+            project: project("new", "New"),
+            at: None,
+        });
+        for mode in [
+            DeleteMode::Cascade,
+            DeleteMode::MoveToInbox,
+            DeleteMode::MoveToParent,
+        ] {
+            assert_undoes(Operation::DeleteProject {
+                project_id: "work".into(),
+                mode,
+                at: Some(Utc::now()),
+            });
+        }
+    }
+
+    #[test]
+    fn redo_is_the_inverse_of_undo() {
+        let now = Utc::now();
+        let mut state = filled();
+        let delete = Operation::DeleteTask {
+            task_id: "first".into(),
+            at: Some(now),
+        };
+
+        let undo = state.inverse_of(&delete, now);
+        state.apply_operation(delete);
+        let after_delete = state.clone();
+
+        let redo: Vec<Operation> = undo
+            .iter()
+            .flat_map(|op| state.inverse_of(op, now))
+            .collect();
+        state.batch_operations(undo);
+        assert_eq!(state, filled());
+
+        state.batch_operations(redo);
+        assert_eq!(state, after_delete);
+    }
+
+    #[test]
+    fn ignored_operations_have_no_inverse() {
+        let state = filled();
+        let now = Utc::now();
+
+        for operation in [
+            Operation::UpdateTaskDone {
+                task_id: "missing".into(),
+                done: true,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
+            },
+            Operation::DeleteProject {
+                project_id: "work".into(),
+                mode: DeleteMode::RefuseNonEmpty,
+                at: Some(now),
+            },
+            Operation::PurgeTrash { before: now },
+            Operation::CreateProject {
+                project: project("work", "Other work"),
+                at: None,
+            },
+        ] {
+            assert!(state.inverse_of(&operation, now).is_empty());
+        }
+    }
+}
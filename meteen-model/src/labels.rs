@@ -0,0 +1,208 @@
+//! Labels like `@phone` or `#waiting`, which group tasks across projects.
+//!
+//! Tasks refer to labels by id. Deleting a label removes it from every task, and labelling a
+//! task with a label that doesn't exist is refused, so concurrent edits never leave a task
+//! with a dangling label.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Database, Task};
+
+#[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
+pub struct Label {
+    pub label_id: String,
+    pub name: String,
+    pub color: Option<String>,
+}
+
+impl Database {
+    /// All labels, sorted by name.
+    pub fn all_labels(&self) -> Vec<&Label> {
+        let mut labels: Vec<&Label> = self.labels.values().collect();
+        labels.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| a.label_id.cmp(&b.label_id))
+        });
+        labels
+    }
+
+    /// The tasks with a label, in no particular order.
+    pub fn tasks_with_label(&self, label_id: &str) -> Vec<&Task> {
+        self.all_tasks()
+            .into_iter()
+            .filter(|task| task.labels.contains(label_id))
+            .collect()
+    }
+
+    /// How many open tasks each label has. Labels without open tasks are included with zero.
+    pub fn label_counts(&self) -> HashMap<&str, usize> {
+        let mut counts: HashMap<&str, usize> = self
+            .labels
+            .keys()
+            .map(|label_id| (label_id.as_str(), 0))
+            .collect();
+
+        for task in self.all_tasks().into_iter().filter(|task| !task.done) {
+            for label_id in &task.labels {
+                if let Some(count) = counts.get_mut(label_id.as_str()) {
+                    *count += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    pub(crate) fn delete_label(&mut self, label_id: &str) {
+        if self.labels.remove(label_id).is_none() {
+            tracing::warn!("Tried to delete non-existant label: {}", label_id);
+            return;
+        }
+
+        for task in self.all_editable_tasks_mut() {
+            task.labels.remove(label_id);
+        }
+    }
+
+    pub(crate) fn update_task_label(&mut self, task_id: &str, label_id: String, add: bool) {
+        if add && !self.labels.contains_key(&label_id) {
+            tracing::warn!("Tried to add non-existant label: {}", label_id);
+            return;
+        }
+
+        match self.editable_task_mut(task_id) {
+            Some(task) if add => {
+                task.labels.insert(label_id);
+            }
+            Some(task) => {
+                task.labels.remove(&label_id);
+            }
+            None => tracing::warn!("Tried to update labels of non-existant task: {}", task_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{task, without_updated_at};
+    use crate::{Database, Label, Operation};
+
+    fn label(label_id: &str, name: &str) -> Operation {
+        Operation::CreateLabel {
+            label: Label {
+                label_id: label_id.into(),
+                name: name.into(),
+                color: None,
+            },
+        }
+    }
+
+    fn labelled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            label("phone", "@phone"),
+            label("waiting", "#waiting"),
+            Operation::CreateTask {
+                task: task("call", "Call mum"),
+                project_id: None,
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("done", "Call bank"),
+                project_id: None,
+                at: None,
+            },
+            Operation::AddTaskLabel {
+                task_id: "call".into(),
+                label_id: "phone".into(),
+                at: None,
+            },
+            Operation::AddTaskLabel {
+                task_id: "done".into(),
+                label_id: "phone".into(),
+                at: None,
+            },
+            Operation::UpdateTaskDone {
+                task_id: "done".into(),
+                done: true,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
+            },
+        ]);
+        state
+    }
+
+    #[test]
+    fn queries() {
+        let state = labelled();
+
+        let names: Vec<&str> = state
+            .all_labels()
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, ["#waiting", "@phone"]);
+        assert_eq!(state.tasks_with_label("phone").len(), 2);
+        assert_eq!(state.label_counts()["phone"], 1);
+        assert_eq!(state.label_counts()["waiting"], 0);
+    }
+
+    #[test]
+    fn delete_removes_label_from_tasks() {
+        let mut state = labelled();
+        state.batch_operations(vec![
+            Operation::DeleteTask {
+                task_id: "done".into(),
+                at: Some(chrono::Utc::now()),
+            },
+            Operation::DeleteLabel {
+                label_id: "phone".into(),
+            },
+        ]);
+
+        assert!(state.get_task("call").unwrap().labels.is_empty());
+        assert!(state.trash.tasks[0].task.labels.is_empty());
+        assert_eq!(state.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn undo_delete_relabels_tasks() {
+        let mut state = labelled();
+        let delete = Operation::DeleteLabel {
+            label_id: "phone".into(),
+        };
+
+        let inverse = state.inverse_of(&delete, chrono::Utc::now());
+        state.apply_operation(delete);
+        state.batch_operations(inverse);
+
+        assert_eq!(without_updated_at(state), labelled());
+    }
+
+    #[test]
+    fn sync_label_and_delete() {
+        let client_a_ops = vec![Operation::DeleteLabel {
+            label_id: "waiting".into(),
+        }];
+        let client_b_ops = vec![Operation::AddTaskLabel {
+            task_id: "call".into(),
+            label_id: "waiting".into(),
+            at: None,
+        }];
+
+        for ops in [
+            [client_a_ops.clone(), client_b_ops.clone()].concat(),
+            [client_b_ops, client_a_ops].concat(),
+        ] {
+            let mut state = labelled();
+            state.batch_operations(ops);
+
+            assert!(!state.labels.contains_key("waiting"));
+            assert_eq!(state.get_task("call").unwrap().labels.len(), 1);
+        }
+    }
+}
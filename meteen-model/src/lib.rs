@@ -1,15 +1,58 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+pub mod filter;
+pub mod format;
+pub mod fsck;
+pub mod hierarchy;
+pub mod ical;
+pub mod ids;
+pub mod import;
+pub mod index;
+pub mod inverse;
+pub mod labels;
+pub mod markdown;
+pub mod merge;
+pub mod order;
+pub mod quickadd;
+pub mod recurrence;
+pub mod subtasks;
+pub mod timestamps;
+pub mod todoist;
+pub mod todotxt;
+pub mod trash;
+
+pub use filter::{Filter, FilterParseError, Sort, SortKey};
+pub use hierarchy::ProjectNode;
+pub use labels::Label;
+pub use recurrence::{Frequency, Recurrence, RecurrenceParseError, RecurrenceRule, WeekdayNum};
+pub use trash::{Trash, TrashedProject, TrashedTask};
+
+/// The project tasks end up in when no project is given. It always exists and can't be deleted.
+pub const INBOX_ID: &str = "inbox";
+
 #[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
 pub struct Project {
     pub name: String,
     pub project_id: String,
     pub parent_id: Option<String>,
     pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Where the project goes among its siblings, see [`order`]
+    #[serde(default)]
+    pub sort_key: String,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
@@ -20,9 +63,32 @@ pub struct Task {
     pub scheduled: Option<DateOrDateTime>,
     pub deadline: Option<DateOrDateTime>,
     pub priority: Priority,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// The task this is a subtask of, which is always in the same project
+    #[serde(default)]
+    pub parent_task_id: Option<String>,
+    /// Where the task goes among its siblings, see [`order`]
+    #[serde(default)]
+    pub sort_key: String,
+    /// The ids of the labels of this task
+    #[serde(default)]
+    pub labels: BTreeSet<String>,
+    /// Notes in Markdown
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Unknown for tasks created by older clients, like the other times
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When the task was last completed. For recurring tasks that is the last occurrence.
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
+/// Ordered from lowest to highest.
+#[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum Priority {
     Low,
     Standard,
@@ -30,44 +96,215 @@ pub enum Priority {
     Urgent,
 }
 
+/// What happens to the contents of a project when it is deleted.
+#[derive(Serialize, Deserialize, Hash, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DeleteMode {
+    /// Delete the subprojects too, together with all tasks in them
+    Cascade,
+    /// Move the tasks to the inbox and the subprojects up to the parent of the deleted project
+    MoveToInbox,
+    /// Move the tasks and the subprojects into the parent of the deleted project. Tasks of a
+    /// top-level project go to the inbox.
+    MoveToParent,
+    /// Only delete the project when it has no tasks and no subprojects. Operations from older
+    /// clients don't carry a mode, so they get this one and can't destroy anything.
+    #[default]
+    RefuseNonEmpty,
+}
+
+/// Operations on tasks and projects carry when they were made in `at`, which the created and
+/// updated times are taken from so every replica records the same. Older clients don't send it,
+/// their operations leave those times alone.
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Operation {
     CreateTask {
         task: Task,
         project_id: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
+    /// Moves the task to the trash. Older clients don't send `at`, the server dates their
+    /// deletions before it applies them, see [`Operation::date_deletion`]. Deletions that reach
+    /// the model undated are ignored, so replicas never read their own clocks.
     DeleteTask {
         task_id: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
     UpdateTaskSummary {
         task_id: String,
         summary: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Sets the notes of a task. With `base`, the text the edit was made against, only the lines
+    /// changed since then are applied, see [`merge`]. Without it the notes are replaced.
+    UpdateTaskDescription {
+        task_id: String,
+        description: Option<String>,
+        #[serde(default)]
+        base: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
+    /// Completing a recurring task moves it on to its next occurrence instead. `at` is when it
+    /// was completed, which tasks repeating after completion count from. With `with_subtasks`
+    /// completing a task completes everything nested in it as well.
+    ///
+    /// `occurrence` is the scheduled date, or else the deadline, of the task being completed.
+    /// Once a recurring task has moved past it completing it does nothing, so two devices
+    /// completing the same occurrence don't skip the next one.
     UpdateTaskDone {
         task_id: String,
         done: bool,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+        #[serde(default)]
+        with_subtasks: bool,
+        #[serde(default)]
+        occurrence: Option<DateOrDateTime>,
     },
     UpdateTaskScheduled {
         task_id: String,
         scheduled: Option<DateOrDateTime>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
     UpdateTaskDeadline {
         task_id: String,
         deadline: Option<DateOrDateTime>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    UpdateTaskPriority {
+        task_id: String,
+        priority: Priority,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
+    UpdateTaskRecurrence {
+        task_id: String,
+        recurrence: Option<Recurrence>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Moves a task and its subtasks. The task is detached from its parent task.
     MoveTask {
         task_id: String,
         project_id_to: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Makes a task the last subtask of the sibling above it.
+    IndentTask {
+        task_id: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Makes a subtask a sibling of its parent.
+    OutdentTask {
+        task_id: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Nests a task under another one, moving it to that task's project if needed, or makes it
+    /// a top-level task.
+    SetTaskParent {
+        task_id: String,
+        parent_task_id: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Gives a task a new place among its siblings, see [`Database::place_task_after`].
+    ReorderTask {
+        task_id: String,
+        sort_key: String,
     },
     CreateProject {
         project: Project,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
     DeleteProject {
         project_id: String,
+        #[serde(default)]
+        mode: DeleteMode,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
     MoveProject {
         project_id: String,
         parent_id_to: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    RenameProject {
+        project_id: String,
+        name: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    UpdateProjectColor {
+        project_id: String,
+        color: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    UpdateProjectIcon {
+        project_id: String,
+        icon: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    UpdateProjectDescription {
+        project_id: String,
+        description: Option<String>,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Gives a project a new place among its siblings, see [`Database::place_project_after`].
+    ReorderProject {
+        project_id: String,
+        sort_key: String,
+    },
+    RestoreTask {
+        task_id: String,
+    },
+    /// Restores a project together with the subprojects and tasks deleted along with it.
+    RestoreProject {
+        project_id: String,
+    },
+    /// Permanently removes everything that was moved to the trash before `before`.
+    PurgeTrash {
+        before: DateTime<Utc>,
+    },
+    /// Creates a label, or replaces the label with the same id.
+    CreateLabel {
+        label: Label,
+    },
+    RenameLabel {
+        label_id: String,
+        name: String,
+    },
+    UpdateLabelColor {
+        label_id: String,
+        color: Option<String>,
+    },
+    /// Deletes a label and removes it from all tasks.
+    DeleteLabel {
+        label_id: String,
+    },
+    AddTaskLabel {
+        task_id: String,
+        label_id: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
+    RemoveTaskLabel {
+        task_id: String,
+        label_id: String,
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
     },
 }
 
@@ -77,9 +314,20 @@ pub enum DateOrDateTime {
     DateTime(DateTime<Utc>),
 }
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "index::StoredDatabase")]
 pub struct Database {
     pub projects: HashMap<String, Project>,
+    #[serde(default)]
+    pub trash: Trash,
+    #[serde(default)]
+    pub labels: HashMap<String, Label>,
+    /// Where each live task is, see [`index`]
+    #[serde(skip)]
+    task_index: HashMap<String, index::TaskLocation>,
+    /// Where each trashed task and project is, see [`index`]
+    #[serde(skip)]
+    trash_index: index::TrashIndex,
 }
 
 #[derive(Debug, Error)]
@@ -97,53 +345,152 @@ pub enum ApplyError {
     NotFound(#[from] NotFoundError),
 }
 
+/// A rule every database has to follow between operations, see [`Database::check_invariants`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InvariantError {
+    #[error("The inbox does not exist")]
+    MissingInbox,
+
+    #[error("The inbox is nested in project {0}")]
+    NestedInbox(String),
+
+    #[error("The project with id {project_id} is stored under id {key}")]
+    MismatchedKey { key: String, project_id: String },
+
+    #[error("The project with id {project_id} has a parent {parent_id} that does not exist")]
+    DanglingParent {
+        project_id: String,
+        parent_id: String,
+    },
+
+    #[error("The project with id {0} is its own ancestor")]
+    Cycle(String),
+
+    #[error(
+        "The task with id {task_id} has a parent task {parent_task_id} that is not in its project"
+    )]
+    DanglingParentTask {
+        task_id: String,
+        parent_task_id: String,
+    },
+
+    #[error("The task with id {0} is its own ancestor")]
+    TaskCycle(String),
+
+    #[error("The task with id {task_id} has a label {label_id} that doesn't exist")]
+    UnknownLabel { task_id: String, label_id: String },
+
+    #[error("There is more than one task with id {0}")]
+    DuplicateTask(String),
+
+    #[error("The task or project with id {0} is not indexed where it is")]
+    StaleIndex(String),
+}
+
 #[derive(Debug, Error)]
 pub enum SyncError {
     #[error("The provided operations are incompatible with the state of the database")]
     CouldNotMerge(#[from] ApplyError),
 }
 
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Database {
     pub fn new() -> Self {
         Self {
             projects: HashMap::from([(
-                "inbox".into(),
+                INBOX_ID.into(),
                 Project {
                     name: "Inbox".into(),
-                    project_id: "inbox".into(),
+                    project_id: INBOX_ID.into(),
                     parent_id: None,
                     tasks: vec![],
+                    color: None,
+                    icon: None,
+                    description: None,
+                    sort_key: String::new(),
+                    created_at: None,
+                    updated_at: None,
                 },
             )]),
+            trash: Trash::default(),
+            labels: HashMap::new(),
+            task_index: HashMap::new(),
+            trash_index: index::TrashIndex::default(),
         }
     }
+    /// Applies an operation, unless it breaks one of the rules of
+    /// [`Database::check_invariants`], then the database is left as it was.
     pub fn apply_operation(&mut self, operation: Operation) {
-        match operation {
-            Operation::CreateTask { task, project_id } => match project_id {
-                Some(project_id) => match self.projects.get_mut(&project_id) {
-                    Some(project) => project.tasks.push(task),
-                    None => {
-                        tracing::warn!(
-                            "Tried to create task in non-existant project: {}",
-                            project_id
-                        );
-                    }
-                },
-                None => self.projects.get_mut("inbox").unwrap().tasks.push(task), // Unwrap is safe because inbox must exist
-            },
-            Operation::DeleteTask { task_id } => {
-                let matches_id = |task: &Task| task.task_id == task_id;
+        self.batch_operations(vec![operation]);
+    }
 
-                for project in self.projects.values_mut() {
-                    if let Some(index) = project.tasks.iter().position(matches_id) {
-                        project.tasks.remove(index);
-                    }
-                }
+    /// Applies operations in order, leaving out those that break one of the rules of
+    /// [`Database::check_invariants`]. Rules that were already broken before don't count
+    /// against an operation, those are up to [`Database::repair`].
+    pub fn batch_operations(&mut self, ops: Vec<Operation>) {
+        // Checking looks at every task, so the batch is checked as a whole and only gone
+        // through again one operation at a time when something in it broke a rule
+        let before = self.clone();
+        for op in ops.iter().cloned() {
+            self.apply_timed(op);
+        }
+        if self.new_violation(&before).is_none() {
+            return;
+        }
+
+        *self = before;
+        for op in ops {
+            let before = self.clone();
+            self.apply_timed(op);
+            if let Some(e) = self.new_violation(&before) {
+                tracing::error!("Rejected operation that breaks the database: {}", e);
+                *self = before;
+            }
+        }
+    }
+
+    /// A rule that is broken now but wasn't in `before`.
+    fn new_violation(&self, before: &Database) -> Option<InvariantError> {
+        let errors = self.check();
+        if errors.is_empty() {
+            return None;
+        }
+        let known = before.check();
+        errors.into_iter().find(|e| !known.contains(e))
+    }
 
-                tracing::warn!("Tried to delete non-existant task: {}", task_id);
+    /// Applies an operation and records its time on what it edited, see [`timestamps`].
+    fn apply_timed(&mut self, operation: Operation) {
+        let edited = operation.edited();
+        let before = edited.as_ref().map(|(edited, _)| self.snapshot(edited));
+
+        self.apply_unchecked(operation);
+
+        if let (Some((edited, at)), Some(before)) = (edited, before) {
+            self.touch(&edited, before, at);
+        }
+    }
+
+    fn apply_unchecked(&mut self, operation: Operation) {
+        match operation {
+            Operation::CreateTask {
+                task, project_id, ..
+            } => {
+                self.create_task(task, project_id);
             }
-            Operation::UpdateTaskSummary { task_id, summary } => {
-                let task = self.get_task_mut(&task_id);
+            Operation::DeleteTask { task_id, at } => match at {
+                Some(at) => self.trash_task(&task_id, at),
+                None => tracing::warn!("Tried to delete task without a time: {}", task_id),
+            },
+            Operation::UpdateTaskSummary {
+                task_id, summary, ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
 
                 if let Some(task) = task {
                     task.summary = summary;
@@ -151,11 +498,64 @@ impl Database {
                     tracing::warn!("Tried to update summary of non-existant task: {}", task_id);
                 }
             }
-            Operation::UpdateTaskDone { task_id, done } => {
-                let task = self.get_task_mut(&task_id);
+            Operation::UpdateTaskDescription {
+                task_id,
+                description,
+                base,
+                ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
+
+                if let Some(task) = task {
+                    task.description = match base {
+                        Some(base) => {
+                            let current = task.description.as_deref().unwrap_or("");
+                            let merged = merge::merge_text(
+                                &base,
+                                current,
+                                description.as_deref().unwrap_or(""),
+                            );
+                            Some(merged).filter(|merged| !merged.is_empty())
+                        }
+                        None => description,
+                    };
+                } else {
+                    tracing::warn!(
+                        "Tried to update description of non-existant task: {}",
+                        task_id
+                    );
+                }
+            }
+            Operation::UpdateTaskDone {
+                task_id,
+                done,
+                at,
+                with_subtasks,
+                occurrence,
+            } => {
+                let completed_already = occurrence.as_ref().is_some_and(|occurrence| {
+                    self.editable_task(&task_id)
+                        .is_some_and(|task| task.has_moved_past(occurrence))
+                });
+                if done && completed_already {
+                    return;
+                }
+
+                if done && with_subtasks && self.get_task(&task_id).is_some() {
+                    self.complete_subtree(&task_id, at);
+                    return;
+                }
+
+                let task = self.editable_task_mut(&task_id);
 
                 if let Some(task) = task {
-                    task.done = done;
+                    // A recurring task stays open and moves on, unless this was its last occurrence
+                    if done {
+                        task.complete(at);
+                    } else {
+                        task.done = false;
+                        task.completed_at = None;
+                    }
                 } else {
                     tracing::warn!(
                         "Tried to update done status of non-existant task: {}",
@@ -163,8 +563,10 @@ impl Database {
                     );
                 }
             }
-            Operation::UpdateTaskScheduled { task_id, scheduled } => {
-                let task = self.get_task_mut(&task_id);
+            Operation::UpdateTaskScheduled {
+                task_id, scheduled, ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
 
                 if let Some(task) = task {
                     task.scheduled = scheduled;
@@ -175,8 +577,10 @@ impl Database {
                     );
                 }
             }
-            Operation::UpdateTaskDeadline { task_id, deadline } => {
-                let task = self.get_task_mut(&task_id);
+            Operation::UpdateTaskDeadline {
+                task_id, deadline, ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
 
                 if let Some(task) = task {
                     task.deadline = deadline;
@@ -184,65 +588,137 @@ impl Database {
                     tracing::warn!("Tried to update deadline of non-existant task: {}", task_id);
                 }
             }
+            Operation::UpdateTaskPriority {
+                task_id, priority, ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
+
+                if let Some(task) = task {
+                    task.priority = priority;
+                } else {
+                    tracing::warn!("Tried to update priority of non-existant task: {}", task_id);
+                }
+            }
+            Operation::UpdateTaskRecurrence {
+                task_id,
+                recurrence,
+                ..
+            } => {
+                let task = self.editable_task_mut(&task_id);
+
+                if let Some(task) = task {
+                    task.recurrence = recurrence;
+                } else {
+                    tracing::warn!(
+                        "Tried to update recurrence of non-existant task: {}",
+                        task_id
+                    );
+                }
+            }
             Operation::MoveTask {
                 task_id,
                 project_id_to,
+                ..
             } => {
                 if !self.projects.contains_key(&project_id_to) {
                     tracing::warn!("Tried to move task to non-existant project: {}", task_id);
                     return;
                 }
+                if self.get_task(&task_id).is_none() {
+                    tracing::warn!("Tried to move non-existant task: {}", task_id);
+                    return;
+                }
 
-                let task = {
-                    let old_project = match self.project_of_mut(&task_id) {
-                        Ok(old_project) => old_project,
-                        Err(_) => {
-                            tracing::warn!("Tried to move non-existant task: {}", task_id);
-                            return;
-                        }
-                    };
-
-                    let index = old_project
-                        .tasks
-                        .iter()
-                        .position(|task| task.task_id == task_id)
-                        .unwrap(); // Unwrap is safe because project_of_mut implies that the project contains this task
+                self.move_task_subtree(&task_id, &project_id_to);
+            }
+            Operation::IndentTask { task_id, .. } => {
+                self.indent_task(&task_id);
+            }
+            Operation::OutdentTask { task_id, .. } => {
+                self.outdent_task(&task_id);
+            }
+            Operation::SetTaskParent {
+                task_id,
+                parent_task_id,
+                ..
+            } => {
+                self.set_task_parent(&task_id, parent_task_id);
+            }
+            Operation::ReorderTask { task_id, sort_key } => {
+                if let Some(task) = self.editable_task_mut(&task_id) {
+                    task.sort_key = sort_key;
+                } else {
+                    tracing::warn!("Tried to reorder non-existant task: {}", task_id);
+                }
+            }
+            Operation::CreateProject { project, .. } => {
+                if project.project_id == INBOX_ID {
+                    tracing::warn!("Tried to replace the inbox");
+                    return;
+                }
+                // The first creation wins, see [`ids`]
+                if self.editable_project(&project.project_id).is_some() {
+                    tracing::warn!("Tried to create existing project: {}", project.project_id);
+                    return;
+                }
 
-                    old_project.tasks.remove(index)
-                };
+                if let Some(ref parent_id) = project.parent_id {
+                    if !self.projects.contains_key(parent_id) {
+                        tracing::warn!(
+                            "Tried to create project in non-existant project: {}",
+                            parent_id
+                        );
+                        return;
+                    }
+                }
 
-                // Unwrap is safe because we check if the project exists at the top of this case
-                let new_project = self.projects.get_mut(&project_id_to).unwrap();
+                // Projects from clients that don't order them go last
+                let mut project = project;
+                if project.sort_key.is_empty() {
+                    project.sort_key = self.next_project_sort_key();
+                }
 
-                new_project.tasks.push(task);
-            }
-            Operation::CreateProject { project } => {
                 let id = project.project_id.clone();
-                self.projects.insert(id, project);
-            }
-            Operation::DeleteProject { project_id } => {
-                match self.projects.remove(&project_id) {
-                    Some(_) => {}
-                    None => {
-                        tracing::warn!("Tried to remove non-existant project: {}", project_id);
-                    }
-                };
+                self.projects.insert(id.clone(), project);
+                self.index_tasks_from(&id, 0);
             }
+            Operation::DeleteProject {
+                project_id,
+                mode,
+                at,
+            } => match at {
+                Some(at) => self.delete_project(&project_id, mode, at),
+                None => tracing::warn!("Tried to delete project without a time: {}", project_id),
+            },
             Operation::MoveProject {
                 project_id,
                 parent_id_to,
+                ..
             } => {
-                match parent_id_to {
-                    Some(ref id) => {
-                        if !self.projects.contains_key(id) {
-                            tracing::warn!(
-                                "Tried to move project into non-existant project: {:?}",
-                                parent_id_to
-                            );
-                            return;
-                        }
+                if let Some(ref id) = parent_id_to {
+                    if !self.projects.contains_key(id) {
+                        tracing::warn!(
+                            "Tried to move project into non-existant project: {:?}",
+                            parent_id_to
+                        );
+                        return;
                     }
-                    None => {}
+                }
+
+                if let Some(ref id) = parent_id_to {
+                    if self.is_within(id, &project_id) {
+                        tracing::warn!(
+                            "Tried to move project {} into its own subproject: {}",
+                            project_id,
+                            id
+                        );
+                        return;
+                    }
+                }
+
+                if project_id == INBOX_ID && parent_id_to.is_some() {
+                    tracing::warn!("Tried to move the inbox into another project");
+                    return;
                 }
 
                 let project = match self.projects.get_mut(&project_id) {
@@ -255,23 +731,258 @@ impl Database {
 
                 project.parent_id = parent_id_to;
             }
+            Operation::RenameProject {
+                project_id, name, ..
+            } => {
+                if let Some(project) = self.editable_project_mut(&project_id) {
+                    project.name = name;
+                } else {
+                    tracing::warn!("Tried to rename non-existant project: {}", project_id);
+                }
+            }
+            Operation::UpdateProjectColor {
+                project_id, color, ..
+            } => {
+                if let Some(project) = self.editable_project_mut(&project_id) {
+                    project.color = color;
+                } else {
+                    tracing::warn!(
+                        "Tried to update color of non-existant project: {}",
+                        project_id
+                    );
+                }
+            }
+            Operation::UpdateProjectIcon {
+                project_id, icon, ..
+            } => {
+                if let Some(project) = self.editable_project_mut(&project_id) {
+                    project.icon = icon;
+                } else {
+                    tracing::warn!(
+                        "Tried to update icon of non-existant project: {}",
+                        project_id
+                    );
+                }
+            }
+            Operation::UpdateProjectDescription {
+                project_id,
+                description,
+                ..
+            } => {
+                if let Some(project) = self.editable_project_mut(&project_id) {
+                    project.description = description;
+                } else {
+                    tracing::warn!(
+                        "Tried to update description of non-existant project: {}",
+                        project_id
+                    );
+                }
+            }
+            Operation::ReorderProject {
+                project_id,
+                sort_key,
+            } => {
+                if let Some(project) = self.editable_project_mut(&project_id) {
+                    project.sort_key = sort_key;
+                } else {
+                    tracing::warn!("Tried to reorder non-existant project: {}", project_id);
+                }
+            }
+            Operation::RestoreTask { task_id } => {
+                self.restore_task(&task_id);
+            }
+            Operation::RestoreProject { project_id } => {
+                self.restore_project(&project_id);
+            }
+            Operation::PurgeTrash { before } => {
+                self.purge_trash(before);
+            }
+            Operation::CreateLabel { label } => {
+                self.labels.insert(label.label_id.clone(), label);
+            }
+            Operation::RenameLabel { label_id, name } => {
+                if let Some(label) = self.labels.get_mut(&label_id) {
+                    label.name = name;
+                } else {
+                    tracing::warn!("Tried to rename non-existant label: {}", label_id);
+                }
+            }
+            Operation::UpdateLabelColor { label_id, color } => {
+                if let Some(label) = self.labels.get_mut(&label_id) {
+                    label.color = color;
+                } else {
+                    tracing::warn!("Tried to update color of non-existant label: {}", label_id);
+                }
+            }
+            Operation::DeleteLabel { label_id } => {
+                self.delete_label(&label_id);
+            }
+            Operation::AddTaskLabel {
+                task_id, label_id, ..
+            } => {
+                self.update_task_label(&task_id, label_id, true);
+            }
+            Operation::RemoveTaskLabel {
+                task_id, label_id, ..
+            } => {
+                self.update_task_label(&task_id, label_id, false);
+            }
         }
     }
 
-    pub fn batch_operations(&mut self, ops: Vec<Operation>) {
-        for op in ops {
-            self.apply_operation(op);
+    fn create_task(&mut self, task: Task, project_id: Option<String>) {
+        // The first creation wins, see [`ids`]
+        if self.editable_task(&task.task_id).is_some() {
+            tracing::warn!("Tried to create existing task: {}", task.task_id);
+            return;
+        }
+
+        // Subtasks go where their parent is
+        let project_id = match &task.parent_task_id {
+            Some(parent_task_id) => match self.project_of(parent_task_id) {
+                Ok(project) => Some(project.project_id.clone()),
+                Err(_) => {
+                    tracing::warn!(
+                        "Tried to create subtask of non-existant task: {}",
+                        parent_task_id
+                    );
+                    return;
+                }
+            },
+            None => project_id,
+        };
+
+        let project = match project_id {
+            Some(project_id) => match self.projects.get_mut(&project_id) {
+                Some(project) => project,
+                None => {
+                    tracing::warn!(
+                        "Tried to create task in non-existant project: {}",
+                        project_id
+                    );
+                    return;
+                }
+            },
+            None => self.projects.get_mut(INBOX_ID).unwrap(), // Unwrap is safe because inbox must exist
+        };
+
+        // Tasks from clients that don't order them go last
+        let mut task = task;
+        // A label may have been deleted while the task was created
+        task.labels
+            .retain(|label_id| self.labels.contains_key(label_id));
+        if task.sort_key.is_empty() {
+            task.sort_key = project.next_sort_key();
         }
+        project.tasks.push(task);
+        let (project_id, position) = (project.project_id.clone(), project.tasks.len() - 1);
+        self.index_tasks_from(&project_id, position);
     }
 
-    pub fn all_tasks<'a>(&'a self) -> Vec<&'a Task> {
+    /// Moves a project to the trash, `mode` decides whether its contents go along with it.
+    fn delete_project(&mut self, project_id: &str, mode: DeleteMode, at: DateTime<Utc>) {
+        if project_id == INBOX_ID {
+            tracing::warn!("Tried to delete the inbox");
+            return;
+        }
+
+        let (parent_id, has_tasks) = match self.projects.get(project_id) {
+            Some(project) => (project.parent_id.clone(), !project.tasks.is_empty()),
+            None => {
+                tracing::warn!("Tried to remove non-existant project: {}", project_id);
+                return;
+            }
+        };
+        let children: Vec<String> = self
+            .projects
+            .values()
+            .filter(|project| project.parent_id.as_deref() == Some(project_id))
+            .map(|project| project.project_id.clone())
+            .collect();
+
+        match mode {
+            DeleteMode::RefuseNonEmpty if has_tasks || !children.is_empty() => {
+                tracing::warn!("Tried to delete non-empty project: {}", project_id);
+            }
+            DeleteMode::RefuseNonEmpty => {
+                // Unwrap is safe because we looked the project up above
+                let project = self.projects.remove(project_id).unwrap();
+                self.push_trashed_project(TrashedProject {
+                    projects: vec![project],
+                    deleted_at: at,
+                });
+            }
+            DeleteMode::Cascade => {
+                let mut ids = vec![project_id.to_string()];
+                ids.extend(
+                    self.descendants_of(project_id)
+                        .into_iter()
+                        .map(|project| project.project_id.clone()),
+                );
+                let projects: Vec<Project> = ids
+                    .iter()
+                    .filter_map(|id| self.projects.remove(id))
+                    .collect();
+                for project in &projects {
+                    self.unindex_tasks(&project.tasks);
+                }
+                self.push_trashed_project(TrashedProject {
+                    projects,
+                    deleted_at: at,
+                });
+            }
+            DeleteMode::MoveToInbox | DeleteMode::MoveToParent => {
+                // Unwrap is safe because we looked the project up above
+                let mut project = self.projects.remove(project_id).unwrap();
+                let tasks = std::mem::take(&mut project.tasks);
+                self.push_trashed_project(TrashedProject {
+                    projects: vec![project],
+                    deleted_at: at,
+                });
+
+                let tasks_to = match (mode, &parent_id) {
+                    (DeleteMode::MoveToParent, Some(parent_id))
+                        if self.projects.contains_key(parent_id) =>
+                    {
+                        parent_id.as_str()
+                    }
+                    _ => INBOX_ID,
+                };
+                let tasks_to = tasks_to.to_string();
+                // Unwrap is safe because the tasks go to a project that exists or the inbox
+                let project = self.projects.get_mut(&tasks_to).unwrap();
+                let start = project.tasks.len();
+                project.tasks.extend(tasks);
+                self.index_tasks_from(&tasks_to, start);
+
+                for id in children {
+                    // Unwrap is safe because the children were collected from the projects
+                    self.projects.get_mut(&id).unwrap().parent_id = parent_id.clone();
+                }
+            }
+        }
+    }
+
+    /// Checks the rules that keep the project structure usable: the inbox exists at the top
+    /// level, every project is stored under its own id, every parent exists and no project is its
+    /// own ancestor. The same goes for subtasks, whose parent has to be in the same project.
+    /// Tasks have unique ids and only have labels that exist. Returns the first rule broken, see
+    /// [`Database::check`] for all of them.
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        match self.check().into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn all_tasks(&self) -> Vec<&Task> {
         self.projects
             .values()
             .flat_map(|proj| &proj.tasks)
             .collect()
     }
 
-    pub fn all_tasks_mut<'a>(&'a mut self) -> Vec<&'a mut Task> {
+    pub fn all_tasks_mut(&mut self) -> Vec<&mut Task> {
         self.projects
             .values_mut()
             .flat_map(|proj| &mut proj.tasks)
@@ -279,53 +990,308 @@ impl Database {
     }
 
     pub fn get_task<'a>(&'a self, task_id: &str) -> Option<&'a Task> {
-        self.all_tasks()
-            .into_iter()
-            .find(|task| task.task_id == task_id)
+        let location = self.task_index.get(task_id)?;
+        self.projects
+            .get(&location.project_id)?
+            .tasks
+            .get(location.position)
+            .filter(|task| task.task_id == task_id)
     }
 
     pub fn get_task_mut<'a>(&'a mut self, task_id: &str) -> Option<&'a mut Task> {
-        self.all_tasks_mut()
-            .into_iter()
-            .find(|task| task.task_id == task_id)
+        let location = self.task_index.get(task_id)?;
+        self.projects
+            .get_mut(&location.project_id)?
+            .tasks
+            .get_mut(location.position)
+            .filter(|task| task.task_id == task_id)
     }
 
     pub fn project_of(&self, task_id: &str) -> Result<&Project, NotFoundError> {
-        for project in self.projects.values() {
-            if project
-                .tasks
-                .iter()
-                .find(|task| task.task_id == task_id)
-                .is_some()
-            {
-                return Ok(project);
-            }
-        }
-
-        return Err(NotFoundError::NoSuchTask(task_id.into())); // Every task must be in a project
+        self.task_index
+            .get(task_id)
+            .and_then(|location| self.projects.get(&location.project_id))
+            .ok_or_else(|| NotFoundError::NoSuchTask(task_id.into())) // Every task must be in a project
     }
 
     pub fn project_of_mut(&mut self, task_id: &str) -> Result<&mut Project, NotFoundError> {
-        for project in self.projects.values_mut() {
-            if project
-                .tasks
-                .iter()
-                .find(|task| task.task_id == task_id)
-                .is_some()
-            {
-                return Ok(project);
-            }
-        }
-
-        return Err(NotFoundError::NoSuchTask(task_id.into())); // Every task must be in a project
+        self.task_index
+            .get(task_id)
+            .and_then(|location| self.projects.get_mut(&location.project_id))
+            .ok_or_else(|| NotFoundError::NoSuchTask(task_id.into())) // Every task must be in a project
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::BTreeSet;
+
+    use chrono::{NaiveDate, Utc};
+
+    use crate::{
+        Database, DateOrDateTime, DeleteMode, Operation, Priority, Project, Task, INBOX_ID,
+    };
 
-    use crate::{Database, Operation, Priority, Project, Task};
+    pub(crate) fn project(project_id: &str, name: &str) -> Project {
+        Project {
+            name: name.into(),
+            project_id: project_id.into(),
+            parent_id: None,
+            tasks: vec![],
+            color: None,
+            icon: None,
+            description: None,
+            sort_key: String::new(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub(crate) fn task(task_id: &str, summary: &str) -> Task {
+        Task {
+            task_id: task_id.into(),
+            summary: summary.into(),
+            done: false,
+            scheduled: None,
+            deadline: None,
+            priority: Priority::Standard,
+            recurrence: None,
+            parent_task_id: None,
+            sort_key: String::new(),
+            labels: BTreeSet::new(),
+            description: None,
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+        }
+    }
+
+    /// The state without the updated times, which undoing an edit moves on rather than back.
+    pub(crate) fn without_updated_at(mut state: Database) -> Database {
+        for project in state.projects.values_mut() {
+            project.updated_at = None;
+        }
+        for task in state.all_editable_tasks_mut() {
+            task.updated_at = None;
+        }
+        state
+    }
+
+    /// Applies the offline operations of two clients to the server and to each other, in
+    /// different orders, and checks that everyone ends up with the same state.
+    fn assert_converges(
+        initial: Database,
+        client_a_ops: Vec<Operation>,
+        client_b_ops: Vec<Operation>,
+    ) -> Database {
+        let mut server_state = initial.clone();
+        let mut client_a_state = initial.clone();
+        let mut client_b_state = initial;
+
+        client_a_state.batch_operations(client_a_ops.clone());
+        client_b_state.batch_operations(client_b_ops.clone());
+
+        server_state.batch_operations(client_a_ops.clone());
+        server_state.batch_operations(client_b_ops.clone());
+        client_a_state.batch_operations(client_b_ops);
+        client_b_state.batch_operations(client_a_ops);
+
+        assert_eq!(server_state, client_a_state);
+        assert_eq!(server_state, client_b_state);
+        assert_eq!(server_state.check_invariants(), Ok(()));
+
+        server_state
+    }
+
+    /// A project `parent` with a task, containing `child` with a task, containing the empty
+    /// project `grandchild`.
+    fn nested_projects() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("parent", "Parent"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("parent".into()),
+                    ..project("child", "Child")
+                },
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("child".into()),
+                    ..project("grandchild", "Grandchild")
+                },
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("parent_task", "In parent"),
+                project_id: Some("parent".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("child_task", "In child"),
+                project_id: Some("child".into()),
+                at: None,
+            },
+        ]);
+        state
+    }
+
+    fn delete(project_id: &str, mode: DeleteMode) -> Operation {
+        Operation::DeleteProject {
+            project_id: project_id.into(),
+            mode,
+            at: Some(Utc::now()),
+        }
+    }
+
+    #[test]
+    pub fn delete_project_modes() {
+        let mut cascade = nested_projects();
+        cascade.apply_operation(delete("parent", DeleteMode::Cascade));
+        assert_eq!(cascade.projects, Database::new().projects);
+
+        let mut to_inbox = nested_projects();
+        to_inbox.apply_operation(delete("child", DeleteMode::MoveToInbox));
+        assert_eq!(
+            to_inbox.project_of("child_task").unwrap().project_id,
+            INBOX_ID
+        );
+        assert_eq!(
+            to_inbox.projects["grandchild"].parent_id.as_deref(),
+            Some("parent")
+        );
+
+        let mut to_parent = nested_projects();
+        to_parent.apply_operation(delete("child", DeleteMode::MoveToParent));
+        assert_eq!(
+            to_parent.project_of("child_task").unwrap().project_id,
+            "parent"
+        );
+        assert_eq!(
+            to_parent.projects["grandchild"].parent_id.as_deref(),
+            Some("parent")
+        );
+
+        // Top-level projects hand their tasks to the inbox
+        to_parent.apply_operation(delete("parent", DeleteMode::MoveToParent));
+        assert_eq!(
+            to_parent.project_of("parent_task").unwrap().project_id,
+            INBOX_ID
+        );
+        assert_eq!(to_parent.projects["grandchild"].parent_id, None);
+
+        for state in [&to_inbox, &to_parent] {
+            assert!(!state.projects.contains_key("child"));
+            assert_eq!(state.all_tasks().len(), 2);
+            assert_eq!(state.check_invariants(), Ok(()));
+        }
+    }
+
+    #[test]
+    pub fn delete_non_empty_project_is_refused() {
+        let mut state = nested_projects();
+        state.batch_operations(vec![
+            delete("parent", DeleteMode::RefuseNonEmpty),
+            delete("child", DeleteMode::RefuseNonEmpty),
+        ]);
+        assert_eq!(state, nested_projects());
+
+        state.apply_operation(delete("grandchild", DeleteMode::RefuseNonEmpty));
+        assert!(!state.projects.contains_key("grandchild"));
+        assert_eq!(state.trash.projects[0].project().project_id, "grandchild");
+    }
+
+    #[test]
+    pub fn operations_breaking_invariants_are_rejected() {
+        let mut state = Database::new();
+        let create = |task_id: &str| Operation::CreateTask {
+            task: task(task_id, "Task"),
+            project_id: None,
+            at: None,
+        };
+        state.apply_operation(create("report"));
+
+        // A project carrying a task that exists already
+        let duplicate = Operation::CreateProject {
+            project: Project {
+                tasks: vec![task("report", "Copy")],
+                ..project("work", "Work")
+            },
+            at: None,
+        };
+        state.batch_operations(vec![create("call"), duplicate, create("email")]);
+
+        assert!(!state.projects.contains_key("work"));
+        assert_eq!(state.all_tasks().len(), 3);
+        assert_eq!(state.get_task("report").unwrap().summary, "Task");
+        assert_eq!(state.check_invariants(), Ok(()));
+
+        // Rules that were broken before don't stop other operations
+        state.projects.get_mut(INBOX_ID).unwrap().parent_id = Some("gone".into());
+        state.apply_operation(create("invoice"));
+        assert!(state.get_task("invoice").is_some());
+    }
+
+    #[test]
+    pub fn inbox_is_protected() {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            delete(INBOX_ID, DeleteMode::Cascade),
+            delete(INBOX_ID, DeleteMode::MoveToInbox),
+            delete(INBOX_ID, DeleteMode::RefuseNonEmpty),
+            Operation::CreateProject {
+                project: project(INBOX_ID, "Not the inbox"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: project("other", "Other"),
+                at: None,
+            },
+            Operation::MoveProject {
+                project_id: INBOX_ID.into(),
+                parent_id_to: Some("other".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("mytask", "Still works"),
+                project_id: None,
+                at: None,
+            },
+        ]);
+
+        assert_eq!(state.projects[INBOX_ID].name, "Inbox");
+        assert_eq!(state.projects[INBOX_ID].parent_id, None);
+        assert_eq!(state.project_of("mytask").unwrap().project_id, INBOX_ID);
+        assert_eq!(state.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    pub fn sync_delete_project() {
+        let client_a_ops = vec![delete("parent", DeleteMode::Cascade)];
+        let client_b_ops = vec![
+            Operation::RenameProject {
+                project_id: "child".into(),
+                name: "Renamed".into(),
+                at: None,
+            },
+            Operation::UpdateTaskDone {
+                task_id: "child_task".into(),
+                done: true,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
+            },
+        ];
+
+        let state = assert_converges(nested_projects(), client_a_ops, client_b_ops);
+
+        assert_eq!(state.projects, Database::new().projects);
+        assert_eq!(state.trash.projects[0].projects.len(), 3);
+    }
 
     #[test]
     pub fn sync() {
@@ -337,7 +1303,14 @@ mod tests {
                     project_id: "myproj".into(),
                     parent_id: None,
                     tasks: vec![],
+                    color: None,
+                    icon: None,
+                    description: None,
+                    sort_key: String::new(),
+                    created_at: None,
+                    updated_at: None,
                 },
+                at: None,
             },
             Operation::CreateTask {
                 task: Task {
@@ -347,8 +1320,17 @@ mod tests {
                     scheduled: None,
                     deadline: None,
                     priority: Priority::Standard,
+                    recurrence: None,
+                    parent_task_id: None,
+                    sort_key: String::new(),
+                    labels: BTreeSet::new(),
+                    description: None,
+                    created_at: None,
+                    updated_at: None,
+                    completed_at: None,
                 },
                 project_id: Some("myproj".into()),
+                at: None,
             },
             Operation::CreateTask {
                 task: Task {
@@ -358,8 +1340,17 @@ mod tests {
                     scheduled: None,
                     deadline: None,
                     priority: Priority::Standard,
+                    recurrence: None,
+                    parent_task_id: None,
+                    sort_key: String::new(),
+                    labels: BTreeSet::new(),
+                    description: None,
+                    created_at: None,
+                    updated_at: None,
+                    completed_at: None,
                 },
                 project_id: Some("myproj".into()),
+                at: None,
             },
         ]);
 
@@ -369,16 +1360,23 @@ mod tests {
         let client_a_ops = Vec::from([Operation::UpdateTaskDone {
             task_id: "mytask".into(),
             done: true,
+            at: None,
+            with_subtasks: false,
+            occurrence: None,
         }]);
 
         let client_b_ops = Vec::from([
             Operation::UpdateTaskDone {
                 task_id: "mysecondtask".into(),
                 done: false,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
             },
             Operation::UpdateTaskSummary {
                 task_id: "mytask".into(),
                 summary: "The first task".into(),
+                at: None,
             },
         ]);
 
@@ -396,4 +1394,269 @@ mod tests {
         assert_eq!(server_state, client_a_state);
         assert_eq!(server_state, client_b_state);
     }
+
+    #[test]
+    pub fn sync_edits() {
+        let mut initial = Database::new();
+        initial.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("myproj", "My Project"),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("mytask", "My Epic Task"),
+                project_id: Some("myproj".into()),
+                at: None,
+            },
+        ]);
+
+        let client_a_ops = vec![
+            Operation::UpdateTaskPriority {
+                task_id: "mytask".into(),
+                priority: Priority::Urgent,
+                at: None,
+            },
+            Operation::RenameProject {
+                project_id: "myproj".into(),
+                name: "Renamed".into(),
+                at: None,
+            },
+        ];
+
+        let client_b_ops = vec![
+            Operation::UpdateProjectColor {
+                project_id: "myproj".into(),
+                color: Some("#ff0000".into()),
+                at: None,
+            },
+            Operation::UpdateProjectIcon {
+                project_id: "myproj".into(),
+                icon: Some("star".into()),
+                at: None,
+            },
+            Operation::UpdateProjectDescription {
+                project_id: "myproj".into(),
+                description: Some("Things to do".into()),
+                at: None,
+            },
+        ];
+
+        let state = assert_converges(initial, client_a_ops, client_b_ops);
+
+        let project = &state.projects["myproj"];
+        assert_eq!(project.name, "Renamed");
+        assert_eq!(project.color.as_deref(), Some("#ff0000"));
+        assert_eq!(project.icon.as_deref(), Some("star"));
+        assert_eq!(project.description.as_deref(), Some("Things to do"));
+        assert_eq!(project.tasks.len(), 1);
+        assert_eq!(state.get_task("mytask").unwrap().priority, Priority::Urgent);
+    }
+
+    #[test]
+    pub fn complete_recurring_task() {
+        let mut initial = Database::new();
+        initial.apply_operation(Operation::CreateTask {
+            task: Task {
+                scheduled: Some(DateOrDateTime::Date(
+                    NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+                )),
+                recurrence: Some("FREQ=WEEKLY;COUNT=2".parse().unwrap()),
+                ..task("mytask", "Weekly review")
+            },
+            project_id: None,
+            at: None,
+        });
+
+        let client_a_ops = vec![Operation::UpdateTaskDone {
+            task_id: "mytask".into(),
+            done: true,
+            at: Some(Utc::now()),
+            with_subtasks: false,
+            occurrence: None,
+        }];
+        let client_b_ops = vec![Operation::UpdateTaskSummary {
+            task_id: "mytask".into(),
+            summary: "Review the week".into(),
+            at: None,
+        }];
+
+        let mut state = assert_converges(initial, client_a_ops, client_b_ops);
+
+        let task = state.get_task("mytask").unwrap();
+        assert!(!task.done);
+        assert_eq!(
+            task.scheduled,
+            Some(DateOrDateTime::Date(
+                NaiveDate::from_ymd_opt(2024, 10, 8).unwrap()
+            ))
+        );
+
+        // The second completion was the last occurrence
+        state.apply_operation(Operation::UpdateTaskDone {
+            task_id: "mytask".into(),
+            done: true,
+            at: None,
+            with_subtasks: false,
+            occurrence: None,
+        });
+        assert!(state.get_task("mytask").unwrap().done);
+    }
+
+    #[test]
+    pub fn complete_occurrence_on_two_devices() {
+        let october = |day| DateOrDateTime::Date(NaiveDate::from_ymd_opt(2024, 10, day).unwrap());
+        let mut initial = Database::new();
+        initial.apply_operation(Operation::CreateTask {
+            task: Task {
+                scheduled: Some(october(1)),
+                recurrence: Some("FREQ=WEEKLY".parse().unwrap()),
+                ..task("mytask", "Weekly review")
+            },
+            project_id: None,
+            at: None,
+        });
+
+        let at = Utc::now();
+        let complete = || Operation::UpdateTaskDone {
+            task_id: "mytask".into(),
+            done: true,
+            at: Some(at),
+            with_subtasks: false,
+            occurrence: Some(october(1)),
+        };
+
+        let state = assert_converges(initial, vec![complete()], vec![complete()]);
+
+        // Both completed the first, so the task moves on once
+        assert_eq!(
+            state.get_task("mytask").unwrap().scheduled,
+            Some(october(8))
+        );
+    }
+
+    #[test]
+    pub fn sync_description_edits() {
+        let base = "Phone: 0612345678\n- [ ] passport";
+        let mut server_state = Database::new();
+        server_state.batch_operations(vec![
+            Operation::CreateTask {
+                task: task("mytask", "Renew passport"),
+                project_id: None,
+                at: None,
+            },
+            Operation::UpdateTaskDescription {
+                task_id: "mytask".into(),
+                description: Some(base.into()),
+                base: None,
+                at: None,
+            },
+        ]);
+
+        let client_a_ops = vec![Operation::UpdateTaskDescription {
+            task_id: "mytask".into(),
+            description: Some("Phone: 0687654321\n- [ ] passport".into()),
+            base: Some(base.into()),
+            at: None,
+        }];
+        let client_b_ops = vec![Operation::UpdateTaskDescription {
+            task_id: "mytask".into(),
+            description: Some("Phone: 0612345678\n- [x] passport\n- [ ] photo".into()),
+            base: Some(base.into()),
+            at: None,
+        }];
+
+        for ops in [
+            [client_a_ops.clone(), client_b_ops.clone()].concat(),
+            [client_b_ops, client_a_ops].concat(),
+        ] {
+            let mut state = server_state.clone();
+            state.batch_operations(ops);
+            assert_eq!(
+                state.get_task("mytask").unwrap().description.as_deref(),
+                Some("Phone: 0687654321\n- [x] passport\n- [ ] photo")
+            );
+        }
+    }
+
+    #[test]
+    pub fn edits_of_missing_items_are_ignored() {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::UpdateTaskPriority {
+                task_id: "missing".into(),
+                priority: Priority::High,
+                at: None,
+            },
+            Operation::RenameProject {
+                project_id: "missing".into(),
+                name: "Renamed".into(),
+                at: None,
+            },
+            Operation::UpdateProjectColor {
+                project_id: "missing".into(),
+                color: None,
+                at: None,
+            },
+        ]);
+
+        assert_eq!(state, Database::new());
+    }
+
+    #[test]
+    pub fn duplicate_creations_are_ignored() {
+        let create = Operation::CreateTask {
+            task: task("report", "Write report"),
+            project_id: None,
+            at: None,
+        };
+        let client_a_ops = vec![
+            create.clone(),
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+        ];
+        // A retry of the same creation, and a project that happens to get the same id
+        let client_b_ops = vec![
+            create.clone(),
+            Operation::CreateProject {
+                project: project("work", "Job"),
+                at: None,
+            },
+        ];
+
+        for ops in [
+            [client_a_ops.clone(), client_b_ops.clone()].concat(),
+            [client_b_ops, client_a_ops].concat(),
+        ] {
+            let mut state = Database::new();
+            state.batch_operations(ops);
+
+            assert_eq!(state.all_tasks().len(), 1);
+            assert_eq!(state.projects.len(), 2);
+            assert_eq!(state.check_invariants(), Ok(()));
+        }
+
+        // The first creation wins, also over a trashed task
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            create.clone(),
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: project("work", "Job"),
+                at: None,
+            },
+            Operation::DeleteTask {
+                task_id: "report".into(),
+                at: Some(Utc::now()),
+            },
+            create,
+        ]);
+        assert_eq!(state.projects["work"].name, "Work");
+        assert!(state.get_task("report").is_none());
+        assert_eq!(state.trash.tasks.len(), 1);
+    }
 }
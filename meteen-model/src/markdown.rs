@@ -0,0 +1,218 @@
+//! Markdown checklists, with a heading per project and subtasks nested in their parent:
+//!
+//! ```markdown
+//! # Work
+//!
+//! ## Clients
+//!
+//! - [ ] (A) Send invoice @money due:2024-10-05 <!-- id:01J9... -->
+//!   - [x] Check hours <!-- id:01J9... -->
+//! ```
+//!
+//! The details of a task are written in the notation of [`todotxt`](crate::todotxt), and its
+//! id in a comment that isn't shown when the Markdown is rendered. Projects nested deeper than
+//! headings go are written at the deepest heading as a path, like `###### Deep / Deeper`.
+//! Everything that isn't a heading or a checklist item is ignored on import.
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    ids,
+    import::{self, ImportedTask, Importer},
+    todotxt, Database, Operation,
+};
+
+/// The deepest heading Markdown has.
+const MAX_LEVEL: usize = 6;
+
+/// Writes every project as a heading with its tasks as a checklist, in the order of the sidebar.
+pub fn export(db: &Database) -> String {
+    let mut sections = vec![];
+
+    let mut nodes: Vec<_> = db.project_tree().into_iter().rev().collect();
+    while let Some(node) = nodes.pop() {
+        nodes.extend(node.children.into_iter().rev());
+        let project = node.project;
+
+        let path = db.project_path(&project.project_id);
+        let level = path.len().min(MAX_LEVEL);
+        let mut section = format!("{} {}\n", "#".repeat(level), path[level - 1..].join(" / "));
+
+        let tasks = todotxt::nested_tasks(project);
+        if !tasks.is_empty() {
+            section.push('\n');
+        }
+        for (depth, task) in tasks {
+            section.push_str(&"  ".repeat(depth));
+            section.push_str(if task.done { "- [x] " } else { "- [ ] " });
+            if let Some(letter) = todotxt::priority_letter(&task.priority) {
+                section.push_str(&format!("({}) ", letter));
+            }
+            todotxt::write_details(&mut section, db, task);
+            section.push_str(&format!(" <!-- id:{} -->\n", task.task_id));
+        }
+
+        sections.push(section);
+    }
+
+    sections.join("\n")
+}
+
+/// The operations that make `db` match a Markdown checklist: tasks with an id that exists are
+/// updated, the others are created. Tasks before the first heading go to the inbox, and a task
+/// with a `+project` tag goes there rather than to its heading. Tasks that aren't in the file
+/// are left alone. `now` is when the changes are made.
+pub fn import(db: &Database, text: &str, now: DateTime<Utc>) -> Vec<Operation> {
+    let mut importer = Importer::new(db, now);
+    let mut path: Vec<String> = vec![];
+    // The indent and id of the items that later items can be nested in
+    let mut parents: Vec<(usize, String)> = vec![];
+    let mut tasks = vec![];
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=MAX_LEVEL).contains(&level) && trimmed[level..].starts_with(' ') {
+            let names = trimmed[level..].trim();
+            path.truncate(level - 1);
+            match level {
+                MAX_LEVEL => path.extend(names.split(" / ").map(Into::into)),
+                _ => path.push(names.into()),
+            }
+            parents.clear();
+            continue;
+        }
+
+        let (done, rest) = match trimmed.get(..6) {
+            Some("- [ ] " | "* [ ] ") => (false, &trimmed[6..]),
+            Some("- [x] " | "- [X] " | "* [x] " | "* [X] ") => (true, &trimmed[6..]),
+            _ => continue,
+        };
+
+        let mut task = import::new_task(String::new());
+        task.done = done;
+        let mut rest = rest.trim();
+        if let Some((details, comment)) = rest.rsplit_once("<!--") {
+            if let Some(task_id) = comment
+                .trim()
+                .strip_suffix("-->")
+                .and_then(|comment| comment.trim().strip_prefix("id:"))
+            {
+                task.task_id = task_id.trim().into();
+                rest = details.trim_end();
+            }
+        }
+        if let Some((priority, after)) = todotxt::leading_priority(rest) {
+            task.priority = priority;
+            rest = after;
+        }
+        let project_tag = todotxt::parse_details(&mut importer, rest, &mut task);
+        if task.task_id.is_empty() {
+            task.task_id = ids::new_id();
+        }
+
+        let indent = line.len() - trimmed.len();
+        while parents
+            .last()
+            .is_some_and(|(parent_indent, _)| *parent_indent >= indent)
+        {
+            parents.pop();
+        }
+        task.parent_task_id = parents.last().map(|(_, parent_id)| parent_id.clone());
+        parents.push((indent, task.task_id.clone()));
+
+        let project_path = match project_tag {
+            Some(tag) => Some(importer.tag_path(&tag)),
+            None => Some(path.clone()).filter(|path| !path.is_empty()),
+        };
+        tasks.push(ImportedTask { task, project_path });
+    }
+
+    importer.create_or_update_tasks(db, tasks);
+    importer.finish().operations
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::tests::{project, task};
+    use crate::{Database, Operation, Priority, Project, Task};
+
+    #[test]
+    pub fn round_trip() {
+        let mut db = Database::new();
+        db.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("clients", "Clients")
+                },
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    priority: Priority::Urgent,
+                    ..task("invoice", "Send invoice")
+                },
+                project_id: Some("clients".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    done: true,
+                    parent_task_id: Some("invoice".into()),
+                    ..task("hours", "Check hours")
+                },
+                project_id: Some("clients".into()),
+                at: None,
+            },
+        ]);
+        let now = Utc.with_ymd_and_hms(2024, 10, 3, 0, 0, 0).unwrap();
+
+        let text = super::export(&db);
+        assert_eq!(
+            text,
+            "# Inbox\n\
+             \n\
+             # Work\n\
+             \n\
+             ## Clients\n\
+             \n\
+             - [ ] (A) Send invoice <!-- id:invoice -->\n  \
+               - [x] Check hours <!-- id:hours -->\n"
+        );
+        assert!(super::import(&db, &text, now).is_empty());
+
+        let text = text.replace("- [x] Check", "- [ ] Check") + "- [ ] Call the bank\n";
+        db.batch_operations(super::import(&db, &text, now));
+
+        assert!(!db.get_task("hours").unwrap().done);
+        assert_eq!(
+            db.get_task("hours").unwrap().parent_task_id.as_deref(),
+            Some("invoice")
+        );
+        let call_id = db
+            .all_tasks()
+            .into_iter()
+            .find(|task| task.summary == "Call the bank")
+            .unwrap()
+            .task_id
+            .clone();
+        assert_eq!(db.project_of(&call_id).unwrap().project_id, "clients");
+        assert_eq!(db.all_tasks().len(), 3);
+
+        // Nesting it under the invoice makes it a subtask
+        let text = super::export(&db).replace("- [ ] Call the bank", "  - [ ] Call the bank");
+        db.batch_operations(super::import(&db, &text, now));
+        assert_eq!(
+            db.get_task(&call_id).unwrap().parent_task_id.as_deref(),
+            Some("invoice")
+        );
+    }
+}
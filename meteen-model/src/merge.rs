@@ -0,0 +1,278 @@
+//! Line-based three-way merging of text, so edits to a task description made on different
+//! devices don't overwrite each other.
+//!
+//! An edit carries the text it was made against. The lines it changed compared to that base are
+//! applied to the current text, keeping whatever other devices changed in the meantime. When
+//! both sides changed the same lines, both versions are kept, the current one first, so nothing
+//! typed is ever lost.
+
+use std::collections::HashMap;
+
+/// A run of base lines, `start..end`, replaced by `lines`.
+#[derive(Debug)]
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+}
+
+/// Splits on newlines so joining the lines with newlines gives back the exact text.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split('\n').collect()
+}
+
+/// The pairs of indices of a longest common subsequence of `a` and `b`.
+fn common_lines(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    // Comparing numbers is cheaper than comparing lines
+    let mut ids = HashMap::new();
+    let mut id = |line| {
+        let next = ids.len();
+        *ids.entry(line).or_insert(next)
+    };
+    let a: Vec<usize> = a.iter().map(|line| id(*line)).collect();
+    let b: Vec<usize> = b.iter().map(|line| id(*line)).collect();
+
+    let mut pairs = vec![];
+    common_subsequence(&a, &b, (0, 0), &mut pairs);
+    pairs
+}
+
+/// Hirschberg's algorithm, which only needs memory for a single row of the table of lengths:
+/// `a` is split in half, and `b` where the common subsequences of both halves together are
+/// longest. The pairs are pushed in order, offset by where `a` and `b` start.
+fn common_subsequence(
+    a: &[usize],
+    b: &[usize],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    // Lines the texts start and end with are always common, and most edits touch few lines
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_rest, b_rest) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (i, j) = (offset.0 + prefix, offset.1 + prefix);
+
+    pairs.extend((0..prefix).map(|k| (offset.0 + k, offset.1 + k)));
+    match a_rest {
+        [] => {}
+        [line] => {
+            if let Some(k) = b_rest.iter().position(|other| other == line) {
+                pairs.push((i, j + k));
+            }
+        }
+        _ if b_rest.is_empty() => {}
+        _ => {
+            let half = a_rest.len() / 2;
+            let forward = last_row(a_rest[..half].iter(), b_rest.iter());
+            let backward = last_row(a_rest[half..].iter().rev(), b_rest.iter().rev());
+            // Unwrap is safe because the range is never empty
+            let split = (0..=b_rest.len())
+                .max_by_key(|k| forward[*k] + backward[b_rest.len() - k])
+                .unwrap();
+
+            common_subsequence(&a_rest[..half], &b_rest[..split], (i, j), pairs);
+            common_subsequence(
+                &a_rest[half..],
+                &b_rest[split..],
+                (i + half, j + split),
+                pairs,
+            );
+        }
+    }
+    let (i, j) = (i + a_rest.len(), j + b_rest.len());
+    pairs.extend((0..suffix).map(|k| (i + k, j + k)));
+}
+
+/// The lengths of the longest common subsequences of all of `a` and every start of `b`.
+fn last_row<'a>(
+    a: impl Iterator<Item = &'a usize>,
+    b: impl Iterator<Item = &'a usize> + Clone,
+) -> Vec<usize> {
+    let mut row = vec![0; b.clone().count() + 1];
+    for x in a {
+        // The length for one line less of both, from the row before
+        let mut diagonal = 0;
+        for (k, y) in b.clone().enumerate() {
+            let above = row[k + 1];
+            row[k + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(row[k])
+            };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+/// The changes that turn `base` into `other`.
+fn hunks<'a>(base: &[&str], other: &[&'a str]) -> Vec<Hunk<'a>> {
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    // The end of both texts acts as a final common line
+    let ends = std::iter::once((base.len(), other.len()));
+
+    for (next_i, next_j) in common_lines(base, other).into_iter().chain(ends) {
+        if next_i > i || next_j > j {
+            hunks.push(Hunk {
+                start: i,
+                end: next_i,
+                lines: other[j..next_j].to_vec(),
+            });
+        }
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+    hunks
+}
+
+fn overlaps(a: &Hunk, b: &Hunk) -> bool {
+    // Insertions at the same spot count as overlapping, so their order is well defined
+    (a.start < b.end && b.start < a.end) || a.start == b.start
+}
+
+/// `base[start..end]` with the hunks applied, which all lie within that range.
+fn apply<'a>(base: &[&'a str], start: usize, end: usize, hunks: &[&Hunk<'a>]) -> Vec<&'a str> {
+    let mut lines = vec![];
+    let mut position = start;
+    for hunk in hunks {
+        lines.extend_from_slice(&base[position..hunk.start]);
+        lines.extend_from_slice(&hunk.lines);
+        position = hunk.end;
+    }
+    lines.extend_from_slice(&base[position..end]);
+    lines
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`.
+pub fn merge_text(base: &str, ours: &str, theirs: &str) -> String {
+    let base_lines = split_lines(base);
+    let ours_lines = split_lines(ours);
+    let theirs_lines = split_lines(theirs);
+
+    // Every hunk is tagged with whether it is ours, then grouped with the hunks it overlaps
+    let mut all: Vec<(bool, Hunk)> = hunks(&base_lines, &ours_lines)
+        .into_iter()
+        .map(|hunk| (true, hunk))
+        .chain(
+            hunks(&base_lines, &theirs_lines)
+                .into_iter()
+                .map(|hunk| (false, hunk)),
+        )
+        .collect();
+    all.sort_by_key(|(is_ours, hunk)| (hunk.start, hunk.end, !is_ours));
+
+    let mut groups: Vec<Vec<(bool, Hunk)>> = vec![];
+    for (is_ours, hunk) in all {
+        match groups.last_mut() {
+            Some(group) if group.iter().any(|(_, other)| overlaps(other, &hunk)) => {
+                group.push((is_ours, hunk))
+            }
+            _ => groups.push(vec![(is_ours, hunk)]),
+        }
+    }
+
+    let mut merged: Vec<&str> = vec![];
+    let mut position = 0;
+    for group in &groups {
+        let start = group
+            .iter()
+            .map(|(_, hunk)| hunk.start)
+            .min()
+            .unwrap_or(position);
+        let end = group
+            .iter()
+            .map(|(_, hunk)| hunk.end)
+            .max()
+            .unwrap_or(position);
+        merged.extend_from_slice(&base_lines[position..start]);
+
+        let side = |ours: bool| -> Vec<&Hunk> {
+            group
+                .iter()
+                .filter(|(is_ours, _)| *is_ours == ours)
+                .map(|(_, hunk)| hunk)
+                .collect()
+        };
+        let (our_hunks, their_hunks) = (side(true), side(false));
+
+        if their_hunks.is_empty() {
+            merged.extend(apply(&base_lines, start, end, &our_hunks));
+        } else if our_hunks.is_empty() {
+            merged.extend(apply(&base_lines, start, end, &their_hunks));
+        } else {
+            let our_version = apply(&base_lines, start, end, &our_hunks);
+            let their_version = apply(&base_lines, start, end, &their_hunks);
+            // Both sides made the same change, or they conflict and both are kept
+            let same = our_version == their_version;
+            merged.extend(our_version);
+            if !same {
+                merged.extend(their_version);
+            }
+        }
+        position = end;
+    }
+    merged.extend_from_slice(&base_lines[position..]);
+
+    merged.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_lines, merge_text};
+
+    #[test]
+    fn merges_edits_to_different_lines() {
+        let base = "Call +31 6 12345678\n- [ ] bring id\n- [ ] bring forms\n";
+        let ours = "Call +31 6 12345678\n- [x] bring id\n- [ ] bring forms\n";
+        let theirs = "Call +31 6 87654321\n- [ ] bring id\n- [ ] bring forms\n- [ ] pay\n";
+
+        assert_eq!(
+            merge_text(base, ours, theirs),
+            "Call +31 6 87654321\n- [x] bring id\n- [ ] bring forms\n- [ ] pay\n"
+        );
+    }
+
+    #[test]
+    fn keeps_both_sides_of_a_conflict() {
+        assert_eq!(merge_text("a\nb\nc", "a\nB\nc", "a\nβ\nc"), "a\nB\nβ\nc");
+        assert_eq!(merge_text("a", "a\nours", "a\ntheirs"), "a\nours\ntheirs");
+        // The same change on both sides is only applied once
+        assert_eq!(merge_text("a\nb", "a\nc", "a\nc"), "a\nc");
+    }
+
+    #[test]
+    fn finds_longest_common_lines() {
+        let lines = |text: &'static str| text.split(' ').collect::<Vec<_>>();
+        let pairs = common_lines(&lines("a b c d e f"), &lines("x b d q e f y"));
+        assert_eq!(pairs, [(1, 1), (3, 2), (4, 4), (5, 5)]);
+
+        let pairs = common_lines(&lines("a b a b a"), &lines("b a b"));
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn merges_long_texts() {
+        let base: Vec<String> = (0..3000).map(|i| format!("line {i}")).collect();
+        let ours: Vec<String> = base.iter().map(|line| format!("{line} ours")).collect();
+        let mut theirs = base.clone();
+        theirs.push("appended".into());
+
+        let merged = merge_text(&base.join("\n"), &ours.join("\n"), &theirs.join("\n"));
+        assert_eq!(merged, ours.join("\n") + "\nappended");
+    }
+
+    #[test]
+    fn unchanged_sides() {
+        assert_eq!(merge_text("a\nb", "a\nb", "x\ny"), "x\ny");
+        assert_eq!(merge_text("a\nb", "x\ny", "a\nb"), "x\ny");
+        assert_eq!(merge_text("", "", "new"), "new");
+        assert_eq!(merge_text("old\n", "old\nmore\n", ""), "more\n");
+    }
+}
@@ -0,0 +1,397 @@
+//! Manual ordering of tasks and projects through fractional sort keys.
+//!
+//! Every task and project carries a `sort_key`, a string of base 62 digits compared as text.
+//! Moving an item only gives it a new key between the keys of its new neighbours, so reorders
+//! on different devices never touch the same items and merge without conflicts. Two devices
+//! picking the same spot end up with equal keys, those stay in the order they were applied in.
+//!
+//! Items from before sort keys existed have an empty key and sort first.
+//!
+//! Most items are added at the end of a list. Halving the room after the last key there would
+//! make keys grow by a digit every few items, so keys at the end count up an integer instead,
+//! which only gains a digit when the integer does.
+
+use crate::{Database, Operation, Project, Task, INBOX_ID};
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The first digit of a key counted up at the end of a list, after any number of the highest
+/// digit. It says how many digits the integer following it has, so longer integers sort last.
+const HEADS: &[u8] = b"uvwxy";
+
+fn digit_value(digit: u8) -> usize {
+    // Keys only ever contain digits generated here, anything else sorts as the lowest digit
+    DIGITS.iter().position(|d| *d == digit).unwrap_or(0)
+}
+
+/// A key sorting strictly between `before` and `after`, where `None` means the start or the end
+/// of the list. Returns `None` when there is no room, because `before` doesn't sort before
+/// `after`.
+pub fn key_between(before: Option<&str>, after: Option<&str>) -> Option<String> {
+    let before = before.unwrap_or("");
+    // Nothing sorts between a key and the same key followed by the lowest digit
+    let unreachable = |after: &str| before >= after || after.ends_with(char::from(DIGITS[0]));
+    if after.is_some_and(unreachable) {
+        return None;
+    }
+
+    match after {
+        Some(after) => Some(midpoint(before.as_bytes(), Some(after.as_bytes()))),
+        None => Some(key_after(before)),
+    }
+}
+
+/// A key sorting after `before`, made of its leading highest digits, a head from [`HEADS`] and
+/// an integer one higher than the digits of `before` after the head. Keys that don't have that
+/// shape, like those from before counting up, sort before the lowest head.
+fn key_after(before: &str) -> String {
+    let highest = DIGITS[DIGITS.len() - 1];
+    let mut level = before.bytes().take_while(|digit| *digit == highest).count();
+    let rest = &before.as_bytes()[level..];
+
+    let (mut head, mut integer) = match rest
+        .first()
+        .and_then(|first| HEADS.iter().position(|head| head == first))
+    {
+        Some(head) => {
+            let digits = (1..=head + 1).map(|i| rest.get(i).map_or(0, |digit| digit_value(*digit)));
+            (head, digits.collect())
+        }
+        None => (0, vec![0]),
+    };
+
+    loop {
+        // Count up like an odometer, the digits that wrap around carry into the one before them
+        let overflowed = integer.iter_mut().rev().all(|digit| {
+            *digit = (*digit + 1) % DIGITS.len();
+            *digit == 0
+        });
+        if overflowed {
+            head += 1;
+            if head == HEADS.len() {
+                level += 1;
+                head = 0;
+            }
+            integer = vec![0; head + 1];
+        } else if integer.last() != Some(&0) {
+            // Keeping the lowest digit off the end leaves room below the key
+            break;
+        }
+    }
+
+    let mut key = vec![highest; level];
+    key.push(HEADS[head]);
+    key.extend(integer.into_iter().map(|digit| DIGITS[digit]));
+    // Unwrap is safe because all digits are ASCII
+    String::from_utf8(key).unwrap()
+}
+
+/// Keys generated here never end in the lowest digit, so there is always room below a key.
+fn midpoint(before: &[u8], after: Option<&[u8]>) -> String {
+    if let Some(after) = after {
+        // Digits both keys share, with `before` padded with the lowest digit
+        let common = after
+            .iter()
+            .enumerate()
+            .take_while(|(i, digit)| before.get(*i).copied().unwrap_or(DIGITS[0]) == **digit)
+            .count();
+        if common > 0 {
+            let prefix = String::from_utf8_lossy(&after[..common]).into_owned();
+            let rest = midpoint(before.get(common..).unwrap_or(&[]), Some(&after[common..]));
+            return prefix + &rest;
+        }
+    }
+
+    let low = before.first().map_or(0, |digit| digit_value(*digit));
+    let high = after.map_or(DIGITS.len(), |after| digit_value(after[0]));
+
+    if high - low > 1 {
+        return char::from(DIGITS[(low + high) / 2]).to_string();
+    }
+
+    match after {
+        // The first digit of `after` alone sorts before it and after `before`
+        Some(after) if after.len() > 1 => char::from(after[0]).to_string(),
+        _ => char::from(DIGITS[low]).to_string() + &midpoint(before.get(1..).unwrap_or(&[]), None),
+    }
+}
+
+/// The keys for `count` items placed one after the other following `before`.
+fn keys_after(before: Option<&str>, count: usize) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let previous = keys.last().map(String::as_str).or(before);
+        // Unwrap is safe because there is always room at the end
+        keys.push(key_between(previous, None).unwrap());
+    }
+    keys
+}
+
+impl Project {
+    /// The tasks of this project in their manual order.
+    pub fn ordered_tasks(&self) -> Vec<&Task> {
+        let mut tasks: Vec<&Task> = self.tasks.iter().collect();
+        // Stable, so tasks with equal keys keep the order they were added in
+        tasks.sort_by(|a, b| a.sort_key.cmp(&b.sort_key));
+        tasks
+    }
+
+    /// A key that puts a task after all tasks in this project.
+    pub(crate) fn next_sort_key(&self) -> String {
+        let last = self.tasks.iter().map(|task| task.sort_key.as_str()).max();
+        // Unwrap is safe because there is always room at the end
+        key_between(last, None).unwrap()
+    }
+}
+
+impl Database {
+    /// A key that puts a project after all other projects.
+    pub(crate) fn next_project_sort_key(&self) -> String {
+        let last = self
+            .projects
+            .values()
+            .map(|project| project.sort_key.as_str())
+            .max();
+        // Unwrap is safe because there is always room at the end
+        key_between(last, None).unwrap()
+    }
+
+    /// The operations that put a task right after `after_task_id` among its siblings, or first
+    /// for `None`. Siblings whose keys leave no room are renumbered along the way.
+    pub fn place_task_after(&self, task_id: &str, after_task_id: Option<&str>) -> Vec<Operation> {
+        let (Ok(project), Some(task)) = (self.project_of(task_id), self.get_task(task_id)) else {
+            return vec![];
+        };
+
+        let siblings: Vec<(&str, &str)> = project
+            .ordered_tasks()
+            .into_iter()
+            .filter(|sibling| sibling.parent_task_id == task.parent_task_id)
+            .map(|sibling| (sibling.task_id.as_str(), sibling.sort_key.as_str()))
+            .collect();
+
+        place_after(&siblings, task_id, after_task_id)
+            .into_iter()
+            .map(|(task_id, sort_key)| Operation::ReorderTask { task_id, sort_key })
+            .collect()
+    }
+
+    /// Like [`Database::place_task_after`], for a project among the other children of its
+    /// parent.
+    pub fn place_project_after(
+        &self,
+        project_id: &str,
+        after_project_id: Option<&str>,
+    ) -> Vec<Operation> {
+        let Some(project) = self.projects.get(project_id) else {
+            return vec![];
+        };
+
+        let siblings: Vec<(&str, &str)> = self
+            .children_of(project.parent_id.as_deref())
+            .into_iter()
+            .filter(|sibling| sibling.project_id != INBOX_ID)
+            .map(|sibling| (sibling.project_id.as_str(), sibling.sort_key.as_str()))
+            .collect();
+
+        place_after(&siblings, project_id, after_project_id)
+            .into_iter()
+            .map(|(project_id, sort_key)| Operation::ReorderProject {
+                project_id,
+                sort_key,
+            })
+            .collect()
+    }
+}
+
+/// The new keys needed to move `id` behind `after_id` in `siblings`, given as ids with their
+/// keys in their current order.
+fn place_after(
+    siblings: &[(&str, &str)],
+    id: &str,
+    after_id: Option<&str>,
+) -> Vec<(String, String)> {
+    let others: Vec<(&str, &str)> = siblings
+        .iter()
+        .copied()
+        .filter(|(sibling_id, _)| *sibling_id != id)
+        .collect();
+
+    let position = match after_id {
+        None => 0,
+        Some(after_id) => match others
+            .iter()
+            .position(|(sibling_id, _)| *sibling_id == after_id)
+        {
+            Some(index) => index + 1,
+            None => {
+                tracing::warn!("Tried to place item after a non-sibling: {}", after_id);
+                return vec![];
+            }
+        },
+    };
+
+    let before = position.checked_sub(1).map(|index| others[index].1);
+    let after = others.get(position).map(|(_, key)| *key);
+    if let Some(key) = key_between(before, after) {
+        return vec![(id.into(), key)];
+    }
+
+    // Neighbours with equal keys, as in vaults from before sort keys, leave no room in between,
+    // so all siblings are renumbered
+    let mut order: Vec<&str> = others.iter().map(|(sibling_id, _)| *sibling_id).collect();
+    order.insert(position, id);
+    let keys = keys_after(None, order.len());
+
+    order
+        .into_iter()
+        .zip(keys)
+        .map(|(id, key)| (id.into(), key))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key_between;
+    use crate::tests::{project, task};
+    use crate::{Database, Operation, INBOX_ID};
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: project("home", "Home"),
+                at: None,
+            },
+        ]);
+        for id in ["a", "b", "c"] {
+            state.apply_operation(Operation::CreateTask {
+                task: task(id, id),
+                project_id: None,
+                at: None,
+            });
+        }
+        state
+    }
+
+    fn task_order(state: &Database) -> Vec<&str> {
+        state.projects[INBOX_ID]
+            .ordered_tasks()
+            .iter()
+            .map(|task| task.task_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn keys_sort_between_their_neighbours() {
+        let mut keys = vec![key_between(None, None).unwrap()];
+        // Keep inserting at the front, right after the first key and at the end
+        for _ in 0..50 {
+            keys.insert(0, key_between(None, Some(&keys[0])).unwrap());
+            keys.insert(1, key_between(Some(&keys[0]), Some(&keys[1])).unwrap());
+            keys.push(key_between(keys.last().map(String::as_str), None).unwrap());
+        }
+
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(keys, sorted);
+        assert!(keys.iter().all(|key| !key.ends_with('0')));
+
+        assert_eq!(key_between(Some("V"), Some("V")), None);
+        assert_eq!(key_between(Some("k"), Some("V")), None);
+    }
+
+    #[test]
+    fn appended_keys_stay_short() {
+        let mut key = key_between(None, None).unwrap();
+        for _ in 0..10_000 {
+            let next = key_between(Some(&key), None).unwrap();
+            assert!(next > key && !next.ends_with('0'));
+            key = next;
+        }
+        assert!(key.len() <= 4, "{key} is too long");
+
+        // Keys from before counting up, and ones where the integer runs out
+        for before in ["", "V", "k3", "zzzzW", "v", "uz", "yzzzzz", "zyzzzzzV"] {
+            let key = key_between(Some(before), None).unwrap();
+            assert!(key.as_str() > before, "{key} doesn't sort after {before}");
+            assert!(key.len() <= before.len() + 2);
+        }
+    }
+
+    #[test]
+    fn place_tasks_and_projects() {
+        let mut state = filled();
+        assert_eq!(task_order(&state), ["a", "b", "c"]);
+
+        let ops = state.place_task_after("c", Some("a"));
+        assert_eq!(ops.len(), 1);
+        state.batch_operations(ops);
+        assert_eq!(task_order(&state), ["a", "c", "b"]);
+
+        state.batch_operations(state.place_task_after("b", None));
+        assert_eq!(task_order(&state), ["b", "a", "c"]);
+
+        state.batch_operations(state.place_project_after("work", Some("home")));
+        let projects: Vec<&str> = state
+            .children_of(None)
+            .iter()
+            .map(|project| project.project_id.as_str())
+            .collect();
+        assert_eq!(projects, [INBOX_ID, "home", "work"]);
+    }
+
+    #[test]
+    fn unordered_tasks_are_renumbered() {
+        let mut state = filled();
+        // Vaults from before sort keys
+        for task in state.all_tasks_mut() {
+            task.sort_key.clear();
+        }
+
+        let ops = state.place_task_after("a", Some("b"));
+        assert_eq!(ops.len(), 3);
+        state.batch_operations(ops);
+        assert_eq!(task_order(&state), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn concurrent_reorders_merge() {
+        let base = filled();
+        let client_a_ops = base.place_task_after("a", Some("c"));
+        let client_b_ops = base.place_task_after("b", None);
+
+        for ops in [
+            [client_a_ops.clone(), client_b_ops.clone()].concat(),
+            [client_b_ops, client_a_ops].concat(),
+        ] {
+            let mut state = base.clone();
+            state.batch_operations(ops);
+            assert_eq!(task_order(&state), ["b", "c", "a"]);
+        }
+    }
+
+    #[test]
+    fn moved_tasks_go_last() {
+        let mut state = filled();
+        state.batch_operations(vec![
+            Operation::MoveTask {
+                task_id: "a".into(),
+                project_id_to: "work".into(),
+                at: None,
+            },
+            Operation::MoveTask {
+                task_id: "a".into(),
+                project_id_to: INBOX_ID.into(),
+                at: None,
+            },
+        ]);
+
+        assert_eq!(task_order(&state), ["b", "c", "a"]);
+    }
+}
@@ -0,0 +1,560 @@
+//! Parsing what is typed into the quick-add field, like `pay rent friday 9am p1 #finance` or
+//! `morgen om 15:00 tandarts bellen !!`, into a task.
+//!
+//! Dates and times are recognized in English and Dutch and are relative to now in the timezone
+//! of the user. They are scheduled, unless preceded by a word like `by` or `uiterlijk`, which
+//! makes them the deadline. Priorities are written as `p1` (urgent) to `p4` (low), or as `!!!`
+//! and `!!`, projects as `#name` and labels as `@name`, with underscores for spaces. Only the
+//! first of each is taken, and projects and labels that don't exist are left in the summary.
+//!
+//! The timezone is a fixed offset from UTC, the one of now, which is used for every date. A time
+//! on the other side of a daylight saving change, like `monday 9am` when the clocks go back on
+//! Sunday, therefore ends up an hour off.
+
+use std::ops::Range;
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, NaiveTime, Weekday};
+use serde::Serialize;
+
+use crate::{ids, import, Database, DateOrDateTime, Priority, Project, Task};
+
+/// What a quick-add input describes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QuickAdd {
+    /// The task with the recognized parts taken out of its summary
+    pub task: Task,
+    /// The project to add the task to, `None` for the inbox
+    pub project_id: Option<String>,
+    /// Where the recognized parts are in the input, in the order they appear
+    pub spans: Vec<Span>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    /// The bytes of the input this covers
+    pub range: Range<usize>,
+    pub kind: SpanKind,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SpanKind {
+    Scheduled,
+    Deadline,
+    Priority,
+    Project,
+    Label,
+}
+
+/// A word of the input, lowercased and without trailing punctuation for matching.
+struct Word<'a> {
+    range: Range<usize>,
+    text: &'a str,
+    lower: String,
+}
+
+/// A date and or time, as it was typed.
+struct When {
+    deadline: bool,
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+}
+
+const DEADLINE_MARKERS: &[&str] = &["by", "due", "before", "deadline", "voor", "uiterlijk"];
+
+impl Database {
+    /// Reads a quick-add input into a task, with dates relative to `now` in its timezone.
+    pub fn quick_add(&self, input: &str, now: &DateTime<FixedOffset>) -> QuickAdd {
+        let words = words(input);
+        let mut task = import::new_task(ids::new_id());
+        let mut project_id = None;
+        let mut spans = vec![];
+        let mut summary = vec![];
+
+        let mut i = 0;
+        while i < words.len() {
+            let word = &words[i];
+            let mut recognized = None;
+
+            if let Some(priority) = parse_priority(&word.lower) {
+                if !spans
+                    .iter()
+                    .any(|span: &Span| span.kind == SpanKind::Priority)
+                {
+                    task.priority = priority;
+                    recognized = Some((1, SpanKind::Priority));
+                }
+            } else if let Some(tag) = word.lower.strip_prefix('#') {
+                if let (None, Some(project)) = (&project_id, self.project_by_tag(tag)) {
+                    project_id = Some(project.project_id.clone());
+                    recognized = Some((1, SpanKind::Project));
+                }
+            } else if let Some(tag) = word.lower.strip_prefix('@') {
+                if let Some(label) = self
+                    .labels
+                    .values()
+                    .find(|label| import::to_tag(&label.name).to_lowercase() == tag)
+                {
+                    task.labels.insert(label.label_id.clone());
+                    recognized = Some((1, SpanKind::Label));
+                }
+            } else if let Some((length, when)) = when_at(&words, i, now) {
+                let field = match when.deadline {
+                    true => &mut task.deadline,
+                    false => &mut task.scheduled,
+                };
+                // Dates beyond what can be represented stay in the summary
+                if let (true, Some(resolved)) =
+                    (field.is_none(), resolve(when.date, when.time, now))
+                {
+                    *field = Some(resolved);
+                    let kind = match when.deadline {
+                        true => SpanKind::Deadline,
+                        false => SpanKind::Scheduled,
+                    };
+                    recognized = Some((length, kind));
+                }
+            }
+
+            match recognized {
+                Some((length, kind)) => {
+                    spans.push(Span {
+                        range: word.range.start..words[i + length - 1].range.end,
+                        kind,
+                    });
+                    i += length;
+                }
+                None => {
+                    summary.push(word.text);
+                    i += 1;
+                }
+            }
+        }
+
+        task.summary = summary.join(" ");
+        QuickAdd {
+            task,
+            project_id,
+            spans,
+        }
+    }
+
+    /// The project a tag like `side_projects` or `work/side_projects` refers to, ignoring case.
+    /// When several projects have the name, the first in the sidebar wins.
+    fn project_by_tag(&self, tag: &str) -> Option<&Project> {
+        let mut nodes: Vec<_> = self.project_tree().into_iter().rev().collect();
+        while let Some(node) = nodes.pop() {
+            nodes.extend(node.children.into_iter().rev());
+            let path: Vec<String> = self
+                .project_path(&node.project.project_id)
+                .into_iter()
+                .map(import::to_tag)
+                .collect();
+            let path = path.join("/").to_lowercase();
+            if path == tag || path.rsplit('/').next() == Some(tag) {
+                return Some(node.project);
+            }
+        }
+        None
+    }
+}
+
+fn words(input: &str) -> Vec<Word<'_>> {
+    let mut words = vec![];
+    let mut start = None;
+    for (index, c) in input.char_indices().chain([(input.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                let text = &input[word_start..index];
+                words.push(Word {
+                    range: word_start..index,
+                    text,
+                    lower: text.trim_end_matches([',', '.', ';', '?']).to_lowercase(),
+                });
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    words
+}
+
+fn parse_priority(word: &str) -> Option<Priority> {
+    match word {
+        "p1" | "!!!" => Some(Priority::Urgent),
+        "p2" | "!!" => Some(Priority::High),
+        "p3" => Some(Priority::Standard),
+        "p4" => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+/// Reads a date and or time starting at word `i`, returning how many words it takes.
+fn when_at(words: &[Word], i: usize, now: &DateTime<FixedOffset>) -> Option<(usize, When)> {
+    let today = now.date_naive();
+    let deadline = DEADLINE_MARKERS.contains(&words[i].lower.as_str());
+    let mut j = i + usize::from(deadline);
+
+    let mut date = date_at(words, j, today).map(|(length, date)| {
+        j += length;
+        date
+    });
+    let time = time_at(words, j).map(|(length, time)| {
+        j += length;
+        time
+    });
+    if date.is_none() && time.is_some() {
+        date = date_at(words, j, today).map(|(length, date)| {
+            j += length;
+            date
+        });
+    }
+
+    match (date, time) {
+        (None, None) => None,
+        _ => Some((
+            j - i,
+            When {
+                deadline,
+                date,
+                time,
+            },
+        )),
+    }
+}
+
+/// Reads a date like `friday`, `next week`, `in 3 days`, `5 oktober` or `2024-10-05`.
+fn date_at(words: &[Word], i: usize, today: NaiveDate) -> Option<(usize, NaiveDate)> {
+    let word = |offset: usize| words.get(i + offset).map(|word| word.lower.as_str());
+
+    // `on friday`, `op vrijdag`
+    if matches!(word(0), Some("on" | "op")) {
+        return date_at(words, i + 1, today).map(|(length, date)| (length + 1, date));
+    }
+
+    match (word(0)?, word(1), word(2)) {
+        ("today" | "vandaag", _, _) => return Some((1, today)),
+        ("tomorrow" | "morgen", _, _) => return Some((1, today.checked_add_days(Days::new(1))?)),
+        ("overmorgen", _, _) => return Some((1, today.checked_add_days(Days::new(2))?)),
+        ("day", Some("after"), Some("tomorrow")) => {
+            return Some((3, today.checked_add_days(Days::new(2))?))
+        }
+        ("next" | "volgende", Some("week"), _) => {
+            let days = 7 - u64::from(today.weekday().num_days_from_monday());
+            return Some((2, today.checked_add_days(Days::new(days))?));
+        }
+        ("next" | "volgende", Some("month" | "maand"), _) => {
+            let month = today.with_day(1)?.checked_add_months(Months::new(1))?;
+            return Some((2, month));
+        }
+        ("next" | "volgende", Some(name), _) => {
+            let weekday = weekday(name)?;
+            return Some((2, next_weekday(today, weekday, true)?));
+        }
+        ("in" | "over", Some(count), Some(unit)) => {
+            let count: u32 = match count {
+                "a" | "an" | "one" | "een" => 1,
+                count => count.parse().ok()?,
+            };
+            let date = match unit {
+                "day" | "days" | "dag" | "dagen" => {
+                    today.checked_add_days(Days::new(count.into()))?
+                }
+                "week" | "weeks" | "weken" => {
+                    today.checked_add_days(Days::new(7 * u64::from(count)))?
+                }
+                "month" | "months" | "maand" | "maanden" => {
+                    today.checked_add_months(Months::new(count))?
+                }
+                _ => return None,
+            };
+            return Some((3, date));
+        }
+        _ => {}
+    }
+
+    if let Some(weekday) = weekday(word(0)?) {
+        return next_weekday(today, weekday, false).map(|date| (1, date));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(word(0)?, "%Y-%m-%d") {
+        return Some((1, date));
+    }
+
+    // `5 oktober`, `oct 5th`, either with a year after it
+    let (day, month) = match (day_number(word(0)?), word(1).and_then(month)) {
+        (Some(day), Some(month)) => (day, month),
+        _ => (day_number(word(1)?)?, month(word(0)?)?),
+    };
+    match word(2).and_then(|year| year.parse::<i32>().ok()) {
+        Some(year) if (1970..=9999).contains(&year) => {
+            Some((3, NaiveDate::from_ymd_opt(year, month, day)?))
+        }
+        _ => {
+            let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+            // Dates that have passed this year are next year's
+            match date < today {
+                true => Some((2, NaiveDate::from_ymd_opt(today.year() + 1, month, day)?)),
+                false => Some((2, date)),
+            }
+        }
+    }
+}
+
+/// Reads a time like `9am`, `9:30 pm`, `at 9`, `15:00` or `om 15 uur`.
+fn time_at(words: &[Word], i: usize) -> Option<(usize, NaiveTime)> {
+    let word = |offset: usize| words.get(i + offset).map(|word| word.lower.as_str());
+    let prefixed = matches!(word(0), Some("at" | "om"));
+    let start = usize::from(prefixed);
+    let first = word(start)?;
+
+    match first {
+        "noon" => return Some((start + 1, NaiveTime::from_hms_opt(12, 0, 0)?)),
+        "midnight" | "middernacht" => return Some((start + 1, NaiveTime::MIN)),
+        _ => {}
+    }
+
+    // The suffix can be part of the word, `9am`, or the next word, `9 am`
+    let (clock, suffix, length) = match ["am", "pm", "uur", "u"]
+        .iter()
+        .find_map(|suffix| Some((first.strip_suffix(suffix)?, *suffix)))
+        .filter(|(clock, _)| !clock.is_empty())
+    {
+        Some((clock, suffix)) => (clock, Some(suffix), 1),
+        None => match word(start + 1) {
+            Some(suffix @ ("am" | "pm" | "uur")) => (first, Some(suffix), 2),
+            _ => (first, None, 1),
+        },
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour.parse().ok()?, minute.parse().ok()?),
+        // A bare number is only a time when it's clear from the context
+        None if suffix.is_some() || prefixed => (clock.parse().ok()?, 0),
+        _ => return None,
+    };
+    let hour = match suffix {
+        Some("am") if hour == 12 => 0,
+        Some("pm") if hour < 12 => hour + 12,
+        Some("am" | "pm") if hour > 12 => return None,
+        _ => hour,
+    };
+
+    Some((start + length, NaiveTime::from_hms_opt(hour, minute, 0)?))
+}
+
+fn weekday(name: &str) -> Option<Weekday> {
+    Some(match name {
+        "monday" | "mon" | "maandag" => Weekday::Mon,
+        "tuesday" | "tue" | "tues" | "dinsdag" => Weekday::Tue,
+        "wednesday" | "wed" | "woensdag" => Weekday::Wed,
+        "thursday" | "thu" | "thurs" | "donderdag" => Weekday::Thu,
+        "friday" | "fri" | "vrijdag" => Weekday::Fri,
+        "saturday" | "zaterdag" => Weekday::Sat,
+        "sunday" | "zondag" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// The first `weekday` from today on, or after today when `strictly_after`.
+fn next_weekday(today: NaiveDate, weekday: Weekday, strictly_after: bool) -> Option<NaiveDate> {
+    let mut days =
+        (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    if days == 0 && strictly_after {
+        days = 7;
+    }
+    today.checked_add_days(Days::new(days.into()))
+}
+
+fn month(name: &str) -> Option<u32> {
+    Some(match name {
+        "january" | "januari" | "jan" => 1,
+        "february" | "februari" | "feb" => 2,
+        "march" | "maart" | "mar" | "mrt" => 3,
+        "april" | "apr" => 4,
+        "may" | "mei" => 5,
+        "june" | "juni" | "jun" => 6,
+        "july" | "juli" | "jul" => 7,
+        "august" | "augustus" | "aug" => 8,
+        "september" | "sep" | "sept" => 9,
+        "october" | "oktober" | "oct" | "okt" => 10,
+        "november" | "nov" => 11,
+        "december" | "dec" => 12,
+        _ => return None,
+    })
+}
+
+/// Reads a day of the month like `5`, `5th` or `5e`.
+fn day_number(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !["", "st", "nd", "rd", "th", "e"].contains(&suffix) {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// Turns a date and or time in the timezone of `now` into a date or a point in time. A time
+/// without a date is today, or tomorrow once it has passed. Returns `None` for times beyond what
+/// can be represented.
+fn resolve(
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+    now: &DateTime<FixedOffset>,
+) -> Option<DateOrDateTime> {
+    let Some(time) = time else {
+        // Unwrap is safe because `when_at` gives a date or a time
+        return Some(DateOrDateTime::Date(date.unwrap()));
+    };
+
+    let today = now.date_naive();
+    let date = match (date, time > now.time()) {
+        (Some(date), _) => date,
+        (None, true) => today,
+        (None, false) => today.checked_add_days(Days::new(1))?,
+    };
+    let utc = date.and_time(time).checked_sub_offset(*now.offset())?;
+    Some(DateOrDateTime::DateTime(utc.and_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+
+    use super::SpanKind;
+    use crate::tests::project;
+    use crate::{Database, DateOrDateTime, Label, Operation, Priority};
+
+    fn example() -> Database {
+        let mut db = Database::new();
+        db.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("finance", "Finance"),
+                at: None,
+            },
+            Operation::CreateLabel {
+                label: Label {
+                    label_id: "phone".into(),
+                    name: "Phone".into(),
+                    color: None,
+                },
+            },
+        ]);
+        db
+    }
+
+    /// Wednesday 2 October 2024, 10:00 in Amsterdam.
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 10, 2, 10, 0, 0)
+            .unwrap()
+    }
+
+    fn date(month: u32, day: u32) -> Option<DateOrDateTime> {
+        Some(DateOrDateTime::Date(
+            NaiveDate::from_ymd_opt(2024, month, day).unwrap(),
+        ))
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> Option<DateOrDateTime> {
+        Some(DateOrDateTime::DateTime(
+            Utc.with_ymd_and_hms(2024, month, day, hour, minute, 0)
+                .unwrap(),
+        ))
+    }
+
+    #[test]
+    pub fn english() {
+        let input = "pay rent friday 9am p1 #finance";
+        let quick_add = example().quick_add(input, &now());
+
+        assert_eq!(quick_add.task.summary, "pay rent");
+        assert_eq!(quick_add.task.scheduled, utc(10, 4, 7, 0));
+        assert_eq!(quick_add.task.priority, Priority::Urgent);
+        assert_eq!(quick_add.project_id.as_deref(), Some("finance"));
+
+        let spans: Vec<(&str, SpanKind)> = quick_add
+            .spans
+            .iter()
+            .map(|span| (&input[span.range.clone()], span.kind))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("friday 9am", SpanKind::Scheduled),
+                ("p1", SpanKind::Priority),
+                ("#finance", SpanKind::Project)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn dutch() {
+        let input = "morgen om 15:00 tandarts bellen !! @phone";
+        let quick_add = example().quick_add(input, &now());
+
+        assert_eq!(quick_add.task.summary, "tandarts bellen");
+        assert_eq!(quick_add.task.scheduled, utc(10, 3, 13, 0));
+        assert_eq!(quick_add.task.priority, Priority::High);
+        assert!(quick_add.task.labels.contains("phone"));
+        assert_eq!(quick_add.project_id, None);
+
+        let quick_add = example().quick_add("belasting uiterlijk 5 oktober", &now());
+        assert_eq!(quick_add.task.summary, "belasting");
+        assert_eq!(quick_add.task.deadline, date(10, 5));
+        assert_eq!(quick_add.task.scheduled, None);
+    }
+
+    #[test]
+    pub fn dates() {
+        let db = example();
+        let parse = |input: &str| db.quick_add(input, &now()).task;
+
+        assert_eq!(parse("a today").scheduled, date(10, 2));
+        assert_eq!(parse("a wednesday").scheduled, date(10, 2));
+        assert_eq!(parse("a next wednesday").scheduled, date(10, 9));
+        assert_eq!(parse("a next week").scheduled, date(10, 7));
+        assert_eq!(parse("a over 3 dagen").scheduled, date(10, 5));
+        assert_eq!(parse("a in 2 weeks").scheduled, date(10, 16));
+        assert_eq!(parse("a oct 1st").scheduled.unwrap(), {
+            DateOrDateTime::Date(NaiveDate::from_ymd_opt(2025, 10, 1).unwrap())
+        });
+        assert_eq!(parse("a on 2024-12-24").scheduled, date(12, 24));
+        // Has passed today, so it's tomorrow
+        assert_eq!(parse("a at 9").scheduled, utc(10, 3, 7, 0));
+        assert_eq!(parse("a 9:30 pm").scheduled, utc(10, 2, 19, 30));
+        assert_eq!(parse("a due tomorrow").deadline, date(10, 3));
+
+        // Only the first date is taken, and numbers aren't times without context
+        let task = parse("buy 9 eggs today tomorrow");
+        assert_eq!(task.summary, "buy 9 eggs tomorrow");
+        assert_eq!(task.scheduled, date(10, 2));
+
+        // Projects that don't exist stay in the text
+        assert_eq!(parse("read #nothing").summary, "read #nothing");
+    }
+
+    #[test]
+    pub fn dates_beyond_the_calendar_stay_in_the_summary() {
+        let end = FixedOffset::west_opt(3600)
+            .unwrap()
+            .from_local_datetime(&NaiveDate::MAX.and_hms_opt(22, 0, 0).unwrap())
+            .unwrap();
+
+        for input in [
+            "tomorrow",
+            "next week",
+            "next month",
+            "next friday",
+            "in 4294967295 days",
+            "in 4294967295 months",
+            "at 9",
+            "today 23:30",
+        ] {
+            let quick_add = example().quick_add(input, &end);
+            assert_eq!(quick_add.task.summary, input);
+            assert_eq!(quick_add.task.scheduled, None);
+        }
+    }
+}
@@ -0,0 +1,718 @@
+//! Repeating tasks.
+//!
+//! Rules are a subset of the RFC 5545 RRULE: `FREQ`, `INTERVAL`, `BYDAY`, `BYMONTHDAY`,
+//! `BYMONTH`, `BYSETPOS`, `COUNT` and `UNTIL`, with weeks starting on Monday. Intervals count
+//! from the current occurrence rather than from the first one, and `BYDAY` in a yearly rule
+//! applies within the months of `BYMONTH`. On top of that a task can repeat a number of days
+//! after it was completed, written as `X-AFTER-COMPLETION=<days>`.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{DateOrDateTime, Task};
+
+/// How many periods we look ahead for an occurrence before concluding there is none, which
+/// happens for rules like the 30th of February.
+const MAX_PERIODS: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
+pub enum Recurrence {
+    Rule(RecurrenceRule),
+    /// Repeats this many days after the task was last completed
+    AfterCompletion {
+        days: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Hash, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A `BYDAY` entry, `-1FR` is the last Friday of the month.
+#[derive(Serialize, Deserialize, Hash, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i8>,
+    pub weekday: Weekday,
+}
+
+#[derive(Serialize, Deserialize, Hash, Clone, PartialEq, Eq, Debug)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<WeekdayNum>,
+    /// Negative days count from the end of the month
+    pub by_month_day: Vec<i8>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    /// How many occurrences are left, including the current one
+    pub count: Option<u32>,
+    /// The last date an occurrence may fall on
+    pub until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecurrenceParseError {
+    #[error("The rule has no FREQ")]
+    MissingFrequency,
+
+    #[error("{0} is not a supported part of a rule")]
+    UnsupportedPart(String),
+
+    #[error("{value} is not a valid value for {part}")]
+    InvalidValue { part: String, value: String },
+}
+
+impl RecurrenceRule {
+    pub fn new(frequency: Frequency) -> Self {
+        Self {
+            frequency,
+            interval: 1,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+            by_set_pos: vec![],
+            count: None,
+            until: None,
+        }
+    }
+
+    /// The first occurrence after `current`, if the rule has one.
+    pub fn next_after(&self, current: NaiveDate) -> Option<NaiveDate> {
+        for period in 0..MAX_PERIODS {
+            let offset = period.checked_mul(self.interval)?;
+            let mut candidates = self.candidates(current, offset)?;
+            candidates.sort();
+            candidates.dedup();
+
+            let next = self
+                .select_set_positions(candidates)
+                .into_iter()
+                .find(|date| *date > current);
+
+            if let Some(next) = next {
+                return match self.until {
+                    Some(until) if next > until => None,
+                    _ => Some(next),
+                };
+            }
+        }
+
+        None
+    }
+
+    /// All dates the rule allows in the period `offset` periods after the one containing
+    /// `current`. `None` when that period is out of range.
+    fn candidates(&self, current: NaiveDate, offset: u32) -> Option<Vec<NaiveDate>> {
+        let candidates = match self.frequency {
+            Frequency::Daily => {
+                let day = current.checked_add_days(Days::new(offset.into()))?;
+                let matches = (self.by_day.is_empty()
+                    || self.by_day.iter().any(|wd| wd.weekday == day.weekday()))
+                    && (self.by_month_day.is_empty()
+                        || self
+                            .by_month_day
+                            .iter()
+                            .any(|d| resolve_month_day(day, *d) == Some(day)))
+                    && (self.by_month.is_empty() || self.by_month.contains(&day.month()));
+                matches.then_some(day).into_iter().collect()
+            }
+            Frequency::Weekly => {
+                let week_start = current
+                    .checked_sub_days(Days::new(current.weekday().num_days_from_monday().into()))?
+                    .checked_add_days(Days::new(u64::from(offset) * 7))?;
+                let weekdays: Vec<Weekday> = match self.by_day.is_empty() {
+                    true => vec![current.weekday()],
+                    false => self.by_day.iter().map(|wd| wd.weekday).collect(),
+                };
+                weekdays
+                    .into_iter()
+                    .filter_map(|weekday| {
+                        week_start
+                            .checked_add_days(Days::new(weekday.num_days_from_monday().into()))
+                    })
+                    .filter(|day| self.by_month.is_empty() || self.by_month.contains(&day.month()))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let month = current
+                    .with_day(1)?
+                    .checked_add_months(Months::new(offset))?;
+                match self.by_month.is_empty() || self.by_month.contains(&month.month()) {
+                    true => self.month_candidates(month, current.day()),
+                    false => vec![],
+                }
+            }
+            Frequency::Yearly => {
+                let year = current.year().checked_add(offset.try_into().ok()?)?;
+                let months = match self.by_month.is_empty() {
+                    true => vec![current.month()],
+                    false => self.by_month.clone(),
+                };
+                months
+                    .into_iter()
+                    .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+                    .flat_map(|month| self.month_candidates(month, current.day()))
+                    .collect()
+            }
+        };
+
+        Some(candidates)
+    }
+
+    /// The dates in the month starting at `first` that match `BYMONTHDAY` and `BYDAY`, or the
+    /// given day when the rule has neither.
+    fn month_candidates(&self, first: NaiveDate, default_day: u32) -> Vec<NaiveDate> {
+        let by_month_day: Vec<NaiveDate> = self
+            .by_month_day
+            .iter()
+            .filter_map(|day| resolve_month_day(first, *day))
+            .collect();
+        let by_day: Vec<NaiveDate> = self
+            .by_day
+            .iter()
+            .flat_map(|weekday| weekdays_in_month(first, *weekday))
+            .collect();
+
+        match (self.by_month_day.is_empty(), self.by_day.is_empty()) {
+            (true, true) => first.with_day(default_day).into_iter().collect(),
+            (false, true) => by_month_day,
+            (true, false) => by_day,
+            (false, false) => by_month_day
+                .into_iter()
+                .filter(|day| by_day.contains(day))
+                .collect(),
+        }
+    }
+
+    fn select_set_positions(&self, candidates: Vec<NaiveDate>) -> Vec<NaiveDate> {
+        if self.by_set_pos.is_empty() {
+            return candidates;
+        }
+
+        let len = candidates.len() as i32;
+        let mut selected: Vec<NaiveDate> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| match *pos {
+                pos if pos > 0 => candidates.get((pos - 1) as usize),
+                pos if pos < 0 && -pos <= len => candidates.get((len + pos) as usize),
+                _ => None,
+            })
+            .copied()
+            .collect();
+        selected.sort();
+        selected
+    }
+}
+
+fn days_in_month(day: NaiveDate) -> Option<u32> {
+    let first = day.with_day(1)?;
+    let next = first.checked_add_months(Months::new(1))?;
+    Some((next - first).num_days() as u32)
+}
+
+/// The date of a `BYMONTHDAY` value in the month of `day`.
+fn resolve_month_day(day: NaiveDate, month_day: i8) -> Option<NaiveDate> {
+    let days = days_in_month(day)? as i32;
+    let month_day = match month_day as i32 {
+        d if d > 0 => d,
+        d => days + 1 + d,
+    };
+    match month_day >= 1 && month_day <= days {
+        true => day.with_day(month_day as u32),
+        false => None,
+    }
+}
+
+/// The dates in the month starting at `first` matching a `BYDAY` entry.
+fn weekdays_in_month(first: NaiveDate, weekday: WeekdayNum) -> Vec<NaiveDate> {
+    let all: Vec<NaiveDate> = first
+        .iter_days()
+        .take_while(|day| day.month() == first.month())
+        .filter(|day| day.weekday() == weekday.weekday)
+        .collect();
+
+    match weekday.ordinal {
+        None => all,
+        Some(n) if n > 0 => all.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => all
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|i| all.get(i))
+            .copied()
+            .into_iter()
+            .collect(),
+    }
+}
+
+impl DateOrDateTime {
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            DateOrDateTime::Date(date) => *date,
+            DateOrDateTime::DateTime(datetime) => datetime.date_naive(),
+        }
+    }
+
+    fn shifted(&self, days: i64) -> Option<DateOrDateTime> {
+        match self {
+            DateOrDateTime::Date(date) => date
+                .checked_add_signed(Duration::days(days))
+                .map(DateOrDateTime::Date),
+            DateOrDateTime::DateTime(datetime) => datetime
+                .checked_add_signed(Duration::days(days))
+                .map(DateOrDateTime::DateTime),
+        }
+    }
+}
+
+impl Task {
+    /// Marks the task as done, or moves it on to its next occurrence when it repeats.
+    pub(crate) fn complete(&mut self, completed_at: Option<DateTime<Utc>>) {
+        self.done = !self.advance_recurrence(completed_at);
+        self.completed_at = completed_at;
+    }
+
+    /// Whether the task repeats and has moved on from `occurrence` to a later one.
+    pub(crate) fn has_moved_past(&self, occurrence: &DateOrDateTime) -> bool {
+        let current = self.scheduled.as_ref().or(self.deadline.as_ref());
+        self.recurrence.is_some()
+            && current.is_some_and(|current| current.date() > occurrence.date())
+    }
+
+    /// Moves `scheduled` and `deadline` on to the next occurrence, keeping the distance between
+    /// them. Returns false, leaving the task as it is, when the recurrence has ended.
+    ///
+    /// Everything is derived from the task and `completed_at`, which travels with the operation,
+    /// so every replica ends up with the same dates.
+    pub(crate) fn advance_recurrence(&mut self, completed_at: Option<DateTime<Utc>>) -> bool {
+        let current = self
+            .scheduled
+            .as_ref()
+            .or(self.deadline.as_ref())
+            .map(DateOrDateTime::date);
+
+        let next = match (&self.recurrence, current) {
+            (Some(Recurrence::Rule(rule)), Some(current)) => {
+                if rule.count.is_some_and(|count| count <= 1) {
+                    return false;
+                }
+                rule.next_after(current)
+            }
+            (Some(Recurrence::AfterCompletion { days }), _) => completed_at
+                .map(|at| at.date_naive())
+                .or(current)
+                .and_then(|base| base.checked_add_days(Days::new((*days).into()))),
+            _ => None,
+        };
+        let Some(next) = next else {
+            return false;
+        };
+
+        match current {
+            Some(current) => {
+                let shift = (next - current).num_days();
+                let scheduled = self.scheduled.as_ref().map(|date| date.shifted(shift));
+                let deadline = self.deadline.as_ref().map(|date| date.shifted(shift));
+                if scheduled == Some(None) || deadline == Some(None) {
+                    return false;
+                }
+                self.scheduled = scheduled.flatten();
+                self.deadline = deadline.flatten();
+            }
+            None => self.scheduled = Some(DateOrDateTime::Date(next)),
+        }
+
+        if let Some(Recurrence::Rule(RecurrenceRule {
+            count: Some(count), ..
+        })) = &mut self.recurrence
+        {
+            *count -= 1;
+        }
+
+        true
+    }
+}
+
+fn invalid(part: &str, value: &str) -> RecurrenceParseError {
+    RecurrenceParseError::InvalidValue {
+        part: part.into(),
+        value: value.into(),
+    }
+}
+
+fn parse_list<T: FromStr>(part: &str, value: &str) -> Result<Vec<T>, RecurrenceParseError> {
+    value
+        .split(',')
+        .map(|item| item.parse().map_err(|_| invalid(part, value)))
+        .collect()
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+impl FromStr for WeekdayNum {
+    type Err = RecurrenceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .len()
+            .checked_sub(2)
+            .filter(|split| s.is_char_boundary(*split))
+            .ok_or_else(|| invalid("BYDAY", s))?;
+        let (ordinal, code) = s.split_at(split);
+
+        let weekday = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ]
+        .into_iter()
+        .find(|weekday| weekday_code(*weekday) == code)
+        .ok_or_else(|| invalid("BYDAY", s))?;
+
+        let ordinal = match ordinal {
+            "" => None,
+            ordinal => match ordinal.parse::<i8>() {
+                Ok(n) if n != 0 && (-5..=5).contains(&n) => Some(n),
+                _ => return Err(invalid("BYDAY", s)),
+            },
+        };
+
+        Ok(WeekdayNum { ordinal, weekday })
+    }
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = RecurrenceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+
+        let mut frequency = None;
+        let mut rule = RecurrenceRule::new(Frequency::Daily);
+
+        for part in s.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| RecurrenceParseError::UnsupportedPart(part.into()))?;
+
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid(name, value)),
+                    })
+                }
+                "INTERVAL" => match value.parse() {
+                    Ok(interval) if interval > 0 => rule.interval = interval,
+                    _ => return Err(invalid(name, value)),
+                },
+                "BYDAY" => rule.by_day = parse_list(name, &value.to_ascii_uppercase())?,
+                "BYMONTHDAY" => {
+                    rule.by_month_day = parse_list(name, value)?;
+                    if rule
+                        .by_month_day
+                        .iter()
+                        .any(|day| *day == 0 || !(-31..=31).contains(day))
+                    {
+                        return Err(invalid(name, value));
+                    }
+                }
+                "BYMONTH" => {
+                    rule.by_month = parse_list(name, value)?;
+                    if rule.by_month.iter().any(|month| !(1..=12).contains(month)) {
+                        return Err(invalid(name, value));
+                    }
+                }
+                "BYSETPOS" => {
+                    rule.by_set_pos = parse_list(name, value)?;
+                    if rule.by_set_pos.contains(&0) {
+                        return Err(invalid(name, value));
+                    }
+                }
+                "COUNT" => match value.parse() {
+                    Ok(count) if count > 0 => rule.count = Some(count),
+                    _ => return Err(invalid(name, value)),
+                },
+                "UNTIL" => {
+                    // Only the date matters to us, so a time part is ignored
+                    let date = value.get(..8).unwrap_or(value);
+                    rule.until = Some(
+                        NaiveDate::parse_from_str(date, "%Y%m%d")
+                            .map_err(|_| invalid(name, value))?,
+                    );
+                }
+                _ => return Err(RecurrenceParseError::UnsupportedPart(part.into())),
+            }
+        }
+
+        rule.frequency = frequency.ok_or(RecurrenceParseError::MissingFrequency)?;
+        Ok(rule)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = RecurrenceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_prefix("X-AFTER-COMPLETION=") {
+            Some(days) => Ok(Recurrence::AfterCompletion {
+                days: days
+                    .parse()
+                    .map_err(|_| invalid("X-AFTER-COMPLETION", days))?,
+            }),
+            None => s.parse().map(Recurrence::Rule),
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, name: &str, items: &[T]) -> fmt::Result {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+    write!(f, ";{}={}", name, items.join(","))
+}
+
+impl fmt::Display for WeekdayNum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ordinal) = self.ordinal {
+            write!(f, "{}", ordinal)?;
+        }
+        f.write_str(weekday_code(self.weekday))
+    }
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        write_list(f, "BYDAY", &self.by_day)?;
+        write_list(f, "BYMONTHDAY", &self.by_month_day)?;
+        write_list(f, "BYMONTH", &self.by_month)?;
+        write_list(f, "BYSETPOS", &self.by_set_pos)?;
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%d"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Rule(rule) => rule.fmt(f),
+            Recurrence::AfterCompletion { days } => write!(f, "X-AFTER-COMPLETION={}", days),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{Recurrence, RecurrenceParseError, RecurrenceRule};
+    use crate::tests::task;
+    use crate::{DateOrDateTime, Task};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn occurrences(rule: &str, start: NaiveDate, n: usize) -> Vec<NaiveDate> {
+        let rule: RecurrenceRule = rule.parse().unwrap();
+        let mut dates = vec![];
+        let mut current = start;
+        while dates.len() < n {
+            match rule.next_after(current) {
+                Some(next) => {
+                    dates.push(next);
+                    current = next;
+                }
+                None => break,
+            }
+        }
+        dates
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(
+            occurrences("FREQ=DAILY;INTERVAL=3", date(2024, 10, 30), 2),
+            [date(2024, 11, 2), date(2024, 11, 5)]
+        );
+        // Wednesday the 2nd, so Friday comes first
+        assert_eq!(
+            occurrences("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR", date(2024, 10, 2), 3),
+            [date(2024, 10, 4), date(2024, 10, 14), date(2024, 10, 18)]
+        );
+        // The last weekday of the month
+        assert_eq!(
+            occurrences(
+                "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+                date(2024, 10, 1),
+                3
+            ),
+            [date(2024, 10, 31), date(2024, 11, 29), date(2024, 12, 31)]
+        );
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYMONTHDAY=-1", date(2024, 1, 31), 2),
+            [date(2024, 2, 29), date(2024, 3, 31)]
+        );
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYDAY=2TU", date(2024, 10, 1), 2),
+            [date(2024, 10, 8), date(2024, 11, 12)]
+        );
+        // Months without a 31st are skipped
+        assert_eq!(
+            occurrences("FREQ=MONTHLY", date(2024, 1, 31), 2),
+            [date(2024, 3, 31), date(2024, 5, 31)]
+        );
+        assert_eq!(
+            occurrences("FREQ=YEARLY", date(2024, 2, 29), 1),
+            [date(2028, 2, 29)]
+        );
+        assert_eq!(
+            occurrences("FREQ=DAILY;UNTIL=20241003", date(2024, 10, 1), 5),
+            [date(2024, 10, 2), date(2024, 10, 3)]
+        );
+        assert!(
+            occurrences("FREQ=MONTHLY;BYMONTHDAY=30;BYMONTH=2", date(2024, 1, 1), 1).is_empty()
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for rule in [
+            "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=-1SU;COUNT=4;UNTIL=20241231",
+            "FREQ=YEARLY;BYMONTHDAY=1,-1;BYMONTH=1,6",
+            "X-AFTER-COMPLETION=3",
+        ] {
+            assert_eq!(rule.parse::<Recurrence>().unwrap().to_string(), rule);
+        }
+
+        assert_eq!(
+            "RRULE:FREQ=DAILY;UNTIL=20241231T235959Z"
+                .parse::<Recurrence>()
+                .unwrap()
+                .to_string(),
+            "FREQ=DAILY;UNTIL=20241231"
+        );
+        assert_eq!(
+            "INTERVAL=2".parse::<Recurrence>(),
+            Err(RecurrenceParseError::MissingFrequency)
+        );
+        assert_eq!(
+            "FREQ=HOURLY".parse::<Recurrence>(),
+            Err(RecurrenceParseError::InvalidValue {
+                part: "FREQ".into(),
+                value: "HOURLY".into()
+            })
+        );
+        assert!("FREQ=DAILY;BYHOUR=9".parse::<Recurrence>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=XX".parse::<Recurrence>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=éA".parse::<Recurrence>().is_err());
+    }
+
+    fn recurring(rule: &str) -> Task {
+        Task {
+            scheduled: Some(DateOrDateTime::Date(date(2024, 10, 1))),
+            deadline: Some(DateOrDateTime::DateTime(
+                Utc.with_ymd_and_hms(2024, 10, 2, 17, 0, 0).unwrap(),
+            )),
+            recurrence: Some(rule.parse().unwrap()),
+            ..task("mytask", "Water plants")
+        }
+    }
+
+    #[test]
+    fn advance_keeps_distance_between_dates() {
+        let mut task = recurring("FREQ=DAILY;INTERVAL=3;COUNT=2");
+
+        assert!(task.advance_recurrence(None));
+        assert_eq!(
+            task.scheduled,
+            Some(DateOrDateTime::Date(date(2024, 10, 4)))
+        );
+        assert_eq!(
+            task.deadline,
+            Some(DateOrDateTime::DateTime(
+                Utc.with_ymd_and_hms(2024, 10, 5, 17, 0, 0).unwrap()
+            ))
+        );
+
+        // That was the last one
+        assert!(!task.advance_recurrence(None));
+        assert_eq!(
+            task.scheduled,
+            Some(DateOrDateTime::Date(date(2024, 10, 4)))
+        );
+    }
+
+    #[test]
+    fn advance_after_completion() {
+        let mut plants = recurring("X-AFTER-COMPLETION=3");
+        let completed_at = Utc.with_ymd_and_hms(2024, 10, 10, 8, 0, 0).unwrap();
+
+        assert!(plants.advance_recurrence(Some(completed_at)));
+        assert_eq!(
+            plants.scheduled,
+            Some(DateOrDateTime::Date(date(2024, 10, 13)))
+        );
+
+        // Completions from older clients don't say when, so we count from the current date
+        assert!(plants.advance_recurrence(None));
+        assert_eq!(
+            plants.scheduled,
+            Some(DateOrDateTime::Date(date(2024, 10, 16)))
+        );
+
+        let mut undated = Task {
+            recurrence: Some(Recurrence::AfterCompletion { days: 1 }),
+            ..task("undated", "Undated")
+        };
+        assert!(undated.advance_recurrence(Some(completed_at)));
+        assert_eq!(
+            undated.scheduled,
+            Some(DateOrDateTime::Date(date(2024, 10, 11)))
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Tasks nested under other tasks, like the items of a checklist.
+//!
+//! A subtask always lives in the same project as its parent, so moving or deleting a task takes
+//! its subtasks along.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{order, Database, Project, Task};
+
+impl Project {
+    /// The ids of a task and everything nested in it, each parent before its children and
+    /// siblings in their manual order. Empty when the task isn't in this project.
+    pub(crate) fn subtree_ids(&self, task_id: &str) -> Vec<String> {
+        if !self.tasks.iter().any(|task| task.task_id == task_id) {
+            return vec![];
+        }
+
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for task in self.ordered_tasks() {
+            if let Some(parent_id) = task.parent_task_id.as_deref() {
+                children.entry(parent_id).or_default().push(&task.task_id);
+            }
+        }
+
+        let mut ids = vec![task_id];
+        let mut seen = HashSet::from([task_id]);
+        let mut next = 0;
+
+        while next < ids.len() {
+            for child in children.get(ids[next]).into_iter().flatten() {
+                if seen.insert(child) {
+                    ids.push(child);
+                }
+            }
+            next += 1;
+        }
+
+        ids.into_iter().map(String::from).collect()
+    }
+
+    /// Removes a task and its subtasks, the task itself comes first. Also returns the position
+    /// the task had.
+    pub(crate) fn take_subtree(&mut self, task_id: &str) -> Option<(usize, Vec<Task>)> {
+        let ids = self.subtree_ids(task_id);
+        let position = self.tasks.iter().position(|task| task.task_id == task_id)?;
+        // Subtasks that were ahead of the task shift its position
+        let index = self.tasks[..position]
+            .iter()
+            .filter(|task| !ids.contains(&task.task_id))
+            .count();
+
+        let (mut subtree, rest): (Vec<Task>, Vec<Task>) = std::mem::take(&mut self.tasks)
+            .into_iter()
+            .partition(|task| ids.contains(&task.task_id));
+        self.tasks = rest;
+
+        // Subtasks can be ahead of their parent in the list after being moved around
+        let root = subtree.iter().position(|task| task.task_id == task_id)?;
+        let task = subtree.remove(root);
+        subtree.insert(0, task);
+
+        Some((index, subtree))
+    }
+}
+
+impl Database {
+    /// The direct subtasks of a task, in their manual order.
+    pub fn subtasks_of(&self, task_id: &str) -> Vec<&Task> {
+        match self.project_of(task_id) {
+            Ok(project) => project
+                .ordered_tasks()
+                .into_iter()
+                .filter(|task| task.parent_task_id.as_deref() == Some(task_id))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// A task followed by all tasks nested in it however deep, each parent before its children.
+    pub fn task_subtree(&self, task_id: &str) -> Vec<&Task> {
+        let Ok(project) = self.project_of(task_id) else {
+            return vec![];
+        };
+
+        project
+            .subtree_ids(task_id)
+            .iter()
+            .filter_map(|id| project.tasks.iter().find(|task| task.task_id == *id))
+            .collect()
+    }
+
+    /// The tasks containing a task, starting with its parent.
+    pub fn task_ancestors(&self, task_id: &str) -> Vec<&Task> {
+        let Ok(project) = self.project_of(task_id) else {
+            return vec![];
+        };
+        let find = |id: &str| project.tasks.iter().find(|task| task.task_id == id);
+
+        let mut ancestors: Vec<&Task> = vec![];
+        let mut current = find(task_id);
+        while let Some(parent_id) = current.and_then(|task| task.parent_task_id.as_deref()) {
+            if parent_id == task_id || ancestors.iter().any(|task| task.task_id == parent_id) {
+                break;
+            }
+            current = find(parent_id);
+            ancestors.extend(current);
+        }
+
+        ancestors
+    }
+
+    pub(crate) fn indent_task(&mut self, task_id: &str) {
+        let Ok(project) = self.project_of_mut(task_id) else {
+            tracing::warn!("Tried to indent non-existant task: {}", task_id);
+            return;
+        };
+
+        let ordered = project.ordered_tasks();
+        // Unwrap is safe because project_of_mut implies that the project contains this task
+        let index = ordered
+            .iter()
+            .position(|task| task.task_id == task_id)
+            .unwrap();
+        let parent_task_id = &ordered[index].parent_task_id;
+
+        // The task becomes the last subtask of the sibling right above it
+        let sibling = ordered[..index]
+            .iter()
+            .rev()
+            .find(|task| task.parent_task_id == *parent_task_id)
+            .map(|task| task.task_id.clone());
+        let Some(sibling) = sibling else {
+            tracing::warn!("Tried to indent task without a sibling above: {}", task_id);
+            return;
+        };
+
+        let sort_key = project
+            .tasks
+            .iter()
+            .filter(|task| task.parent_task_id.as_ref() == Some(&sibling))
+            .map(|task| task.sort_key.clone())
+            .max();
+        let task = project
+            .tasks
+            .iter_mut()
+            .find(|task| task.task_id == task_id)
+            .unwrap();
+        task.parent_task_id = Some(sibling);
+        // Keep it below the subtasks the sibling already has
+        if let Some(key) = sort_key.and_then(|key| order::key_between(Some(&key), None)) {
+            task.sort_key = key;
+        }
+    }
+
+    pub(crate) fn outdent_task(&mut self, task_id: &str) {
+        let Ok(project) = self.project_of_mut(task_id) else {
+            tracing::warn!("Tried to outdent non-existant task: {}", task_id);
+            return;
+        };
+
+        let Some(parent_task_id) = project
+            .tasks
+            .iter()
+            .find(|task| task.task_id == task_id)
+            .and_then(|task| task.parent_task_id.clone())
+        else {
+            tracing::warn!("Tried to outdent top-level task: {}", task_id);
+            return;
+        };
+
+        let grandparent_id = project
+            .tasks
+            .iter()
+            .find(|task| task.task_id == parent_task_id)
+            .and_then(|task| task.parent_task_id.clone());
+
+        // The task goes right below its old parent, among the parent's siblings
+        let ordered = project.ordered_tasks();
+        let parent = ordered
+            .iter()
+            .position(|task| task.task_id == parent_task_id);
+        let sort_key = parent.and_then(|parent| {
+            let next = ordered[parent + 1..]
+                .iter()
+                .find(|task| task.parent_task_id == grandparent_id)
+                .map(|task| task.sort_key.as_str());
+            order::key_between(Some(&ordered[parent].sort_key), next)
+        });
+
+        for task in project.tasks.iter_mut() {
+            if task.task_id == task_id {
+                task.parent_task_id = grandparent_id.clone();
+                if let Some(ref key) = sort_key {
+                    task.sort_key = key.clone();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn set_task_parent(&mut self, task_id: &str, parent_task_id: Option<String>) {
+        let Ok(project) = self.project_of(task_id) else {
+            tracing::warn!("Tried to set parent of non-existant task: {}", task_id);
+            return;
+        };
+        let project_id = project.project_id.clone();
+
+        let parent_project_id = match &parent_task_id {
+            None => project_id.clone(),
+            Some(parent_task_id) => {
+                let Ok(parent_project) = self.project_of(parent_task_id) else {
+                    tracing::warn!(
+                        "Tried to move task under non-existant task: {}",
+                        parent_task_id
+                    );
+                    return;
+                };
+                let within_subtree = parent_project.project_id == project_id
+                    && project.subtree_ids(task_id).contains(parent_task_id);
+                if within_subtree {
+                    tracing::warn!(
+                        "Tried to move task {} under its own subtask: {}",
+                        task_id,
+                        parent_task_id
+                    );
+                    return;
+                }
+                parent_project.project_id.clone()
+            }
+        };
+
+        if parent_project_id != project_id {
+            self.move_task_subtree(task_id, &parent_project_id);
+        }
+
+        if let Some(task) = self.get_task_mut(task_id) {
+            task.parent_task_id = parent_task_id;
+        }
+    }
+
+    /// Moves a task with its subtasks to the end of another project. The task is detached from
+    /// its parent, which stays behind.
+    pub(crate) fn move_task_subtree(&mut self, task_id: &str, project_id_to: &str) {
+        let Some(project_to) = self.projects.get(project_id_to) else {
+            tracing::warn!(
+                "Tried to move task to non-existant project: {}",
+                project_id_to
+            );
+            return;
+        };
+        let sort_key = project_to.next_sort_key();
+
+        let Ok(project) = self.project_of_mut(task_id) else {
+            return;
+        };
+        if project.project_id == project_id_to {
+            return;
+        }
+
+        // Unwrap is safe because project_of_mut implies that the project contains this task
+        let (_, mut subtree) = project.take_subtree(task_id).unwrap();
+        subtree[0].parent_task_id = None;
+        subtree[0].sort_key = sort_key;
+        let project_id_from = project.project_id.clone();
+        self.index_tasks_from(&project_id_from, 0);
+
+        // Unwrap is safe because we looked the project up above
+        let project = self.projects.get_mut(project_id_to).unwrap();
+        let start = project.tasks.len();
+        project.tasks.extend(subtree);
+        self.index_tasks_from(project_id_to, start);
+    }
+
+    /// Marks a task and everything nested in it as done.
+    pub(crate) fn complete_subtree(
+        &mut self,
+        task_id: &str,
+        at: Option<chrono::DateTime<chrono::Utc>>,
+    ) {
+        let ids: Vec<String> = self
+            .task_subtree(task_id)
+            .into_iter()
+            .map(|task| task.task_id.clone())
+            .collect();
+
+        for id in ids {
+            if let Some(task) = self.get_task_mut(&id) {
+                task.complete(at);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{project, task};
+    use crate::{Database, Operation, Task, INBOX_ID};
+
+    fn subtask(task_id: &str, parent_task_id: &str) -> Operation {
+        Operation::CreateTask {
+            task: Task {
+                parent_task_id: Some(parent_task_id.into()),
+                ..task(task_id, task_id)
+            },
+            project_id: None,
+            at: None,
+        }
+    }
+
+    fn ids(tasks: Vec<&Task>) -> Vec<&str> {
+        tasks.iter().map(|task| task.task_id.as_str()).collect()
+    }
+
+    /// Each task with its project, parent and whether it is done.
+    fn nesting(state: &Database) -> Vec<(String, String, Option<String>, bool)> {
+        let mut nesting: Vec<_> = state
+            .projects
+            .values()
+            .flat_map(|project| {
+                project.tasks.iter().map(|task| {
+                    (
+                        task.task_id.clone(),
+                        project.project_id.clone(),
+                        task.parent_task_id.clone(),
+                        task.done,
+                    )
+                })
+            })
+            .collect();
+        nesting.sort();
+        nesting
+    }
+
+    /// `trip` with subtasks `pack` (with `socks`) and `book`, followed by `other`.
+    fn checklist() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("trip", "Trip"),
+                project_id: None,
+                at: None,
+            },
+            subtask("pack", "trip"),
+            subtask("socks", "pack"),
+            subtask("book", "trip"),
+            Operation::CreateTask {
+                task: task("other", "Other"),
+                project_id: None,
+                at: None,
+            },
+        ]);
+        state
+    }
+
+    #[test]
+    fn queries() {
+        let state = checklist();
+
+        assert_eq!(ids(state.subtasks_of("trip")), ["pack", "book"]);
+        assert_eq!(
+            ids(state.task_subtree("trip")),
+            ["trip", "pack", "book", "socks"]
+        );
+        assert_eq!(ids(state.task_ancestors("socks")), ["pack", "trip"]);
+        assert!(state.task_subtree("missing").is_empty());
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut state = checklist();
+
+        state.apply_operation(Operation::IndentTask {
+            task_id: "book".into(),
+            at: None,
+        });
+        assert_eq!(ids(state.task_ancestors("book")), ["pack", "trip"]);
+
+        state.apply_operation(Operation::OutdentTask {
+            task_id: "book".into(),
+            at: None,
+        });
+        state.apply_operation(Operation::OutdentTask {
+            task_id: "book".into(),
+            at: None,
+        });
+        assert!(state.task_ancestors("book").is_empty());
+        // Right below its old parent rather than wherever its old key puts it, which is after
+        // `other` for a subtask added last
+        state.batch_operations(vec![
+            subtask("late", "trip"),
+            Operation::OutdentTask {
+                task_id: "late".into(),
+                at: None,
+            },
+        ]);
+        let top_level: Vec<&Task> = state.projects[INBOX_ID]
+            .ordered_tasks()
+            .into_iter()
+            .filter(|task| task.parent_task_id.is_none())
+            .collect();
+        assert_eq!(ids(top_level), ["trip", "late", "book", "other"]);
+
+        // There is nothing above the first task to indent it under
+        state.apply_operation(Operation::IndentTask {
+            task_id: "trip".into(),
+            at: None,
+        });
+        assert!(state.task_ancestors("trip").is_empty());
+    }
+
+    #[test]
+    fn move_to_missing_project_keeps_subtree() {
+        let mut state = checklist();
+        state.apply_operation(Operation::DeleteProject {
+            project_id: "work".into(),
+            mode: crate::DeleteMode::Cascade,
+            at: Some(chrono::Utc::now()),
+        });
+        let before = nesting(&state);
+
+        for project_id in ["gone", "work"] {
+            state.move_task_subtree("trip", project_id);
+        }
+
+        assert_eq!(nesting(&state), before);
+    }
+
+    #[test]
+    fn set_parent_refuses_cycles_and_follows_parent_project() {
+        let mut state = checklist();
+
+        state.apply_operation(Operation::SetTaskParent {
+            task_id: "trip".into(),
+            parent_task_id: Some("socks".into()),
+            at: None,
+        });
+        assert!(state.task_ancestors("trip").is_empty());
+
+        state.batch_operations(vec![
+            Operation::CreateTask {
+                task: task("plan", "Plan"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::SetTaskParent {
+                task_id: "pack".into(),
+                parent_task_id: Some("plan".into()),
+                at: None,
+            },
+        ]);
+        assert_eq!(state.project_of("socks").unwrap().project_id, "work");
+        assert_eq!(ids(state.task_ancestors("socks")), ["pack", "plan"]);
+    }
+
+    #[test]
+    fn move_and_delete_take_subtasks_along() {
+        let mut state = checklist();
+
+        state.apply_operation(Operation::MoveTask {
+            task_id: "pack".into(),
+            project_id_to: "work".into(),
+            at: None,
+        });
+        assert_eq!(
+            ids(state.projects["work"].tasks.iter().collect()),
+            ["pack", "socks"]
+        );
+        assert!(state.task_ancestors("pack").is_empty());
+
+        state.apply_operation(Operation::DeleteTask {
+            task_id: "trip".into(),
+            at: Some(chrono::Utc::now()),
+        });
+        assert_eq!(
+            ids(state.projects[INBOX_ID].tasks.iter().collect()),
+            ["other"]
+        );
+        assert_eq!(state.trash.tasks[0].subtasks.len(), 1);
+
+        state.apply_operation(Operation::RestoreTask {
+            task_id: "trip".into(),
+        });
+        assert_eq!(ids(state.subtasks_of("trip")), ["book"]);
+    }
+
+    #[test]
+    fn complete_with_subtasks() {
+        let mut state = checklist();
+
+        state.apply_operation(Operation::UpdateTaskDone {
+            task_id: "pack".into(),
+            done: true,
+            at: None,
+            with_subtasks: true,
+            occurrence: None,
+        });
+
+        assert!(state.get_task("pack").unwrap().done);
+        assert!(state.get_task("socks").unwrap().done);
+        assert!(!state.get_task("trip").unwrap().done);
+    }
+
+    #[test]
+    fn undo_restores_nesting_and_subtasks() {
+        let now = chrono::Utc::now();
+
+        for operation in [
+            Operation::MoveTask {
+                task_id: "pack".into(),
+                project_id_to: "work".into(),
+                at: None,
+            },
+            Operation::OutdentTask {
+                task_id: "socks".into(),
+                at: None,
+            },
+            Operation::UpdateTaskDone {
+                task_id: "trip".into(),
+                done: true,
+                at: None,
+                with_subtasks: true,
+                occurrence: None,
+            },
+        ] {
+            let mut state = checklist();
+            let inverse = state.inverse_of(&operation, now);
+            state.apply_operation(operation);
+            state.batch_operations(inverse);
+
+            // Moving back appends, so only the nesting is compared
+            assert_eq!(nesting(&state), nesting(&checklist()));
+        }
+    }
+}
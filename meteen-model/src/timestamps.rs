@@ -0,0 +1,232 @@
+//! When tasks and projects were created and last changed.
+//!
+//! The times come from the `at` of the operations, never from the clock of the replica applying
+//! them, so every replica records the same. An operation only counts as a change when it
+//! actually changed something, and the updated time never moves back, so the result doesn't
+//! depend on the order operations arrive in.
+
+use chrono::{DateTime, Utc};
+
+use crate::{Database, Operation, Project, Task};
+
+/// The task or project an operation edits.
+pub(crate) enum Edited {
+    Task(String),
+    Project(String),
+}
+
+/// A copy of the edited item from before the operation.
+pub(crate) enum Snapshot {
+    Task(Option<Task>),
+    Project(Option<ProjectFields>),
+}
+
+/// The fields of a project that operations on it change, leaving out its tasks so taking a
+/// snapshot doesn't copy them.
+#[derive(PartialEq)]
+pub(crate) struct ProjectFields {
+    name: String,
+    parent_id: Option<String>,
+    color: Option<String>,
+    icon: Option<String>,
+    description: Option<String>,
+    sort_key: String,
+}
+
+impl From<&Project> for ProjectFields {
+    fn from(project: &Project) -> Self {
+        ProjectFields {
+            name: project.name.clone(),
+            parent_id: project.parent_id.clone(),
+            color: project.color.clone(),
+            icon: project.icon.clone(),
+            description: project.description.clone(),
+            sort_key: project.sort_key.clone(),
+        }
+    }
+}
+
+impl Operation {
+    /// What the operation edits and when, for operations that carry a time.
+    pub(crate) fn edited(&self) -> Option<(Edited, DateTime<Utc>)> {
+        let task = |task_id: &String, at: &Option<DateTime<Utc>>| {
+            at.map(|at| (Edited::Task(task_id.clone()), at))
+        };
+        let project = |project_id: &String, at: &Option<DateTime<Utc>>| {
+            at.map(|at| (Edited::Project(project_id.clone()), at))
+        };
+
+        match self {
+            Operation::CreateTask { task: new, at, .. } => task(&new.task_id, at),
+            Operation::UpdateTaskSummary { task_id, at, .. }
+            | Operation::UpdateTaskDescription { task_id, at, .. }
+            | Operation::UpdateTaskDone { task_id, at, .. }
+            | Operation::UpdateTaskScheduled { task_id, at, .. }
+            | Operation::UpdateTaskDeadline { task_id, at, .. }
+            | Operation::UpdateTaskPriority { task_id, at, .. }
+            | Operation::UpdateTaskRecurrence { task_id, at, .. }
+            | Operation::MoveTask { task_id, at, .. }
+            | Operation::IndentTask { task_id, at }
+            | Operation::OutdentTask { task_id, at }
+            | Operation::SetTaskParent { task_id, at, .. }
+            | Operation::AddTaskLabel { task_id, at, .. }
+            | Operation::RemoveTaskLabel { task_id, at, .. } => task(task_id, at),
+            Operation::CreateProject { project: new, at } => project(&new.project_id, at),
+            Operation::MoveProject { project_id, at, .. }
+            | Operation::RenameProject { project_id, at, .. }
+            | Operation::UpdateProjectColor { project_id, at, .. }
+            | Operation::UpdateProjectIcon { project_id, at, .. }
+            | Operation::UpdateProjectDescription { project_id, at, .. } => project(project_id, at),
+            _ => None,
+        }
+    }
+}
+
+impl Database {
+    pub(crate) fn snapshot(&self, edited: &Edited) -> Snapshot {
+        match edited {
+            Edited::Task(task_id) => Snapshot::Task(self.editable_task(task_id).cloned()),
+            Edited::Project(project_id) => {
+                Snapshot::Project(self.editable_project(project_id).map(ProjectFields::from))
+            }
+        }
+    }
+
+    /// Records the time of an operation on the item it edited, given the item from before.
+    pub(crate) fn touch(&mut self, edited: &Edited, before: Snapshot, at: DateTime<Utc>) {
+        match (edited, before) {
+            (Edited::Task(task_id), Snapshot::Task(before)) => {
+                let Some(task) = self.editable_task_mut(task_id) else {
+                    return;
+                };
+                if before.as_ref() == Some(task) {
+                    return;
+                }
+                if before.is_none() {
+                    task.created_at = Some(at);
+                }
+                task.updated_at = task.updated_at.max(Some(at));
+            }
+            (Edited::Project(project_id), Snapshot::Project(before)) => {
+                let Some(project) = self.editable_project_mut(project_id) else {
+                    return;
+                };
+                if before.as_ref() == Some(&ProjectFields::from(&*project)) {
+                    return;
+                }
+                if before.is_none() {
+                    project.created_at = Some(at);
+                }
+                project.updated_at = project.updated_at.max(Some(at));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Task {
+    /// Tasks completed since `since`, including recurring tasks whose last occurrence was.
+    pub fn completed_since(&self, since: DateTime<Utc>) -> bool {
+        self.completed_at
+            .is_some_and(|completed_at| completed_at >= since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::tests::{project, task};
+    use crate::{Database, Operation};
+
+    fn at(day: u32) -> Option<DateTime<Utc>> {
+        Some(Utc.with_ymd_and_hms(2024, 10, day, 12, 0, 0).unwrap())
+    }
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: at(1),
+            },
+            Operation::CreateTask {
+                task: task("report", "Write report"),
+                project_id: Some("work".into()),
+                at: at(2),
+            },
+        ]);
+        state
+    }
+
+    #[test]
+    fn timestamps_follow_operations() {
+        let mut state = filled();
+        let task = state.get_task("report").unwrap();
+        assert_eq!((task.created_at, task.updated_at), (at(2), at(2)));
+        assert_eq!(state.projects["work"].created_at, at(1));
+
+        state.batch_operations(vec![
+            Operation::UpdateTaskSummary {
+                task_id: "report".into(),
+                summary: "Write the report".into(),
+                at: at(4),
+            },
+            // Changes nothing, so it isn't an update
+            Operation::UpdateTaskSummary {
+                task_id: "report".into(),
+                summary: "Write the report".into(),
+                at: at(5),
+            },
+            Operation::UpdateTaskDone {
+                task_id: "report".into(),
+                done: true,
+                at: at(6),
+                with_subtasks: false,
+                occurrence: None,
+            },
+            Operation::RenameProject {
+                project_id: "work".into(),
+                name: "Job".into(),
+                at: at(3),
+            },
+        ]);
+
+        let task = state.get_task("report").unwrap();
+        assert_eq!((task.created_at, task.updated_at), (at(2), at(6)));
+        assert!(task.completed_since(at(6).unwrap()));
+        assert_eq!(state.projects["work"].updated_at, at(3));
+
+        state.apply_operation(Operation::UpdateTaskDone {
+            task_id: "report".into(),
+            done: false,
+            at: at(7),
+            with_subtasks: false,
+            occurrence: None,
+        });
+        assert_eq!(state.get_task("report").unwrap().completed_at, None);
+    }
+
+    #[test]
+    fn sync_keeps_latest_update() {
+        let client_a_ops = vec![Operation::UpdateTaskPriority {
+            task_id: "report".into(),
+            priority: crate::Priority::High,
+            at: at(9),
+        }];
+        let client_b_ops = vec![Operation::UpdateTaskSummary {
+            task_id: "report".into(),
+            summary: "Report".into(),
+            at: at(8),
+        }];
+
+        for ops in [
+            [client_a_ops.clone(), client_b_ops.clone()].concat(),
+            [client_b_ops, client_a_ops].concat(),
+        ] {
+            let mut state = filled();
+            state.batch_operations(ops);
+            assert_eq!(state.get_task("report").unwrap().updated_at, at(9));
+        }
+    }
+}
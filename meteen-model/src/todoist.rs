@@ -0,0 +1,576 @@
+//! Importing from Todoist, either a backup of every project as returned by its sync API, or the
+//! CSV export of a single project.
+//!
+//! Sections have no counterpart here, so each section becomes a subproject of its project.
+//! Todoist's p1 to p4 become [`Priority::Urgent`] to [`Priority::Low`], its due date becomes the
+//! scheduled date and its deadline the deadline. Labels are matched by name.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    ids,
+    import::{self, Import, ImportError, ImportedTask, Importer},
+    Database, DateOrDateTime, Priority, INBOX_ID,
+};
+
+/// Fields that only matter to Todoist's own bookkeeping, these aren't reported as unmapped.
+const BOOKKEEPING: &[&str] = &[
+    // The language of a date only matters to reading its text
+    "lang",
+    "sync_token",
+    "full_sync",
+    "temp_id_mapping",
+    "v2_id",
+    "v2_project_id",
+    "v2_section_id",
+    "v2_parent_id",
+    "user_id",
+    "added_by_uid",
+    "sync_id",
+    "is_deleted",
+    "updated_at",
+    "day_order",
+    "section_order",
+];
+
+#[derive(Deserialize)]
+struct Backup {
+    #[serde(default)]
+    projects: Vec<TodoistProject>,
+    #[serde(default)]
+    sections: Vec<Section>,
+    #[serde(default)]
+    items: Vec<Item>,
+    #[serde(default)]
+    notes: Vec<Note>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct TodoistProject {
+    id: String,
+    name: String,
+    parent_id: Option<String>,
+    #[serde(default)]
+    child_order: i64,
+    #[serde(default, alias = "is_inbox_project")]
+    inbox_project: bool,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Section {
+    id: String,
+    name: String,
+    project_id: String,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    id: String,
+    content: String,
+    #[serde(default)]
+    description: String,
+    project_id: String,
+    section_id: Option<String>,
+    parent_id: Option<String>,
+    #[serde(default)]
+    child_order: i64,
+    /// From 1 for p4 to 4 for p1
+    #[serde(default = "lowest_priority")]
+    priority: u8,
+    due: Option<Due>,
+    deadline: Option<Due>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    checked: bool,
+    added_at: Option<DateTime<Utc>>,
+    completed_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Due {
+    date: String,
+    #[serde(default)]
+    string: String,
+    #[serde(default)]
+    is_recurring: bool,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Note {
+    item_id: String,
+    content: String,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+fn lowest_priority() -> u8 {
+    1
+}
+
+/// Reads a Todoist backup, as returned by a full sync of its sync API.
+pub fn import_json(db: &Database, json: &str, now: DateTime<Utc>) -> Result<Import, ImportError> {
+    let backup: Backup =
+        serde_json::from_str(json).map_err(|e| ImportError::InvalidJson(e.to_string()))?;
+    let mut importer = Importer::new(db, now);
+
+    report_other(&mut importer, None, &backup.other);
+    let projects: HashMap<&str, &TodoistProject> = backup
+        .projects
+        .iter()
+        .map(|project| (project.id.as_str(), project))
+        .collect();
+    let paths: HashMap<&str, Option<Vec<String>>> = backup
+        .projects
+        .iter()
+        .map(|project| {
+            report_other(&mut importer, Some("projects"), &project.other);
+            (project.id.as_str(), project_path(&projects, project))
+        })
+        .collect();
+    let sections: HashMap<&str, &Section> = backup
+        .sections
+        .iter()
+        .map(|section| {
+            report_other(&mut importer, Some("sections"), &section.other);
+            (section.id.as_str(), section)
+        })
+        .collect();
+
+    let mut notes: HashMap<&str, Vec<&str>> = HashMap::new();
+    for note in &backup.notes {
+        report_other(&mut importer, Some("notes"), &note.other);
+        notes
+            .entry(note.item_id.as_str())
+            .or_default()
+            .push(note.content.as_str());
+    }
+
+    // Tasks get new ids, so subtasks have to be pointed at the new id of their parent
+    let task_ids: HashMap<&str, String> = backup
+        .items
+        .iter()
+        .map(|item| (item.id.as_str(), ids::new_id()))
+        .collect();
+
+    let mut items: Vec<&Item> = backup.items.iter().collect();
+    items.sort_by_key(|item| {
+        let project = projects.get(item.project_id.as_str());
+        (project.map(|project| project.child_order), item.child_order)
+    });
+
+    let mut tasks = vec![];
+    for item in items {
+        report_other(&mut importer, Some("items"), &item.other);
+
+        let mut task = import::new_task(task_ids[item.id.as_str()].clone());
+        task.summary = item.content.clone();
+        let mut description = vec![item.description.as_str()];
+        description.extend(notes.get(item.id.as_str()).into_iter().flatten());
+        description.retain(|paragraph| !paragraph.is_empty());
+        if !description.is_empty() {
+            task.description = Some(description.join("\n\n"));
+        }
+        task.priority = priority(5 - item.priority.clamp(1, 4));
+        task.parent_task_id = item
+            .parent_id
+            .as_ref()
+            .and_then(|parent_id| task_ids.get(parent_id.as_str()).cloned());
+        task.labels = item
+            .labels
+            .iter()
+            .map(|name| importer.label(name.clone()))
+            .collect();
+        task.done = item.checked;
+        task.completed_at = item.completed_at;
+        task.created_at = item.added_at;
+
+        if let Some(due) = &item.due {
+            report_other(&mut importer, Some("items.due"), &due.other);
+            task.scheduled = read_date(&mut importer, "due", due);
+        }
+        if let Some(deadline) = &item.deadline {
+            report_other(&mut importer, Some("items.deadline"), &deadline.other);
+            task.deadline = read_date(&mut importer, "deadline", deadline);
+        }
+
+        let section = item
+            .section_id
+            .as_ref()
+            .and_then(|section_id| sections.get(section_id.as_str()));
+        let project_id = section.map_or(&item.project_id, |section| &section.project_id);
+        let mut project_path = paths.get(project_id.as_str()).cloned().flatten();
+        if let Some(section) = section {
+            // Sections of the inbox become projects in our inbox
+            let mut path = project_path.unwrap_or_else(|| vec![db.projects[INBOX_ID].name.clone()]);
+            path.push(section.name.clone());
+            project_path = Some(path);
+        }
+
+        tasks.push(ImportedTask { task, project_path });
+    }
+
+    importer.create_tasks(tasks);
+    Ok(importer.finish())
+}
+
+/// The path of a Todoist project, or `None` for its inbox.
+fn project_path(
+    projects: &HashMap<&str, &TodoistProject>,
+    project: &TodoistProject,
+) -> Option<Vec<String>> {
+    if project.inbox_project {
+        return None;
+    }
+
+    let mut path = vec![project.name.clone()];
+    let mut parent_id = project.parent_id.as_deref();
+    // Stopping at the number of projects keeps a cycle from looping forever
+    while let Some(parent) = parent_id.and_then(|parent_id| projects.get(parent_id)) {
+        if path.len() > projects.len() {
+            break;
+        }
+        path.push(parent.name.clone());
+        parent_id = parent.parent_id.as_deref();
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Reads the date of a due date or deadline, reporting what of it can't be imported.
+fn read_date(importer: &mut Importer, field: &str, due: &Due) -> Option<DateOrDateTime> {
+    if due.is_recurring {
+        importer.unmapped(format!("{}: {}", field, due.string));
+    }
+    let date = import::parse_date(&due.date);
+    if date.is_none() {
+        importer.unmapped(format!("{}: {}", field, due.date));
+    }
+    date
+}
+
+/// Reports the fields of a Todoist object that aren't imported and aren't empty either.
+fn report_other(importer: &mut Importer, kind: Option<&str>, other: &BTreeMap<String, Value>) {
+    for (key, value) in other {
+        let empty = match value {
+            Value::Null => true,
+            Value::Bool(value) => !value,
+            Value::String(value) => value.is_empty(),
+            Value::Array(values) => values.is_empty(),
+            Value::Object(values) => values.is_empty(),
+            Value::Number(_) => false,
+        };
+        if !empty && !BOOKKEEPING.contains(&key.as_str()) {
+            importer.unmapped(match kind {
+                Some(kind) => format!("{}.{}", kind, key),
+                None => key.clone(),
+            });
+        }
+    }
+}
+
+/// Todoist's p1 to p4.
+fn priority(p: u8) -> Priority {
+    match p {
+        1 => Priority::Urgent,
+        2 => Priority::High,
+        3 => Priority::Standard,
+        _ => Priority::Low,
+    }
+}
+
+/// Reads the CSV export of a single Todoist project, whose tasks go to the project at `project`,
+/// a path like `Work/Clients`.
+///
+/// Labels are taken from the `@label`s in the content of a task and comments are added to its
+/// description. Dates are only read when they are written as dates, like `2024-10-01`, rather
+/// than as text like `every monday`.
+pub fn import_csv(
+    db: &Database,
+    csv: &str,
+    project: &str,
+    now: DateTime<Utc>,
+) -> Result<Import, ImportError> {
+    let mut rows = import::parse_csv(csv, ',')?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let required = |name: &str| column(name).ok_or_else(|| ImportError::MissingColumn(name.into()));
+
+    let kind = required("TYPE")?;
+    let content = required("CONTENT")?;
+    let description = column("DESCRIPTION");
+    let priority_column = column("PRIORITY");
+    let indent = column("INDENT");
+    let date = column("DATE");
+    let deadline = column("DEADLINE");
+    // The language of the dates only matters to reading the text of a date
+    let mapped = [
+        Some(kind),
+        Some(content),
+        description,
+        priority_column,
+        indent,
+        date,
+        deadline,
+        column("DATE_LANG"),
+        column("DEADLINE_LANG"),
+    ];
+
+    let project_path: Vec<String> = project
+        .split('/')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    let mut section_path = None;
+    let mut importer = Importer::new(db, now);
+    let mut tasks: Vec<ImportedTask> = vec![];
+    // The ids of the last task at each indent, to find the parents of subtasks
+    let mut parents: Vec<String> = vec![];
+
+    for row in rows {
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|column| row.get(column))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        match value(Some(kind)).unwrap_or_default() {
+            "section" => {
+                let mut path = project_path.clone();
+                path.push(value(Some(content)).unwrap_or_default().into());
+                section_path = Some(path);
+                parents.clear();
+                continue;
+            }
+            "note" => {
+                let (Some(task), Some(note)) = (tasks.last_mut(), value(Some(content))) else {
+                    continue;
+                };
+                let description = task.task.description.get_or_insert_with(String::new);
+                if !description.is_empty() {
+                    description.push_str("\n\n");
+                }
+                description.push_str(note);
+                continue;
+            }
+            "task" => {}
+            // Rows like `meta` only hold how Todoist shows the project
+            _ => continue,
+        }
+
+        for (index, (column, value)) in header.iter().zip(&row).enumerate() {
+            if !value.trim().is_empty() && !mapped.contains(&Some(index)) {
+                importer.unmapped(column.clone());
+            }
+        }
+
+        let mut task = import::new_task(ids::new_id());
+        let (summary, labels): (Vec<&str>, Vec<&str>) = value(Some(content))
+            .unwrap_or_default()
+            .split(' ')
+            .partition(|word| !(word.len() > 1 && word.starts_with('@')));
+        task.summary = summary.join(" ").trim().into();
+        task.labels = labels
+            .into_iter()
+            .map(|label| importer.label(label[1..].into()))
+            .collect();
+        task.description = value(description).map(Into::into);
+
+        if let Some(value) = value(priority_column) {
+            match value.parse() {
+                Ok(p @ 1..=4) => task.priority = priority(p),
+                _ => importer.unmapped(format!("PRIORITY: {}", value)),
+            }
+        }
+        for (column, name, date) in [
+            (date, "DATE", &mut task.scheduled),
+            (deadline, "DEADLINE", &mut task.deadline),
+        ] {
+            if let Some(value) = value(column) {
+                *date = import::parse_date(value);
+                if date.is_none() {
+                    importer.unmapped(format!("{}: {}", name, value));
+                }
+            }
+        }
+
+        let depth = value(indent)
+            .and_then(|indent| indent.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(1);
+        parents.truncate(depth - 1);
+        task.parent_task_id = parents.last().cloned();
+        parents.push(task.task_id.clone());
+
+        let path = section_path.clone().unwrap_or_else(|| project_path.clone());
+        tasks.push(ImportedTask {
+            task,
+            project_path: Some(path).filter(|path| !path.is_empty()),
+        });
+    }
+
+    importer.create_tasks(tasks);
+    Ok(importer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::{Database, DateOrDateTime, Priority, INBOX_ID};
+
+    #[test]
+    pub fn import_backup() {
+        let json = r#"{
+            "sync_token": "abc",
+            "projects": [
+                {"id": "1", "name": "Inbox", "inbox_project": true, "child_order": 0},
+                {"id": "2", "name": "Work", "child_order": 1, "color": "berry_red"},
+                {"id": "3", "name": "Clients", "parent_id": "2", "child_order": 0}
+            ],
+            "sections": [{"id": "10", "name": "Waiting", "project_id": "3"}],
+            "items": [
+                {
+                    "id": "21", "content": "Chase invoice", "project_id": "3",
+                    "section_id": "10", "priority": 1,
+                    "due": {"date": "2024-10-02", "string": "every wednesday", "is_recurring": true}
+                },
+                {
+                    "id": "20", "content": "Invoices", "project_id": "3", "priority": 4,
+                    "labels": ["money"], "responsible_uid": "42",
+                    "deadline": {"date": "2024-10-31"}
+                },
+                {"id": "23", "content": "Milk", "project_id": "1", "parent_id": "22"},
+                {"id": "22", "content": "Groceries", "project_id": "1", "priority": 3}
+            ],
+            "notes": [{"item_id": "20", "content": "Ask Sam first"}],
+            "filters": [{"name": "Today"}]
+        }"#;
+        let mut db = Database::new();
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+
+        let import = super::import_json(&db, json, now).unwrap();
+        db.batch_operations(import.operations);
+
+        assert_eq!(
+            import.unmapped,
+            [
+                "due: every wednesday",
+                "filters",
+                "items.responsible_uid",
+                "projects.color"
+            ]
+        );
+        let find = |summary: &str| {
+            db.all_tasks()
+                .into_iter()
+                .find(|task| task.summary == summary)
+                .unwrap()
+                .clone()
+        };
+
+        let invoices = find("Invoices");
+        let clients = db.project_of(&invoices.task_id).unwrap();
+        assert_eq!(db.project_path(&clients.project_id), ["Work", "Clients"]);
+        assert_eq!(invoices.priority, Priority::Urgent);
+        assert_eq!(invoices.description.as_deref(), Some("Ask Sam first"));
+        assert_eq!(db.labels[invoices.labels.first().unwrap()].name, "money");
+        assert_eq!(
+            invoices.deadline,
+            Some(DateOrDateTime::Date(
+                NaiveDate::from_ymd_opt(2024, 10, 31).unwrap()
+            ))
+        );
+
+        let chase = find("Chase invoice");
+        let waiting = db.project_of(&chase.task_id).unwrap();
+        assert_eq!(
+            db.project_path(&waiting.project_id),
+            ["Work", "Clients", "Waiting"]
+        );
+        assert_eq!(chase.priority, Priority::Low);
+        assert_eq!(
+            chase.scheduled,
+            Some(DateOrDateTime::Date(
+                NaiveDate::from_ymd_opt(2024, 10, 2).unwrap()
+            ))
+        );
+
+        let groceries = find("Groceries");
+        assert_eq!(
+            db.project_of(&groceries.task_id).unwrap().project_id,
+            INBOX_ID
+        );
+        assert_eq!(groceries.priority, Priority::High);
+        assert_eq!(find("Milk").parent_task_id, Some(groceries.task_id));
+    }
+
+    #[test]
+    pub fn import_project_csv() {
+        let csv =
+            "TYPE,CONTENT,DESCRIPTION,PRIORITY,INDENT,AUTHOR,RESPONSIBLE,DATE,DATE_LANG,TIMEZONE\n\
+                   meta,view_style=list,,,,,,,,\n\
+                   task,Plan trip @travel,,1,1,Sam (1),,2024-10-05,en,Europe/Amsterdam\n\
+                   task,Book hotel,\"Near the station, if possible\",2,2,Sam (1),,tomorrow,en,\n\
+                   note,Ask for a quiet room,,,,Sam (1),,,,\n\
+                   ,,,,,,,,,\n\
+                   section,Later,,,,,,,,\n\
+                   task,Write postcards,,4,1,Sam (1),,,en,\n";
+        let mut db = Database::new();
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+
+        let import = super::import_csv(&db, csv, "Personal/Holidays", now).unwrap();
+        db.batch_operations(import.operations);
+
+        assert_eq!(import.unmapped, ["AUTHOR", "DATE: tomorrow", "TIMEZONE"]);
+        let find = |summary: &str| {
+            db.all_tasks()
+                .into_iter()
+                .find(|task| task.summary == summary)
+                .unwrap()
+                .clone()
+        };
+
+        let trip = find("Plan trip");
+        let holidays = db.project_of(&trip.task_id).unwrap();
+        assert_eq!(
+            db.project_path(&holidays.project_id),
+            ["Personal", "Holidays"]
+        );
+        assert_eq!(trip.priority, Priority::Urgent);
+        assert_eq!(db.labels[trip.labels.first().unwrap()].name, "travel");
+
+        let hotel = find("Book hotel");
+        assert_eq!(hotel.parent_task_id, Some(trip.task_id));
+        assert_eq!(hotel.priority, Priority::High);
+        assert_eq!(hotel.scheduled, None);
+        assert_eq!(
+            hotel.description.as_deref(),
+            Some("Near the station, if possible\n\nAsk for a quiet room")
+        );
+
+        let postcards = find("Write postcards");
+        let later = db.project_of(&postcards.task_id).unwrap();
+        assert_eq!(
+            db.project_path(&later.project_id),
+            ["Personal", "Holidays", "Later"]
+        );
+        assert_eq!(postcards.priority, Priority::Low);
+        assert_eq!(postcards.parent_task_id, None);
+    }
+}
@@ -0,0 +1,488 @@
+//! The [todo.txt](https://github.com/todotxt/todo.txt) format, one task per line:
+//!
+//! ```text
+//! x 2024-10-02 2024-09-30 Send invoice +Work/Clients @money due:2024-10-05 pri:A id:01J9...
+//! (B) Check hours +Work/Clients t:2024-10-01 parent:01J9... id:01J9...
+//! ```
+//!
+//! Priorities are written as `(A)` for urgent to `(D)` for low, or as `pri:` on completed
+//! tasks, and standard tasks have none. `due:` is the deadline, `t:` the scheduled date and
+//! `rec:` the recurrence. Spaces in the names of projects and labels are written as
+//! underscores. Every task carries its id, so an edited file can be imported again to update the
+//! tasks rather than duplicate them. Notes aren't written.
+//!
+//! Words in a summary that would be read as something else, like `@alice`, `due:friday` or an
+//! `x` at the start, are written with a backslash in front, as are words that start with one.
+//! Whitespace in summaries is written as single spaces, so every task stays on its own line.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    ids,
+    import::{self, ImportedTask, Importer},
+    Database, DateOrDateTime, Operation, Priority, Project, Task, INBOX_ID,
+};
+
+/// The keys [`parse_details`] understands.
+const KEYS: &[&str] = &["due", "t", "rec", "pri", "id", "parent"];
+
+/// Writes every task, project by project in the order of the sidebar.
+pub fn export(db: &Database) -> String {
+    let mut out = String::new();
+
+    let mut nodes: Vec<_> = db.project_tree().into_iter().rev().collect();
+    while let Some(node) = nodes.pop() {
+        nodes.extend(node.children.into_iter().rev());
+        let project = node.project;
+
+        for (_, task) in nested_tasks(project) {
+            match (task.done, task.completed_at) {
+                (true, Some(completed_at)) => {
+                    out.push_str(&format!("x {} ", completed_at.format("%Y-%m-%d")));
+                    if let Some(created_at) = task.created_at {
+                        out.push_str(&format!("{} ", created_at.format("%Y-%m-%d")));
+                    }
+                }
+                (true, None) => out.push_str("x "),
+                (false, _) => {
+                    if let Some(letter) = priority_letter(&task.priority) {
+                        out.push_str(&format!("({}) ", letter));
+                    }
+                    if let Some(created_at) = task.created_at {
+                        out.push_str(&format!("{} ", created_at.format("%Y-%m-%d")));
+                    }
+                }
+            }
+
+            write_details(&mut out, db, task);
+            if project.project_id != INBOX_ID {
+                let path: Vec<String> = db
+                    .project_path(&project.project_id)
+                    .into_iter()
+                    .map(import::to_tag)
+                    .collect();
+                out.push_str(&format!(" +{}", path.join("/")));
+            }
+            if task.done {
+                if let Some(letter) = priority_letter(&task.priority) {
+                    out.push_str(&format!(" pri:{}", letter));
+                }
+            }
+            if let Some(parent_task_id) = &task.parent_task_id {
+                out.push_str(&format!(" parent:{}", parent_task_id));
+            }
+            out.push_str(&format!(" id:{}\n", task.task_id));
+        }
+    }
+
+    out
+}
+
+/// The operations that make `db` match a todo.txt file: tasks with an id that exists are
+/// updated, the others are created. Tasks that aren't in the file are left alone. `now` is
+/// when the changes are made.
+pub fn import(db: &Database, text: &str, now: DateTime<Utc>) -> Vec<Operation> {
+    let mut importer = Importer::new(db, now);
+
+    let tasks = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut task = import::new_task(String::new());
+            let mut rest = line.trim();
+
+            if let Some(completed) = rest.strip_prefix("x ") {
+                task.done = true;
+                rest = completed.trim_start();
+                if let Some((completed_at, after)) = leading_date(rest) {
+                    task.completed_at = Some(completed_at);
+                    rest = after;
+                }
+            } else if let Some((priority, after)) = leading_priority(rest) {
+                task.priority = priority;
+                rest = after;
+            }
+            if let Some((created_at, after)) = leading_date(rest) {
+                task.created_at = Some(created_at);
+                rest = after;
+            }
+
+            let project_tag = parse_details(&mut importer, rest, &mut task);
+            if task.task_id.is_empty() {
+                task.task_id = ids::new_id();
+            }
+            ImportedTask {
+                task,
+                project_path: project_tag.map(|tag| importer.tag_path(&tag)),
+            }
+        })
+        .collect();
+
+    importer.create_or_update_tasks(db, tasks);
+    importer.finish().operations
+}
+
+/// The tasks of a project in their manual order, each followed by its subtasks, with how deep
+/// they are nested.
+pub(crate) fn nested_tasks(project: &Project) -> Vec<(usize, &Task)> {
+    let tasks = project.ordered_tasks();
+    let ids: HashSet<&str> = tasks.iter().map(|task| task.task_id.as_str()).collect();
+    let mut nested = vec![];
+    let mut seen = HashSet::new();
+
+    // Tasks whose parent is missing are shown at the top level, like the sidebar does
+    let mut stack: Vec<(usize, &Task)> = tasks
+        .iter()
+        .rev()
+        .filter(|task| {
+            task.parent_task_id
+                .as_deref()
+                .is_none_or(|parent_task_id| !ids.contains(parent_task_id))
+        })
+        .map(|task| (0, *task))
+        .collect();
+    while let Some((depth, task)) = stack.pop() {
+        if !seen.insert(task.task_id.as_str()) {
+            continue;
+        }
+        nested.push((depth, task));
+        stack.extend(
+            tasks
+                .iter()
+                .rev()
+                .filter(|subtask| subtask.parent_task_id.as_ref() == Some(&task.task_id))
+                .map(|subtask| (depth + 1, *subtask)),
+        );
+    }
+
+    nested
+}
+
+/// Writes the summary of a task followed by its labels, dates and recurrence.
+pub(crate) fn write_details(out: &mut String, db: &Database, task: &Task) {
+    let words: Vec<String> = task
+        .summary
+        .split_whitespace()
+        .enumerate()
+        .map(|(index, word)| match is_misread(word, index == 0) {
+            true => format!("\\{}", word),
+            false => word.into(),
+        })
+        .collect();
+    out.push_str(&words.join(" "));
+
+    let mut labels: Vec<String> = task
+        .labels
+        .iter()
+        .filter_map(|label_id| db.labels.get(label_id))
+        .map(|label| import::to_tag(&label.name))
+        .collect();
+    labels.sort();
+    for label in labels {
+        out.push_str(&format!(" @{}", label));
+    }
+    if let Some(deadline) = &task.deadline {
+        out.push_str(&format!(" due:{}", format_date(deadline)));
+    }
+    if let Some(scheduled) = &task.scheduled {
+        out.push_str(&format!(" t:{}", format_date(scheduled)));
+    }
+    if let Some(recurrence) = &task.recurrence {
+        out.push_str(&format!(" rec:{}", recurrence));
+    }
+}
+
+/// Whether a word of a summary would be read as something else than itself, which for the
+/// `first` word includes the completion mark, a priority and a date that start a line.
+fn is_misread(word: &str, first: bool) -> bool {
+    let tagged = word.len() > 1 && word.starts_with(['+', '@']);
+    let key = word
+        .split_once(':')
+        .is_some_and(|(key, _)| KEYS.contains(&key));
+    let leading = first
+        && (word == "x"
+            || leading_priority(&format!("{} ", word)).is_some()
+            || leading_date(word).is_some());
+
+    tagged || key || leading || word.starts_with('\\')
+}
+
+/// Reads what [`write_details`] writes, together with the `id:`, `parent:` and `pri:` keys,
+/// into `task`. Returns the first `+project` tag. Words that aren't understood, like a key with
+/// an invalid date, are kept in the summary, as are words escaped with a backslash, without it.
+pub(crate) fn parse_details(
+    importer: &mut Importer,
+    text: &str,
+    task: &mut Task,
+) -> Option<String> {
+    let mut project_tag = None;
+    let mut summary = vec![];
+
+    for word in text.split_whitespace() {
+        if let Some(escaped) = word.strip_prefix('\\') {
+            summary.push(escaped);
+            continue;
+        }
+        if let Some(tag) = word.strip_prefix('+').filter(|tag| !tag.is_empty()) {
+            if project_tag.is_none() {
+                project_tag = Some(tag.to_string());
+                continue;
+            }
+        }
+        if let Some(tag) = word.strip_prefix('@').filter(|tag| !tag.is_empty()) {
+            task.labels.insert(importer.tag_label(tag));
+            continue;
+        }
+
+        let understood = match word.split_once(':') {
+            Some(("due", value)) => parse_date(value).map(|date| task.deadline = Some(date)),
+            Some(("t", value)) => parse_date(value).map(|date| task.scheduled = Some(date)),
+            Some(("rec", value)) => value
+                .parse()
+                .ok()
+                .map(|recurrence| task.recurrence = Some(recurrence)),
+            Some(("pri", value)) => priority_from_letter(value).map(|p| task.priority = p),
+            Some(("id", value)) if !value.is_empty() => {
+                task.task_id = value.into();
+                Some(())
+            }
+            Some(("parent", value)) if !value.is_empty() => {
+                task.parent_task_id = Some(value.into());
+                Some(())
+            }
+            _ => None,
+        };
+        if understood.is_none() {
+            summary.push(word);
+        }
+    }
+
+    task.summary = summary.join(" ");
+    project_tag
+}
+
+/// The letter of a priority, standard has none.
+pub(crate) fn priority_letter(priority: &Priority) -> Option<char> {
+    match priority {
+        Priority::Urgent => Some('A'),
+        Priority::High => Some('B'),
+        Priority::Standard => None,
+        Priority::Low => Some('D'),
+    }
+}
+
+/// `A` is urgent, `B` high, `C` standard and anything up to `Z` low.
+fn priority_from_letter(letter: &str) -> Option<Priority> {
+    match letter {
+        "A" => Some(Priority::Urgent),
+        "B" => Some(Priority::High),
+        "C" => Some(Priority::Standard),
+        _ if letter.len() == 1 && letter.chars().all(|c| c.is_ascii_uppercase()) => {
+            Some(Priority::Low)
+        }
+        _ => None,
+    }
+}
+
+/// Splits a priority like `(A)` off the start of a line.
+pub(crate) fn leading_priority(text: &str) -> Option<(Priority, &str)> {
+    let rest = text.strip_prefix('(')?;
+    let (letter, rest) = rest.split_once(") ")?;
+    Some((priority_from_letter(letter)?, rest.trim_start()))
+}
+
+/// Splits a date like `2024-10-01` off the start of a line, as midnight UTC.
+fn leading_date(text: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (date, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    // Unwrap is safe because midnight exists on every day
+    Some((
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+        rest.trim_start(),
+    ))
+}
+
+fn format_date(date: &DateOrDateTime) -> String {
+    match date {
+        DateOrDateTime::Date(date) => date.format("%Y-%m-%d").to_string(),
+        DateOrDateTime::DateTime(datetime) => datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    }
+}
+
+fn parse_date(value: &str) -> Option<DateOrDateTime> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Some(DateOrDateTime::Date(date)),
+        Err(_) => DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|datetime| DateOrDateTime::DateTime(datetime.with_timezone(&Utc))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::tests::{project, task};
+    use crate::{Database, DateOrDateTime, Label, Operation, Priority, Project, Task, INBOX_ID};
+
+    fn example() -> Database {
+        let mut db = Database::new();
+        db.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("side", "Side projects")
+                },
+                at: None,
+            },
+            Operation::CreateLabel {
+                label: Label {
+                    label_id: "waiting".into(),
+                    name: "waiting for".into(),
+                    color: None,
+                },
+            },
+            Operation::CreateTask {
+                task: Task {
+                    priority: Priority::Urgent,
+                    deadline: Some(DateOrDateTime::Date(
+                        NaiveDate::from_ymd_opt(2024, 10, 5).unwrap(),
+                    )),
+                    labels: BTreeSet::from(["waiting".into()]),
+                    created_at: Some(Utc.with_ymd_and_hms(2024, 9, 30, 0, 0, 0).unwrap()),
+                    ..task("invoice", "Send invoice")
+                },
+                project_id: Some("side".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: Task {
+                    done: true,
+                    priority: Priority::Low,
+                    completed_at: Some(Utc.with_ymd_and_hms(2024, 10, 2, 0, 0, 0).unwrap()),
+                    parent_task_id: Some("invoice".into()),
+                    recurrence: Some("FREQ=WEEKLY;BYDAY=TU".parse().unwrap()),
+                    ..task("hours", "Check hours")
+                },
+                project_id: Some("side".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("milk", "Buy milk"),
+                project_id: None,
+                at: None,
+            },
+        ]);
+        db
+    }
+
+    #[test]
+    pub fn export_tasks() {
+        let text = super::export(&example());
+
+        assert_eq!(
+            text,
+            "Buy milk id:milk\n\
+             (A) 2024-09-30 Send invoice @waiting_for due:2024-10-05 +Work/Side_projects id:invoice\n\
+             x 2024-10-02 Check hours rec:FREQ=WEEKLY;BYDAY=TU +Work/Side_projects pri:D parent:invoice id:hours\n"
+        );
+    }
+
+    #[test]
+    pub fn import_edits() {
+        let mut db = example();
+        let now = Utc.with_ymd_and_hms(2024, 10, 3, 0, 0, 0).unwrap();
+
+        // Unchanged, nothing happens
+        assert!(super::import(&db, &super::export(&db), now).is_empty());
+
+        let text = super::export(&db)
+            .replace(
+                "Buy milk",
+                "(B) Buy oat milk due:2024-10-04 +Work @waiting_for",
+            )
+            .replace("(A) 2024-09-30", "x 2024-10-03 2024-09-30")
+            .replace("+Work/Side_projects pri:D parent:invoice", "+Home")
+            + "Call mom t:2024-10-06 +Home see:notes\n";
+        db.batch_operations(super::import(&db, &text, now));
+
+        let milk = db.get_task("milk").unwrap();
+        assert_eq!(milk.summary, "Buy oat milk");
+        assert_eq!(milk.priority, Priority::High);
+        assert_eq!(
+            milk.deadline,
+            Some(DateOrDateTime::Date(
+                NaiveDate::from_ymd_opt(2024, 10, 4).unwrap()
+            ))
+        );
+        assert_eq!(milk.labels, BTreeSet::from(["waiting".into()]));
+        assert_eq!(db.project_of("milk").unwrap().project_id, "work");
+
+        assert!(db.get_task("invoice").unwrap().done);
+
+        let hours = db.get_task("hours").unwrap();
+        assert_eq!(hours.parent_task_id, None);
+        assert_eq!(hours.priority, Priority::Standard);
+        let home = db.project_of("hours").unwrap();
+        assert_eq!(home.name, "Home");
+        assert_eq!(home.parent_id, None);
+
+        let call = db
+            .all_tasks()
+            .into_iter()
+            .find(|task| task.summary == "Call mom see:notes")
+            .unwrap();
+        assert_eq!(db.project_of(&call.task_id).unwrap().name, "Home");
+        assert_eq!(db.all_tasks().len(), 4);
+        assert_ne!(db.project_of(&call.task_id).unwrap().project_id, INBOX_ID);
+    }
+
+    #[test]
+    pub fn summaries_round_trip() {
+        let summaries = [
+            "x ray the @alice +launch",
+            "(A) team due:friday id:other",
+            "2024-10-01 retro \\o/ \\\\server",
+            "Ask (B) about pri:A and t:",
+        ];
+        let mut db = Database::new();
+        db.batch_operations(
+            summaries
+                .iter()
+                .enumerate()
+                .map(|(index, summary)| Operation::CreateTask {
+                    task: task(&index.to_string(), summary),
+                    project_id: None,
+                    at: None,
+                })
+                .collect(),
+        );
+        let now = Utc.with_ymd_and_hms(2024, 10, 3, 0, 0, 0).unwrap();
+
+        let text = super::export(&db);
+        assert!(text.starts_with("\\x ray the \\@alice \\+launch id:0\n"));
+        assert!(super::import(&db, &text, now).is_empty());
+
+        let mut imported = Database::new();
+        imported.batch_operations(super::import(&imported, &text, now));
+        for (index, summary) in summaries.iter().enumerate() {
+            let task = imported.get_task(&index.to_string()).unwrap();
+            assert_eq!(task.summary, *summary);
+            assert_eq!(task.priority, Priority::Standard);
+            assert!(task.labels.is_empty() && !task.done);
+            assert_eq!((task.deadline.clone(), task.created_at), (None, None));
+        }
+        assert_eq!(imported.projects.len(), 1);
+
+        let text = crate::markdown::export(&db);
+        assert!(crate::markdown::import(&db, &text, now).is_empty());
+    }
+}
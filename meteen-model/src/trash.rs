@@ -0,0 +1,538 @@
+//! Deleted tasks and projects, kept around until they are restored or purged.
+//!
+//! Operations carry the time of deletion themselves so every replica purges the same entries.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{index::TrashedAt, Database, Operation, Project, Task, INBOX_ID};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Trash {
+    pub tasks: Vec<TrashedTask>,
+    pub projects: Vec<TrashedProject>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct TrashedTask {
+    pub task: Task,
+    /// The project the task was in, it is restored there if that project still exists
+    pub project_id: String,
+    /// The position of the task within that project
+    #[serde(default)]
+    pub index: usize,
+    /// Everything nested in the task, deleted along with it
+    #[serde(default)]
+    pub subtasks: Vec<Task>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct TrashedProject {
+    /// The deleted project followed by the subprojects deleted along with it, parents before
+    /// their children. Tasks stay inside their projects.
+    pub projects: Vec<Project>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl TrashedProject {
+    pub fn project(&self) -> &Project {
+        // Trashed projects are never created without at least the deleted project itself
+        &self.projects[0]
+    }
+}
+
+impl Operation {
+    /// Dates a deletion at `now` when it carries no time or an earlier one. The server does this
+    /// with its own clock before applying operations, so a deletion from a client that doesn't
+    /// send the time, or whose clock is behind, isn't purged by the sync that brings it in.
+    pub fn date_deletion(&mut self, now: DateTime<Utc>) {
+        match self {
+            Operation::DeleteTask { at, .. } | Operation::DeleteProject { at, .. } => {
+                *at = Some(at.map_or(now, |at| at.max(now)));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Database {
+    pub(crate) fn trash_task(&mut self, task_id: &str, at: DateTime<Utc>) {
+        let project = match self.project_of_mut(task_id) {
+            Ok(project) => project,
+            Err(_) => {
+                tracing::warn!("Tried to delete non-existant task: {}", task_id);
+                return;
+            }
+        };
+
+        // Unwrap is safe because project_of_mut implies that the project contains this task
+        let (index, mut subtree) = project.take_subtree(task_id).unwrap();
+        let project_id = project.project_id.clone();
+        self.unindex_tasks(&subtree);
+        // Subtasks can be anywhere before the task, so everything may have moved
+        self.index_tasks_from(&project_id, 0);
+        let task = subtree.remove(0);
+
+        self.trash.tasks.push(TrashedTask {
+            task,
+            project_id,
+            index,
+            subtasks: subtree,
+            deleted_at: at,
+        });
+        self.trash_index
+            .add_task_entry(&self.trash, self.trash.tasks.len() - 1);
+    }
+
+    pub(crate) fn push_trashed_project(&mut self, trashed: TrashedProject) {
+        self.trash.projects.push(trashed);
+        self.trash_index
+            .add_project_entry(&self.trash, self.trash.projects.len() - 1);
+    }
+
+    /// The most recently trashed copy of a task.
+    pub fn trashed_task(&self, task_id: &str) -> Option<&TrashedTask> {
+        self.trash
+            .tasks
+            .iter()
+            .rfind(|trashed| trashed.task.task_id == task_id)
+    }
+
+    /// The most recently trashed copy of a project.
+    pub fn trashed_project(&self, project_id: &str) -> Option<&TrashedProject> {
+        self.trash
+            .projects
+            .iter()
+            .rfind(|trashed| trashed.project().project_id == project_id)
+    }
+
+    pub(crate) fn restore_task(&mut self, task_id: &str) {
+        let Some(position) = self
+            .trash
+            .tasks
+            .iter()
+            .rposition(|trashed| trashed.task.task_id == task_id)
+        else {
+            tracing::warn!("Tried to restore non-existant task: {}", task_id);
+            return;
+        };
+
+        let trashed = &self.trash.tasks[position];
+        let taken = std::iter::once(&trashed.task)
+            .chain(&trashed.subtasks)
+            .any(|task| self.get_task(&task.task_id).is_some());
+        if taken {
+            tracing::warn!("Tried to restore task that already exists: {}", task_id);
+            return;
+        }
+
+        let TrashedTask {
+            mut task,
+            project_id,
+            index,
+            subtasks,
+            ..
+        } = self.trash.tasks.remove(position);
+        self.reindex_trash();
+
+        let (project_id, index) = match self.projects.contains_key(&project_id) {
+            true => (project_id, index),
+            false => (INBOX_ID.to_string(), usize::MAX),
+        };
+        // The parent may have been deleted or moved elsewhere in the meantime
+        let parent_present = |parent_task_id: &String| {
+            self.project_of(parent_task_id)
+                .is_ok_and(|project| project.project_id == project_id)
+        };
+        if !task.parent_task_id.as_ref().is_some_and(parent_present) {
+            task.parent_task_id = None;
+        }
+
+        // Unwrap is safe because the project exists or is the inbox, which must exist
+        let project = self.projects.get_mut(&project_id).unwrap();
+        let index = index.min(project.tasks.len());
+        project
+            .tasks
+            .splice(index..index, std::iter::once(task).chain(subtasks));
+        self.index_tasks_from(&project_id, index);
+    }
+
+    pub(crate) fn restore_project(&mut self, project_id: &str) {
+        let Some(index) = self
+            .trash
+            .projects
+            .iter()
+            .rposition(|trashed| trashed.project().project_id == project_id)
+        else {
+            tracing::warn!("Tried to restore non-existant project: {}", project_id);
+            return;
+        };
+
+        let taken = |project: &Project| {
+            self.projects.contains_key(&project.project_id)
+                || project
+                    .tasks
+                    .iter()
+                    .any(|task| self.get_task(&task.task_id).is_some())
+        };
+        if self.trash.projects[index].projects.iter().any(taken) {
+            tracing::warn!(
+                "Tried to restore project that conflicts with existing items: {}",
+                project_id
+            );
+            return;
+        }
+
+        let TrashedProject { mut projects, .. } = self.trash.projects.remove(index);
+        self.reindex_trash();
+
+        // The parent may have been deleted in the meantime
+        let root = &mut projects[0];
+        if let Some(ref parent_id) = root.parent_id {
+            if !self.projects.contains_key(parent_id) {
+                root.parent_id = None;
+            }
+        }
+
+        for project in projects {
+            let project_id = project.project_id.clone();
+            self.projects.insert(project_id.clone(), project);
+            self.index_tasks_from(&project_id, 0);
+        }
+    }
+
+    /// Looks a task up among the live and the trashed tasks. Edits are applied to trashed items
+    /// too, so an edit and a concurrent delete give the same result in either order.
+    pub(crate) fn editable_task_mut(&mut self, task_id: &str) -> Option<&mut Task> {
+        if self.get_task(task_id).is_some() {
+            return self.get_task_mut(task_id);
+        }
+
+        match self.trash_index.task(task_id)? {
+            TrashedAt::Task { entry, position } => {
+                let trashed = &mut self.trash.tasks[entry];
+                match position {
+                    0 => Some(&mut trashed.task),
+                    _ => trashed.subtasks.get_mut(position - 1),
+                }
+            }
+            TrashedAt::ProjectTask {
+                entry,
+                project,
+                position,
+            } => self.trash.projects[entry].projects[project]
+                .tasks
+                .get_mut(position),
+        }
+    }
+
+    /// All live and trashed tasks, for changes that have to reach every task.
+    pub(crate) fn all_editable_tasks_mut(&mut self) -> Vec<&mut Task> {
+        let live_tasks = self
+            .projects
+            .values_mut()
+            .flat_map(|project| &mut project.tasks);
+        let trashed_tasks =
+            self.trash.tasks.iter_mut().flat_map(|trashed| {
+                std::iter::once(&mut trashed.task).chain(&mut trashed.subtasks)
+            });
+        let trashed_project_tasks = self
+            .trash
+            .projects
+            .iter_mut()
+            .flat_map(|trashed| &mut trashed.projects)
+            .flat_map(|project| &mut project.tasks);
+
+        live_tasks
+            .chain(trashed_tasks)
+            .chain(trashed_project_tasks)
+            .collect()
+    }
+
+    /// Like [`Database::all_editable_tasks_mut`].
+    pub(crate) fn all_editable_tasks(&self) -> Vec<&Task> {
+        let trashed_tasks = self
+            .trash
+            .tasks
+            .iter()
+            .flat_map(|trashed| std::iter::once(&trashed.task).chain(&trashed.subtasks));
+        let trashed_project_tasks = self
+            .trash
+            .projects
+            .iter()
+            .flat_map(|trashed| &trashed.projects)
+            .flat_map(|project| &project.tasks);
+
+        self.all_tasks()
+            .into_iter()
+            .chain(trashed_tasks)
+            .chain(trashed_project_tasks)
+            .collect()
+    }
+
+    /// A task that operations can still edit, see [`Database::editable_task_mut`].
+    pub(crate) fn editable_task(&self, task_id: &str) -> Option<&Task> {
+        if let Some(task) = self.get_task(task_id) {
+            return Some(task);
+        }
+
+        match self.trash_index.task(task_id)? {
+            TrashedAt::Task { entry, position } => {
+                let trashed = &self.trash.tasks[entry];
+                match position {
+                    0 => Some(&trashed.task),
+                    _ => trashed.subtasks.get(position - 1),
+                }
+            }
+            TrashedAt::ProjectTask {
+                entry,
+                project,
+                position,
+            } => self.trash.projects[entry].projects[project]
+                .tasks
+                .get(position),
+        }
+    }
+
+    /// A project that operations can still edit, see [`Database::editable_task_mut`].
+    pub(crate) fn editable_project(&self, project_id: &str) -> Option<&Project> {
+        self.projects.get(project_id).or_else(|| {
+            let (entry, position) = self.trash_index.project(project_id)?;
+            Some(&self.trash.projects[entry].projects[position])
+        })
+    }
+
+    /// Like [`Database::editable_task_mut`], for projects.
+    pub(crate) fn editable_project_mut(&mut self, project_id: &str) -> Option<&mut Project> {
+        if self.projects.contains_key(project_id) {
+            return self.projects.get_mut(project_id);
+        }
+
+        let (entry, position) = self.trash_index.project(project_id)?;
+        Some(&mut self.trash.projects[entry].projects[position])
+    }
+
+    /// Permanently removes everything that was deleted before `before`.
+    pub(crate) fn purge_trash(&mut self, before: DateTime<Utc>) {
+        let trashed = self.trash.tasks.len() + self.trash.projects.len();
+        self.trash
+            .tasks
+            .retain(|trashed| trashed.deleted_at >= before);
+        self.trash
+            .projects
+            .retain(|trashed| trashed.deleted_at >= before);
+        if self.trash.tasks.len() + self.trash.projects.len() != trashed {
+            self.reindex_trash();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::tests::{project, task};
+    use crate::{Database, DeleteMode, Operation, Project, INBOX_ID};
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 10, day, 12, 0, 0).unwrap()
+    }
+
+    fn filled() -> Database {
+        let mut state = Database::new();
+        state.batch_operations(vec![
+            Operation::CreateProject {
+                project: project("work", "Work"),
+                at: None,
+            },
+            Operation::CreateProject {
+                project: Project {
+                    parent_id: Some("work".into()),
+                    ..project("clients", "Clients")
+                },
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("report", "Write report"),
+                project_id: Some("work".into()),
+                at: None,
+            },
+            Operation::CreateTask {
+                task: task("call", "Call client"),
+                project_id: Some("clients".into()),
+                at: None,
+            },
+        ]);
+        state
+    }
+
+    #[test]
+    fn restore_task_into_original_project() {
+        let mut state = filled();
+        state.apply_operation(Operation::DeleteTask {
+            task_id: "report".into(),
+            at: Some(at(1)),
+        });
+
+        assert!(state.get_task("report").is_none());
+        assert_eq!(state.trash.tasks[0].project_id, "work");
+
+        state.apply_operation(Operation::RestoreTask {
+            task_id: "report".into(),
+        });
+
+        assert_eq!(state.project_of("report").unwrap().project_id, "work");
+        assert!(state.trash.tasks.is_empty());
+    }
+
+    #[test]
+    fn restore_task_of_deleted_project_goes_to_inbox() {
+        let mut state = filled();
+        state.batch_operations(vec![
+            Operation::DeleteTask {
+                task_id: "call".into(),
+                at: Some(at(1)),
+            },
+            Operation::DeleteProject {
+                project_id: "clients".into(),
+                mode: DeleteMode::RefuseNonEmpty,
+                at: Some(at(1)),
+            },
+            Operation::RestoreTask {
+                task_id: "call".into(),
+            },
+        ]);
+
+        assert_eq!(state.project_of("call").unwrap().project_id, INBOX_ID);
+    }
+
+    #[test]
+    fn restore_project_with_subprojects() {
+        let mut state = filled();
+        state.apply_operation(Operation::DeleteProject {
+            project_id: "work".into(),
+            mode: DeleteMode::Cascade,
+            at: Some(at(1)),
+        });
+
+        assert_eq!(state.projects.len(), 1);
+        assert_eq!(state.trash.projects[0].projects.len(), 2);
+
+        state.apply_operation(Operation::RestoreProject {
+            project_id: "work".into(),
+        });
+
+        assert_eq!(state, filled());
+    }
+
+    #[test]
+    fn deletions_are_dated_by_the_server() {
+        let dated = |sent: Option<DateTime<Utc>>| {
+            let mut delete = Operation::DeleteTask {
+                task_id: "report".into(),
+                at: sent,
+            };
+            delete.date_deletion(at(10));
+            match delete {
+                Operation::DeleteTask { at, .. } => at,
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(dated(None), Some(at(10)));
+        assert_eq!(dated(Some(at(1))), Some(at(10)));
+        assert_eq!(dated(Some(at(20))), Some(at(20)));
+
+        // Purging right after applying the deletion keeps it in the trash
+        let mut state = filled();
+        let mut delete = Operation::DeleteTask {
+            task_id: "report".into(),
+            at: None,
+        };
+        delete.date_deletion(at(10));
+        state.batch_operations(vec![delete, Operation::PurgeTrash { before: at(9) }]);
+        assert_eq!(state.trash.tasks.len(), 1);
+    }
+
+    #[test]
+    fn undated_deletions_are_ignored() {
+        let mut state = filled();
+        state.batch_operations(vec![
+            Operation::DeleteTask {
+                task_id: "report".into(),
+                at: None,
+            },
+            Operation::DeleteProject {
+                project_id: "clients".into(),
+                mode: DeleteMode::Cascade,
+                at: None,
+            },
+        ]);
+
+        assert_eq!(state, filled());
+    }
+
+    #[test]
+    fn purge_removes_old_entries() {
+        let mut state = filled();
+        state.batch_operations(vec![
+            Operation::DeleteTask {
+                task_id: "report".into(),
+                at: Some(at(1)),
+            },
+            Operation::DeleteProject {
+                project_id: "clients".into(),
+                mode: DeleteMode::Cascade,
+                at: Some(at(10)),
+            },
+            Operation::PurgeTrash { before: at(5) },
+        ]);
+
+        assert!(state.trash.tasks.is_empty());
+        assert_eq!(state.trash.projects.len(), 1);
+
+        state.apply_operation(Operation::RestoreTask {
+            task_id: "report".into(),
+        });
+        assert!(state.get_task("report").is_none());
+    }
+
+    #[test]
+    fn edits_apply_to_trashed_items() {
+        let mut state = filled();
+        state.batch_operations(vec![
+            Operation::DeleteTask {
+                task_id: "report".into(),
+                at: Some(at(1)),
+            },
+            Operation::DeleteProject {
+                project_id: "clients".into(),
+                mode: DeleteMode::Cascade,
+                at: Some(at(1)),
+            },
+            Operation::UpdateTaskDone {
+                task_id: "report".into(),
+                done: true,
+                at: None,
+                with_subtasks: false,
+                occurrence: None,
+            },
+            Operation::UpdateTaskSummary {
+                task_id: "call".into(),
+                summary: "Call Acme".into(),
+                at: None,
+            },
+            Operation::RenameProject {
+                project_id: "clients".into(),
+                name: "Customers".into(),
+                at: None,
+            },
+        ]);
+
+        assert!(state.trash.tasks[0].task.done);
+        let restored = &state.trash.projects[0].projects[0];
+        assert_eq!(restored.name, "Customers");
+        assert_eq!(restored.tasks[0].summary, "Call Acme");
+    }
+}
@@ -1,10 +1,22 @@
 import { writable, type Writable } from "svelte/store";
 import * as wasm_model from "../meteen-storage-wasm/meteen_storage_wasm";
-import { nanoid } from "nanoid";
 import * as keyval from "idb-keyval";
 
 const DO_JSON = true;
 
+/** The stored vault couldn't be loaded. It is left as it is, nothing is saved over it. */
+export class VaultLoadError extends Error {
+  /** The vault was written by a newer version, which this one has to be updated to read */
+  public updateRequired: boolean;
+
+  constructor(cause: unknown) {
+    const message = cause instanceof Error ? cause.message : String(cause);
+    super(message);
+    this.name = "VaultLoadError";
+    this.updateRequired = message.includes("newer version");
+  }
+}
+
 export default class VaultStorage {
   public projects: Writable<wasm_model.Project[]>;
   private didLoad = false;
@@ -43,11 +55,15 @@ export default class VaultStorage {
       await keyval.get("vault");
 
     if (serialized) {
-      if (DO_JSON) {
-        console.dir(JSON.parse(serialized as string));
-        wasm_model.deserialize_json(serialized as string);
-      } else {
-        wasm_model.deserialize(serialized as Uint8Array);
+      try {
+        if (DO_JSON) {
+          wasm_model.deserialize_json(serialized as string);
+        } else {
+          wasm_model.deserialize(serialized as Uint8Array);
+        }
+      } catch (e) {
+        // Not marking the vault as loaded keeps the empty one from being saved over it
+        throw new VaultLoadError(e);
       }
       this.projects.set(wasm_model.get_all_projects());
     } else {
@@ -67,7 +83,7 @@ export default class VaultStorage {
     scheduledHasTime: boolean,
     priority: number,
   ) {
-    const task = new wasm_model.Task(nanoid());
+    const task = new wasm_model.Task();
     task.summary = name;
     task.priority = new wasm_model.Priority(priority);
 
@@ -2,4 +2,7 @@
 
 pub mod prelude;
 
+pub mod api_token;
+pub mod invite_code;
+pub mod totp_recovery_code;
 pub mod user;
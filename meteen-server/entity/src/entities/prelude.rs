@@ -1,3 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.1
 
+pub use super::api_token::Entity as ApiToken;
+pub use super::invite_code::Entity as InviteCode;
+pub use super::totp_recovery_code::Entity as TotpRecoveryCode;
 pub use super::user::Entity as User;
@@ -12,9 +12,33 @@ pub struct Model {
     pub password_salt: String,
     #[sea_orm(unique)]
     pub vault_id: String,
+    pub is_admin: bool,
+    pub approved: bool,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub totp_failed_attempts: i32,
+    pub totp_locked_until: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+    #[sea_orm(has_many = "super::totp_recovery_code::Entity")]
+    TotpRecoveryCode,
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl Related<super::totp_recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TotpRecoveryCode.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,12 +1,22 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20241020_000002_registration;
+mod m20241021_000003_api_tokens;
+mod m20241022_000004_totp;
+mod m20241023_000005_totp_lockout;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20241020_000002_registration::Migration),
+            Box::new(m20241021_000003_api_tokens::Migration),
+            Box::new(m20241022_000004_totp::Migration),
+            Box::new(m20241023_000005_totp_lockout::Migration),
+        ]
     }
 }
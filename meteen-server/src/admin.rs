@@ -0,0 +1,383 @@
+//! Administrative actions, shared by the `/admin` routes and the `meteen-server admin` CLI.
+
+use color_eyre::eyre::{eyre, Result};
+use entity::{invite_code, prelude::*, user};
+use meteen_model::import::ImportFormat;
+use sea_orm::{
+    prelude::*, ActiveValue::NotSet, IntoActiveModel, QueryOrder, Set, TransactionTrait,
+};
+
+use crate::{
+    routes::{
+        import::{count_tasks, parse_format, ImportReport},
+        two_factor::disable_two_factor,
+    },
+    vaults::{Vaults, VaultsLock},
+};
+
+const USAGE: &str = "Usage: meteen-server admin <command>
+
+Commands:
+    create-invite         Create a single-use invite code
+    list-pending          List accounts that are awaiting approval
+    approve <username>    Approve a pending account
+    reject <username>     Delete a pending account and its vault
+    promote <username>    Give an account admin privileges
+    demote <username>     Take admin privileges away from an account
+    reset-2fa <username>  Turn off two-factor authentication, e.g. after losing all codes
+    fsck <username> [--repair]
+                          Check the vault of an account for damage, and repair it
+    import <username> <file> [format=<format>] [<column>=<name>...]
+                          Add the tasks in a file to the vault of an account, formats are ical
+                          (the default), todoist, todoist-csv with project=<path>, csv,
+                          todo-txt and markdown
+
+Commands that change vaults refuse to run while the server is running.";
+
+/// Takes the lock on the vaults, see [`Vaults::lock`].
+fn claim_vaults(vaults: &Vaults) -> Result<VaultsLock> {
+    vaults
+        .lock()
+        .map_err(|e| eyre!("The vaults are in use ({e}), stop the server before changing vaults"))
+}
+
+pub async fn create_invite(
+    conn: &DatabaseConnection,
+    created_by: Option<String>,
+) -> Result<String, DbErr> {
+    let code: String = nanoid::nanoid!();
+
+    invite_code::ActiveModel {
+        code: Set(code.clone()),
+        created_by: Set(created_by),
+        created_at: NotSet,
+        used_by: Set(None),
+    }
+    .insert(conn)
+    .await?;
+
+    Ok(code)
+}
+
+pub async fn pending_users(conn: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    let users = User::find()
+        .filter(user::Column::Approved.eq(false))
+        .order_by_asc(user::Column::Username)
+        .all(conn)
+        .await?;
+
+    Ok(users.into_iter().map(|user| user.username).collect())
+}
+
+/// Returns false if there is no such user.
+pub async fn approve_user(conn: &DatabaseConnection, username: &str) -> Result<bool, DbErr> {
+    let user = match User::find_by_id(username).one(conn).await? {
+        Some(user) => user,
+        None => return Ok(false),
+    };
+
+    let mut user = user.into_active_model();
+    user.approved = Set(true);
+    user.update(conn).await?;
+
+    Ok(true)
+}
+
+/// Deletes an account that hasn't been approved yet. Returns false if there is no such pending
+/// account, approved accounts are left alone.
+///
+/// Like deleting an account, the user row is removed inside a transaction that is only committed
+/// once the vault is gone, and the vault is put back if the commit fails.
+pub async fn reject_user(
+    conn: &DatabaseConnection,
+    vaults: &mut Vaults,
+    username: &str,
+) -> Result<bool> {
+    let transaction = conn.begin().await?;
+
+    let user = match User::find_by_id(username).one(&transaction).await? {
+        Some(user) if !user.approved => user,
+        _ => {
+            transaction.rollback().await?;
+            return Ok(false);
+        }
+    };
+
+    if let Err(e) = User::delete_by_id(&user.username).exec(&transaction).await {
+        let _ = transaction.rollback().await;
+        return Err(e.into());
+    }
+
+    let backup = vaults.read_vault(&user.vault_id).await.ok();
+
+    if let Err(e) = vaults.delete_vault(&user.vault_id).await {
+        let _ = transaction.rollback().await;
+        return Err(e.into());
+    }
+
+    if let Err(e) = transaction.commit().await {
+        if let Some(backup) = backup {
+            if let Err(e) = vaults.save_vault(&user.vault_id, &backup).await {
+                eprintln!("Failed to restore vault of \"{}\": {}", user.username, e);
+            }
+        }
+        return Err(e.into());
+    }
+
+    Ok(true)
+}
+
+/// Returns false if there is no such user.
+pub async fn set_admin(
+    conn: &DatabaseConnection,
+    username: &str,
+    is_admin: bool,
+) -> Result<bool, DbErr> {
+    let user = match User::find_by_id(username).one(conn).await? {
+        Some(user) => user,
+        None => return Ok(false),
+    };
+
+    let mut user = user.into_active_model();
+    user.is_admin = Set(is_admin);
+    user.update(conn).await?;
+
+    Ok(true)
+}
+
+/// Turns off two-factor authentication for a user. Returns false if there is no such user.
+pub async fn reset_two_factor(conn: &DatabaseConnection, username: &str) -> Result<bool, DbErr> {
+    if User::find_by_id(username).one(conn).await?.is_none() {
+        return Ok(false);
+    }
+
+    disable_two_factor(conn, username).await?;
+
+    Ok(true)
+}
+
+/// Lists what is wrong with the vault of a user, and with `repair` fixes it and saves the
+/// result. Returns `None` if there is no such user. Repairing bypasses the vaults the server has
+/// in memory, see [`claim_vaults`].
+pub async fn check_vault(
+    conn: &DatabaseConnection,
+    vaults: &Vaults,
+    username: &str,
+    repair: bool,
+) -> Result<Option<Vec<String>>> {
+    let user = match User::find_by_id(username).one(conn).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let mut vault = vaults.read_vault(&user.vault_id).await?;
+    let problems = match repair {
+        true => {
+            let problems = vault.repair();
+            vaults.save_vault(&user.vault_id, &vault).await?;
+            problems
+        }
+        false => vault.check(),
+    };
+
+    Ok(Some(problems.iter().map(ToString::to_string).collect()))
+}
+
+/// Adds the tasks in `text` to the vault of a user and saves it. Returns `None` if there is no
+/// such user. This bypasses the vaults the server has in memory, see [`claim_vaults`].
+pub async fn import_into_vault(
+    conn: &DatabaseConnection,
+    vaults: &Vaults,
+    username: &str,
+    format: &ImportFormat,
+    text: &str,
+) -> Result<Option<ImportReport>> {
+    let user = match User::find_by_id(username).one(conn).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let mut vault = vaults.read_vault(&user.vault_id).await?;
+    let import = meteen_model::import::import(&vault, format, text, chrono::Utc::now())?;
+    let imported = count_tasks(&import.operations);
+    vault.batch_operations(import.operations);
+    vaults.save_vault(&user.vault_id, &vault).await?;
+
+    Ok(Some(ImportReport {
+        imported,
+        unmapped: import.unmapped,
+    }))
+}
+
+/// Runs the admin command in `args`, which holds everything after `meteen-server admin`.
+pub async fn run_cli(
+    args: &[String],
+    conn: &DatabaseConnection,
+    vaults: &mut Vaults,
+) -> Result<()> {
+    let username = || {
+        args.get(1)
+            .map(String::as_str)
+            .ok_or(eyre!("Missing username\n\n{USAGE}"))
+    };
+
+    let found = match args.first().map(String::as_str) {
+        Some("create-invite") => {
+            println!("{}", create_invite(conn, None).await?);
+            true
+        }
+        Some("list-pending") => {
+            for username in pending_users(conn).await? {
+                println!("{}", username);
+            }
+            true
+        }
+        Some("approve") => approve_user(conn, username()?).await?,
+        Some("reject") => {
+            let _claim = claim_vaults(vaults)?;
+            if !reject_user(conn, vaults, username()?).await? {
+                return Err(eyre!("No pending account named {}", username()?));
+            }
+            true
+        }
+        Some("promote") => set_admin(conn, username()?, true).await?,
+        Some("demote") => set_admin(conn, username()?, false).await?,
+        Some("reset-2fa") => reset_two_factor(conn, username()?).await?,
+        Some("fsck") => {
+            let repair = args.get(2).map(String::as_str) == Some("--repair");
+            let _claim = match repair {
+                true => Some(claim_vaults(vaults)?),
+                false => None,
+            };
+            match check_vault(conn, vaults, username()?, repair).await? {
+                Some(problems) => {
+                    for problem in &problems {
+                        println!("{}", problem);
+                    }
+                    if problems.is_empty() {
+                        println!("No problems found");
+                    } else if repair {
+                        println!("Repaired {} problems", problems.len());
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+        Some("import") => {
+            let path = args.get(2).ok_or(eyre!("Missing file\n\n{USAGE}"))?;
+            let params = args[3..]
+                .iter()
+                .map(|arg| {
+                    arg.split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .ok_or(eyre!("Expected <key>=<value>, got {arg}\n\n{USAGE}"))
+                })
+                .collect::<Result<_>>()?;
+            let format = parse_format(params).map_err(|e| eyre!(e))?;
+            let text = tokio::fs::read_to_string(path).await?;
+            let _claim = claim_vaults(vaults)?;
+
+            match import_into_vault(conn, vaults, username()?, &format, &text).await? {
+                Some(report) => {
+                    for unmapped in &report.unmapped {
+                        println!("Not imported: {}", unmapped);
+                    }
+                    println!("Imported {} tasks", report.imported);
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => return Err(eyre!("{USAGE}")),
+    };
+
+    if !found {
+        return Err(eyre!("No such user: {}", username()?));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::user;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use tempfile::TempDir;
+
+    use super::{claim_vaults, reject_user};
+    use crate::vaults::Vaults;
+
+    fn pending_user(approved: bool) -> user::Model {
+        user::Model {
+            username: "jorik".into(),
+            password_hash: vec![1, 2, 3],
+            password_salt: "salt".into(),
+            vault_id: "jorik".into(),
+            is_admin: false,
+            approved,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            totp_failed_attempts: 0,
+            totp_locked_until: None,
+        }
+    }
+
+    async fn vaults_with_jorik() -> (TempDir, Vaults) {
+        let dir = TempDir::new().unwrap();
+        tokio::fs::create_dir_all(dir.path().join("vaults"))
+            .await
+            .unwrap();
+        let vaults = Vaults::new(dir.path().to_path_buf());
+        vaults
+            .save_vault("jorik", &meteen_model::Database::new())
+            .await
+            .unwrap();
+        (dir, vaults)
+    }
+
+    #[tokio::test]
+    async fn rejects_pending_user_in_transaction() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending_user(false)]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let (dir, mut vaults) = vaults_with_jorik().await;
+
+        assert!(reject_user(&conn, &mut vaults, "jorik").await.unwrap());
+
+        assert!(!dir.path().join("vaults").join("jorik.mtvault").exists());
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(log.contains("DELETE FROM"));
+        assert!(log.contains("COMMIT"));
+    }
+
+    #[tokio::test]
+    async fn leaves_approved_user_alone() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending_user(true)]])
+            .into_connection();
+        let (dir, mut vaults) = vaults_with_jorik().await;
+
+        assert!(!reject_user(&conn, &mut vaults, "jorik").await.unwrap());
+
+        assert!(dir.path().join("vaults").join("jorik.mtvault").exists());
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(!log.contains("DELETE FROM"));
+    }
+
+    #[tokio::test]
+    async fn vaults_are_only_claimed_once() {
+        let (_dir, vaults) = vaults_with_jorik().await;
+
+        let claim = claim_vaults(&vaults).unwrap();
+        assert!(claim_vaults(&vaults).is_err());
+
+        drop(claim);
+        assert!(claim_vaults(&vaults).is_ok());
+    }
+}
@@ -1,36 +1,80 @@
 use axum::{
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
-use entity::{prelude::*, user};
-use sea_orm::prelude::*;
+use entity::{prelude::*, totp_recovery_code, user};
+use sea_orm::{prelude::*, sea_query::Expr, Condition, IntoActiveModel, Set};
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
+use subtle::ConstantTimeEq;
 
-pub async fn is_valid_login(username: &str, password: &str, conn: DatabaseConnection) -> bool {
-    let user = match User::find_by_id(username).one(&conn).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            eprintln!("Login failed for {}, no such user", username);
-            return false;
+use crate::{cfg::RegistrationPolicy, totp};
+
+/// How many wrong two-factor codes in a row lock two-factor login
+const MAX_WRONG_CODES: i32 = 5;
+/// How long two-factor login stays locked, which with [`MAX_WRONG_CODES`] limits guessing to a
+/// few hundred codes a day
+const LOCKOUT_MINUTES: i64 = 15;
+
+/// What an API token may be used for. Scopes are ordered, every scope includes the ones before it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Reading the vault
+    Read,
+    /// Reading the vault and syncing operations into it
+    Sync,
+    /// Everything, including the admin routes if the user is an admin
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Sync => "sync",
+            Scope::Admin => "admin",
         }
-        Err(e) => {
-            eprintln!(
-                "Login failed for {}, error retrieving user: {}",
-                username, e
-            );
-            return false;
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "read" => Some(Scope::Read),
+            "sync" => Some(Scope::Sync),
+            "admin" => Some(Scope::Admin),
+            _ => None,
         }
-    };
+    }
+}
 
-    let hashed = hash_password(password, &user.password_salt);
-    if hashed == user.password_hash {
-        return true;
+/// Authenticates a request through either the `Login` header or an API token in the
+/// `Authorization: Bearer` header. Logging in with a password grants every scope, a token has to
+/// have been created with at least `scope`.
+pub async fn check_auth_headers(
+    conn: &DatabaseConnection,
+    headers: &HeaderMap,
+    scope: Scope,
+) -> Result<user::Model, Response> {
+    if headers.contains_key("Login") {
+        return check_login_headers(conn, headers).await;
     }
 
-    false
+    match headers.get(header::AUTHORIZATION) {
+        Some(authorization) => check_token(conn, authorization, scope).await,
+        None => Err((
+            StatusCode::UNAUTHORIZED,
+            "Please provide a login header or an API token",
+        )
+            .into_response()),
+    }
 }
 
-pub async fn check_auth_headers(
+/// Authenticates a request through the `Login` header only. Used for account management, which
+/// API tokens must not be able to do.
+///
+/// Users with two-factor authentication enabled also need to send a code from their
+/// authenticator app, or one of their recovery codes, in the `Totp` header.
+pub async fn check_login_headers(
     conn: &DatabaseConnection,
     headers: &HeaderMap,
 ) -> Result<user::Model, Response> {
@@ -73,12 +117,300 @@ pub async fn check_auth_headers(
 
     let hashed_input = hash_password(password, &user.password_salt);
 
-    if hashed_input == user.password_hash {
+    if same_hash(&hashed_input, &user.password_hash) {
+        if !user.approved {
+            return Err(awaiting_approval());
+        }
+
+        if user.totp_enabled {
+            return check_second_factor(conn, user, headers).await;
+        }
+
         return Ok(user);
     }
 
     eprintln!("Wrong pasword for user: \"{}\"", username);
-    return Err((StatusCode::UNAUTHORIZED, "Username or password incorrect").into_response());
+    Err((StatusCode::UNAUTHORIZED, "Username or password incorrect").into_response())
+}
+
+async fn check_token(
+    conn: &DatabaseConnection,
+    authorization: &HeaderValue,
+    scope: Scope,
+) -> Result<user::Model, Response> {
+    let invalid = || (StatusCode::UNAUTHORIZED, "Invalid API token").into_response();
+
+    let token = authorization
+        .to_str()
+        .ok()
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .ok_or_else(|| {
+            (StatusCode::UNAUTHORIZED, "Malformed authorization header").into_response()
+        })?;
+
+    let (id, secret) = token.split_once('.').ok_or_else(invalid)?;
+
+    let token = match ApiToken::find_by_id(id).one(conn).await {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            eprintln!("API token \"{}\" not found", id);
+            return Err(invalid());
+        }
+        Err(e) => {
+            eprintln!("Error while retrieving API token \"{}\": {}", id, e);
+            return Err(invalid());
+        }
+    };
+
+    if !same_hash(&hash_token(secret), &token.token_hash) {
+        eprintln!("Wrong secret for API token \"{}\"", id);
+        return Err(invalid());
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+
+    if token.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err((StatusCode::UNAUTHORIZED, "API token has expired").into_response());
+    }
+
+    if Scope::parse(&token.scope).is_none_or(|token_scope| token_scope < scope) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("API token lacks the {} scope", scope.as_str()),
+        )
+            .into_response());
+    }
+
+    let user = match User::find_by_id(&token.username).one(conn).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(invalid()),
+        Err(e) => {
+            eprintln!(
+                "Error while retrieving owner of API token \"{}\": {}",
+                id, e
+            );
+            return Err(invalid());
+        }
+    };
+
+    let mut token = token.into_active_model();
+    token.last_used_at = Set(Some(now));
+    if let Err(e) = token.update(conn).await {
+        // Not worth failing the request over
+        eprintln!("Failed to update last use of API token \"{}\": {}", id, e);
+    }
+
+    if !user.approved {
+        return Err(awaiting_approval());
+    }
+
+    Ok(user)
+}
+
+async fn check_second_factor(
+    conn: &DatabaseConnection,
+    user: user::Model,
+    headers: &HeaderMap,
+) -> Result<user::Model, Response> {
+    let incorrect = || (StatusCode::UNAUTHORIZED, "Two-factor code incorrect").into_response();
+
+    let code = match headers.get("Totp").and_then(|code| code.to_str().ok()) {
+        Some(code) => code.trim(),
+        None => return Err((StatusCode::UNAUTHORIZED, "Two-factor code required").into_response()),
+    };
+
+    let now = chrono::Utc::now();
+    if user
+        .totp_locked_until
+        .is_some_and(|locked_until| locked_until > now)
+    {
+        eprintln!("Two-factor login of \"{}\" is locked", user.username);
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many incorrect two-factor codes, try again later",
+        )
+            .into_response());
+    }
+
+    let step = user
+        .totp_secret
+        .as_deref()
+        .and_then(|secret| totp::verify(secret, code, now.timestamp()));
+
+    if let Some(step) = step {
+        // Only moving the last used step forward makes sure every code is accepted once, even
+        // when two requests race with the same code
+        let claimed = User::update_many()
+            .col_expr(user::Column::TotpLastStep, Expr::value(step))
+            .col_expr(user::Column::TotpFailedAttempts, Expr::value(0))
+            .filter(user::Column::Username.eq(&user.username))
+            .filter(
+                Condition::any()
+                    .add(user::Column::TotpLastStep.is_null())
+                    .add(user::Column::TotpLastStep.lt(step)),
+            )
+            .exec(conn)
+            .await;
+
+        return match claimed {
+            Ok(result) if result.rows_affected == 1 => Ok(user),
+            Ok(_) => {
+                eprintln!("Reused two-factor code for \"{}\"", user.username);
+                Err((StatusCode::UNAUTHORIZED, "Two-factor code was already used").into_response())
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to store two-factor step of \"{}\": {}",
+                    user.username, e
+                );
+                Err(incorrect())
+            }
+        };
+    }
+
+    // Recovery codes are deleted when used, so they only work once
+    let used = TotpRecoveryCode::delete_many()
+        .filter(totp_recovery_code::Column::Username.eq(&user.username))
+        .filter(totp_recovery_code::Column::CodeHash.eq(hash_token(code)))
+        .exec(conn)
+        .await;
+
+    match used {
+        Ok(result) if result.rows_affected > 0 => {
+            let reset = User::update_many()
+                .col_expr(user::Column::TotpFailedAttempts, Expr::value(0))
+                .filter(user::Column::Username.eq(&user.username))
+                .exec(conn)
+                .await;
+            if let Err(e) = reset {
+                eprintln!(
+                    "Failed to reset wrong two-factor codes of \"{}\": {}",
+                    user.username, e
+                );
+            }
+            Ok(user)
+        }
+        Ok(_) => {
+            eprintln!("Wrong two-factor code for \"{}\"", user.username);
+            if let Err(e) = record_wrong_code(conn, &user.username, now).await {
+                eprintln!(
+                    "Failed to count wrong two-factor code of \"{}\": {}",
+                    user.username, e
+                );
+            }
+            Err(incorrect())
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to check recovery codes of \"{}\": {}",
+                user.username, e
+            );
+            Err(incorrect())
+        }
+    }
+}
+
+/// Counts a wrong two-factor code, and locks two-factor login for a while once there have been
+/// too many in a row, so the codes can't be guessed. The count is increased in the database
+/// rather than set, so concurrent guesses are all counted.
+async fn record_wrong_code(
+    conn: &DatabaseConnection,
+    username: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), DbErr> {
+    let counted = User::update_many()
+        .col_expr(
+            user::Column::TotpFailedAttempts,
+            Expr::col(user::Column::TotpFailedAttempts).add(1),
+        )
+        .filter(user::Column::Username.eq(username))
+        .exec_with_returning(conn)
+        .await?;
+
+    if counted
+        .first()
+        .is_some_and(|user| user.totp_failed_attempts >= MAX_WRONG_CODES)
+    {
+        eprintln!("Locking two-factor login of \"{}\"", username);
+        let locked_until = (now + chrono::Duration::minutes(LOCKOUT_MINUTES)).fixed_offset();
+        User::update_many()
+            .col_expr(user::Column::TotpLockedUntil, Expr::value(locked_until))
+            .col_expr(user::Column::TotpFailedAttempts, Expr::value(0))
+            .filter(user::Column::Username.eq(username))
+            .exec(conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn awaiting_approval() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        "Account is awaiting approval by an admin",
+    )
+        .into_response()
+}
+
+/// Like [`check_auth_headers`], but additionally requires the user to be an admin.
+pub async fn check_admin_headers(
+    conn: &DatabaseConnection,
+    headers: &HeaderMap,
+) -> Result<user::Model, Response> {
+    let user = check_auth_headers(conn, headers, Scope::Admin).await?;
+
+    if !user.is_admin {
+        eprintln!("User \"{}\" tried to use an admin route", user.username);
+        return Err((StatusCode::FORBIDDEN, "Admin privileges required").into_response());
+    }
+
+    Ok(user)
+}
+
+/// Usernames double as vault file names, so they are restricted to a safe set of characters.
+pub fn check_username_policy(username: &str) -> Result<(), &'static str> {
+    if username.len() < 3 || username.len() > 32 {
+        return Err("Username must be between 3 and 32 characters long");
+    }
+
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    if !username.chars().all(allowed) || username.starts_with('.') {
+        return Err(
+            "Username may only contain letters, digits, '-', '_' and '.', and can't start with '.'",
+        );
+    }
+
+    Ok(())
+}
+
+pub fn check_password_policy(password: &str, policy: &RegistrationPolicy) -> Result<(), String> {
+    let length = password.chars().count();
+
+    if length < policy.min_password_length {
+        return Err(format!(
+            "Password must be at least {} characters long",
+            policy.min_password_length
+        ));
+    }
+
+    if length > 1024 {
+        return Err("Password must be at most 1024 characters long".into());
+    }
+
+    Ok(())
+}
+
+/// API token secrets and recovery codes are random, so unlike passwords they don't need a salt.
+pub fn hash_token(secret: &str) -> Vec<u8> {
+    let mut hasher = sha2::Sha512::new();
+    hasher.update(secret);
+    hasher.finalize().to_vec()
+}
+
+/// Compares hashes in constant time, so how long a comparison takes doesn't tell how much of a
+/// guess was right.
+pub fn same_hash(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
 }
 
 pub fn hash_password(password: &str, salt: &str) -> Vec<u8> {
@@ -86,7 +418,261 @@ pub fn hash_password(password: &str, salt: &str) -> Vec<u8> {
 
     let mut hasher = sha2::Sha512::new();
     hasher.update(salted);
-    let hashed = hasher.finalize().to_vec();
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, StatusCode};
+    use entity::{api_token, user};
+    use sea_orm::{DatabaseBackend, DatabaseConnection, MockDatabase, MockExecResult};
+
+    use super::{
+        check_auth_headers, check_login_headers, check_second_factor, hash_password, hash_token,
+        Scope, MAX_WRONG_CODES,
+    };
+    use crate::totp;
+
+    fn test_user() -> user::Model {
+        user::Model {
+            username: "jorik".into(),
+            password_hash: vec![],
+            password_salt: "salt".into(),
+            vault_id: "jorik".into(),
+            is_admin: false,
+            approved: true,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            totp_failed_attempts: 0,
+            totp_locked_until: None,
+        }
+    }
+
+    fn test_token(scope: Scope, expires_in_days: i64) -> api_token::Model {
+        let now = chrono::Utc::now().fixed_offset();
+        api_token::Model {
+            id: "tokenid".into(),
+            username: "jorik".into(),
+            name: "cron".into(),
+            token_hash: hash_token("secret"),
+            scope: scope.as_str().into(),
+            created_at: now,
+            expires_at: Some(now + chrono::Duration::days(expires_in_days)),
+            last_used_at: None,
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn mock(token: api_token::Model) -> DatabaseConnection {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![token.clone()]])
+            .append_query_results([vec![test_user()]])
+            .append_query_results([vec![token]])
+            .into_connection()
+    }
+
+    #[tokio::test]
+    async fn accepts_token_with_enough_scope() {
+        let conn = mock(test_token(Scope::Sync, 1));
+
+        let user = check_auth_headers(&conn, &bearer("tokenid.secret"), Scope::Read)
+            .await
+            .unwrap();
+
+        assert_eq!(user.username, "jorik");
+    }
+
+    #[tokio::test]
+    async fn rejects_token_without_scope() {
+        let conn = mock(test_token(Scope::Read, 1));
+
+        let response = check_auth_headers(&conn, &bearer("tokenid.secret"), Scope::Sync)
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_secret() {
+        let conn = mock(test_token(Scope::Admin, 1));
+
+        let response = check_auth_headers(&conn, &bearer("tokenid.guess"), Scope::Read)
+            .await
+            .unwrap_err();
 
-    return hashed;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_token() {
+        let conn = mock(test_token(Scope::Admin, -1));
+
+        let response = check_auth_headers(&conn, &bearer("tokenid.secret"), Scope::Read)
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_login_awaiting_approval() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![user::Model {
+                password_hash: hash_password("password", "salt"),
+                approved: false,
+                ..test_user()
+            }]])
+            .into_connection();
+        let mut headers = HeaderMap::new();
+        headers.insert("Login", "jorik:password".parse().unwrap());
+
+        let response = check_login_headers(&conn, &headers).await.unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn two_factor_user() -> user::Model {
+        user::Model {
+            totp_secret: Some(SECRET.into()),
+            totp_enabled: true,
+            ..test_user()
+        }
+    }
+
+    fn totp_header(code: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Totp", code.parse().unwrap());
+        headers
+    }
+
+    fn rows_affected(rows_affected: u64) -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected,
+        }
+    }
+
+    fn current_code() -> String {
+        totp::code_at(SECRET, chrono::Utc::now().timestamp())
+    }
+
+    #[tokio::test]
+    async fn accepts_current_code() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([rows_affected(1)])
+            .into_connection();
+
+        let headers = totp_header(&current_code());
+        let user = check_second_factor(&conn, two_factor_user(), &headers)
+            .await
+            .unwrap();
+
+        assert_eq!(user.username, "jorik");
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_code() {
+        // The last used step was already moved to this code by an earlier request
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([rows_affected(0)])
+            .into_connection();
+
+        let headers = totp_header(&current_code());
+        let response = check_second_factor(&conn, two_factor_user(), &headers)
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn recovery_code_works_once() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([rows_affected(1), rows_affected(1), rows_affected(0)])
+            .append_query_results([vec![user::Model {
+                totp_failed_attempts: 1,
+                ..two_factor_user()
+            }]])
+            .into_connection();
+        let headers = totp_header("recovery-code");
+
+        assert!(check_second_factor(&conn, two_factor_user(), &headers)
+            .await
+            .is_ok());
+        let response = check_second_factor(&conn, two_factor_user(), &headers)
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(log.contains(r#"DELETE FROM \"totp_recovery_code\""#));
+    }
+
+    #[tokio::test]
+    async fn counts_wrong_code() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([rows_affected(0)])
+            .append_query_results([vec![user::Model {
+                totp_failed_attempts: 1,
+                ..two_factor_user()
+            }]])
+            .into_connection();
+
+        let response = check_second_factor(&conn, two_factor_user(), &totp_header("wrong"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(log.contains(r#"\"totp_failed_attempts\" + "#));
+        assert!(!log.contains(r#"SET \"totp_locked_until\""#));
+    }
+
+    #[tokio::test]
+    async fn locks_after_too_many_wrong_codes() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([rows_affected(0), rows_affected(1)])
+            .append_query_results([vec![user::Model {
+                totp_failed_attempts: MAX_WRONG_CODES,
+                ..two_factor_user()
+            }]])
+            .into_connection();
+
+        let response = check_second_factor(&conn, two_factor_user(), &totp_header("wrong"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(log.contains(r#"SET \"totp_locked_until\""#));
+    }
+
+    #[tokio::test]
+    async fn rejects_correct_code_while_locked() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+        let user = user::Model {
+            totp_locked_until: Some((chrono::Utc::now() + chrono::Duration::minutes(5)).into()),
+            ..two_factor_user()
+        };
+
+        let headers = totp_header(&current_code());
+        let response = check_second_factor(&conn, user, &headers)
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(conn.into_transaction_log().is_empty());
+    }
 }
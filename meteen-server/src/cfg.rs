@@ -8,6 +8,40 @@ use std::{
     str::FromStr,
 };
 
+/// Who is allowed to create an account through `POST /create`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// Anyone who can reach the server can register
+    Open,
+    /// Registering requires a single-use invite code created by an admin
+    InviteOnly,
+    /// No new accounts can be created
+    Closed,
+}
+
+impl FromStr for RegistrationMode {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "open" => Ok(RegistrationMode::Open),
+            "invite" => Ok(RegistrationMode::InviteOnly),
+            "closed" => Ok(RegistrationMode::Closed),
+            _ => Err(eyre!(
+                "METEEN_REGISTRATION must be one of \"open\", \"invite\" or \"closed\""
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    /// New accounts can't log in until an admin approves them
+    pub require_approval: bool,
+    pub min_password_length: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub address: IpAddr,
@@ -17,6 +51,9 @@ pub struct Config {
     pub db_user: String,
     pub db_host: String,
     pub data_dir: std::path::PathBuf,
+    pub registration: RegistrationPolicy,
+    /// Deleted tasks and projects are purged from the trash after this many days
+    pub trash_retention_days: u32,
 }
 
 impl Config {
@@ -51,6 +88,25 @@ impl Config {
             }
         };
 
+        let registration = RegistrationPolicy {
+            mode: empty_string_is_none(var("METEEN_REGISTRATION").ok())
+                .unwrap_or("open".into())
+                .parse()?,
+            require_approval: empty_string_is_none(var("METEEN_REQUIRE_APPROVAL").ok())
+                .unwrap_or("false".into())
+                .parse()
+                .context("METEEN_REQUIRE_APPROVAL must be true or false")?,
+            min_password_length: empty_string_is_none(var("METEEN_MIN_PASSWORD_LENGTH").ok())
+                .unwrap_or("8".into())
+                .parse()
+                .context("METEEN_MIN_PASSWORD_LENGTH is not a valid number")?,
+        };
+
+        let trash_retention_days = empty_string_is_none(var("METEEN_TRASH_RETENTION_DAYS").ok())
+            .unwrap_or("30".into())
+            .parse()
+            .context("METEEN_TRASH_RETENTION_DAYS is not a valid number")?;
+
         tokio::fs::create_dir_all(&data_dir).await?;
         tokio::fs::create_dir_all(&data_dir.join("vaults")).await?;
 
@@ -62,14 +118,16 @@ impl Config {
             db_user,
             db_host,
             data_dir,
+            registration,
+            trash_retention_days,
         })
     }
 }
 
 fn empty_string_is_none(str: Option<String>) -> Option<String> {
     match str {
-        Some(str) if str == "" => Some("127.0.0.1".into()),
-        Some(str) => Some(str.into()),
+        Some(str) if str.is_empty() => None,
+        Some(str) => Some(str),
         _ => None,
     }
 }
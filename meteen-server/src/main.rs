@@ -1,24 +1,41 @@
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use sea_orm::{prelude::*, Database};
 
+mod admin;
 mod auth;
 mod cfg;
 mod routes;
+mod totp;
 mod vaults;
 
 use cfg::Config;
-use routes::{create_user::create_user, get_vault::get_vault, sync::sync};
+use routes::{
+    admin::{approve_user, create_invite, list_pending, reject_user},
+    change_password::change_password,
+    create_user::create_user,
+    delete_account::delete_account,
+    export::export,
+    get_vault::get_vault,
+    import::import,
+    query_tasks::query_tasks,
+    sync::sync,
+    tokens::{create_token, list_tokens, revoke_token},
+    two_factor::{confirm_totp, disable_totp, enroll_totp, login},
+};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 #[derive(Clone)]
 struct AppState {
-    conn: DatabaseConnection,
+    // Behind an `Arc` because the connection isn't `Clone` with the `mock` feature the tests use
+    conn: Arc<DatabaseConnection>,
     vaults: Arc<Mutex<vaults::Vaults>>,
+    registration: cfg::RegistrationPolicy,
+    trash_retention_days: u32,
 }
 
 #[tokio::main]
@@ -28,7 +45,6 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
 
     let config = Config::from_env().await?;
-    dbg!(&config);
     let Config {
         db_user,
         db_name,
@@ -37,24 +53,53 @@ async fn main() -> Result<()> {
         address,
         port,
         data_dir,
+        registration,
+        trash_retention_days,
     } = config;
 
     tokio::fs::create_dir_all(&data_dir).await?;
 
-    let listener = tokio::net::TcpListener::bind((address, port)).await?;
-
     let conn_str = format!("postgres://{db_user}:{db_pass}@{db_host}/{db_name}");
-    dbg!(&conn_str);
     let connection = Database::connect(&conn_str).await?;
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("admin") {
+        let mut vaults = vaults::Vaults::new(data_dir);
+        return admin::run_cli(&args[1..], &connection, &mut vaults).await;
+    }
+
+    let vaults = vaults::Vaults::new(data_dir);
+    // Held for as long as the server runs, so the CLI doesn't change vaults behind its back
+    let _lock = vaults
+        .lock()
+        .map_err(|e| eyre!("The vaults are in use, is the server already running? ({e})"))?;
+    let listener = tokio::net::TcpListener::bind((address, port)).await?;
+
     let app = Router::new()
         .route("/", get(root))
         .route("/create", post(create_user))
         .route("/get", get(get_vault))
         .route("/sync", post(sync))
+        .route("/password", post(change_password))
+        .route("/delete", post(delete_account))
+        .route("/export", get(export))
+        .route("/import", post(import))
+        .route("/tasks", get(query_tasks))
+        .route("/login", post(login))
+        .route("/tokens", get(list_tokens).post(create_token))
+        .route("/tokens/:id", delete(revoke_token))
+        .route("/2fa/enroll", post(enroll_totp))
+        .route("/2fa/confirm", post(confirm_totp))
+        .route("/2fa/disable", post(disable_totp))
+        .route("/admin/invites", post(create_invite))
+        .route("/admin/pending", get(list_pending))
+        .route("/admin/approve/:username", post(approve_user))
+        .route("/admin/reject/:username", post(reject_user))
         .with_state(AppState {
-            conn: connection,
-            vaults: Arc::new(Mutex::new(vaults::Vaults::new(data_dir))),
+            conn: Arc::new(connection),
+            vaults: Arc::new(Mutex::new(vaults)),
+            registration,
+            trash_retention_days,
         });
 
     axum::serve(listener, app).await?;
@@ -0,0 +1,80 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{admin, auth::check_admin_headers, AppState};
+
+fn internal_error(action: &str, e: impl std::fmt::Display) -> Response {
+    eprintln!("Failed to {}: {}", action, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+        .into_response()
+}
+
+/// Creates a single-use invite code for invite-only registration.
+pub async fn create_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<String, Response> {
+    let AppState { conn, .. } = state;
+
+    let admin = check_admin_headers(&conn, &headers).await?;
+
+    admin::create_invite(&conn, Some(admin.username))
+        .await
+        .map_err(|e| internal_error("create invite", e))
+}
+
+/// Lists the usernames of accounts awaiting approval.
+pub async fn list_pending(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<String>>, Response> {
+    let AppState { conn, .. } = state;
+
+    check_admin_headers(&conn, &headers).await?;
+
+    admin::pending_users(&conn)
+        .await
+        .map(Json)
+        .map_err(|e| internal_error("list pending users", e))
+}
+
+pub async fn approve_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> Result<Response, Response> {
+    let AppState { conn, .. } = state;
+
+    check_admin_headers(&conn, &headers).await?;
+
+    match admin::approve_user(&conn, &username).await {
+        Ok(true) => Ok((StatusCode::OK, "OK").into_response()),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "No such user").into_response()),
+        Err(e) => Err(internal_error("approve user", e)),
+    }
+}
+
+/// Deletes a pending account and its vault.
+pub async fn reject_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> Result<Response, Response> {
+    let AppState { conn, vaults, .. } = state;
+
+    check_admin_headers(&conn, &headers).await?;
+
+    let mut vaults = vaults.lock().await;
+    match admin::reject_user(&conn, &mut vaults, &username).await {
+        Ok(true) => Ok((StatusCode::OK, "OK").into_response()),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "No such pending user").into_response()),
+        Err(e) => Err(internal_error("reject user", e)),
+    }
+}
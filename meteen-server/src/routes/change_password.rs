@@ -0,0 +1,69 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use entity::{api_token, prelude::*};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{check_login_headers, check_password_policy, hash_password},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChangePassword {
+    password: String,
+}
+
+/// Replaces the password of the authenticated user. A fresh salt is generated and all API tokens
+/// of the user are revoked, so no credential issued before the change keeps working.
+pub async fn change_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(ChangePassword { password }): Json<ChangePassword>,
+) -> Result<Response, Response> {
+    let AppState {
+        conn, registration, ..
+    } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    if let Err(message) = check_password_policy(&password, &registration) {
+        return Err((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let username = user.username.clone();
+    let failed = |e: sea_orm::DbErr| {
+        eprintln!("Failed to change password for \"{}\": {}", username, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to change password",
+        )
+            .into_response()
+    };
+
+    let salt: String = nanoid::nanoid!();
+
+    let mut user = user.into_active_model();
+    user.password_hash = Set(hash_password(&password, &salt));
+    user.password_salt = Set(salt);
+
+    let transaction = conn.begin().await.map_err(failed)?;
+
+    user.update(&transaction).await.map_err(failed)?;
+
+    ApiToken::delete_many()
+        .filter(api_token::Column::Username.eq(&username))
+        .exec(&transaction)
+        .await
+        .map_err(failed)?;
+
+    transaction.commit().await.map_err(failed)?;
+
+    Ok((StatusCode::OK, "OK").into_response())
+}
@@ -1,25 +1,76 @@
+use std::future::Future;
+
 use axum::http::StatusCode;
 use axum::response::Response;
 use axum::{extract::State, response::IntoResponse, Json};
-use entity::user;
+use entity::{invite_code, prelude::*, user};
 use meteen_model::Database as MeteenVault;
 use sea_orm::entity::ActiveModelTrait;
-use sea_orm::IntoActiveModel;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter, SqlErr, TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::auth::hash_password;
+use crate::auth::{check_password_policy, check_username_policy, hash_password};
+use crate::cfg::RegistrationMode;
+use crate::vaults::Vaults;
 use crate::AppState;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CreateUser {
     name: String,
     password: String,
+    /// Required when registration is invite-only
+    invite: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProvisionError {
+    UsernameTaken,
+    InvalidInvite,
+    Database(DbErr),
+    Vault(tokio::io::Error),
 }
 
 #[axum::debug_handler]
 pub async fn create_user(state: State<AppState>, user: Json<CreateUser>) -> Response {
-    let State(AppState { conn, vaults }) = state;
-    let Json(CreateUser { name, password }) = user;
+    let State(AppState {
+        conn,
+        vaults,
+        registration,
+        ..
+    }) = state;
+    let Json(CreateUser {
+        name,
+        password,
+        invite,
+    }) = user;
+
+    if registration.mode == RegistrationMode::Closed {
+        return (StatusCode::FORBIDDEN, "Registration is closed").into_response();
+    }
+
+    if let Err(message) = check_username_policy(&name) {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+
+    if let Err(message) = check_password_policy(&password, &registration) {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+
+    let invite = match (registration.mode, invite) {
+        (RegistrationMode::InviteOnly, Some(invite)) => Some(invite),
+        (RegistrationMode::InviteOnly, None) => {
+            return (
+                StatusCode::FORBIDDEN,
+                "An invite code is required to register",
+            )
+                .into_response()
+        }
+        _ => None,
+    };
 
     let salt: String = nanoid::nanoid!();
 
@@ -28,56 +79,444 @@ pub async fn create_user(state: State<AppState>, user: Json<CreateUser>) -> Resp
         password_hash: hash_password(&password, &salt),
         password_salt: salt,
         vault_id: name.clone(),
+        is_admin: false,
+        approved: !registration.require_approval,
+        totp_secret: None,
+        totp_enabled: false,
+        totp_last_step: None,
+        totp_failed_attempts: 0,
+        totp_locked_until: None,
     };
 
-    let transaction = match conn.get_postgres_connection_pool().begin().await {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("Failed to begin transaction: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to begin transaction",
+    let mut vaults = vaults.lock().await;
+    let provisioned = provision_user(&conn, &mut vaults, user_model, invite.as_deref()).await;
+
+    match provisioned {
+        Ok(()) => {}
+        Err(ProvisionError::UsernameTaken) => {
+            return (StatusCode::CONFLICT, "Username is already taken").into_response();
+        }
+        Err(ProvisionError::InvalidInvite) => {
+            return (StatusCode::FORBIDDEN, "Invalid or already used invite code").into_response();
+        }
+        Err(ProvisionError::Database(e)) => {
+            eprintln!("Failed to create user \"{}\": {}", name, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response();
+        }
+        Err(ProvisionError::Vault(e)) => {
+            eprintln!("Failed to create vault for \"{}\": {}", name, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create vault").into_response();
+        }
+    }
+
+    if registration.require_approval {
+        return (
+            StatusCode::ACCEPTED,
+            "Account created, it can be used once an admin approves it",
+        )
+            .into_response();
+    }
+
+    (StatusCode::OK, "OK").into_response()
+}
+
+/// Creates the user row and their empty vault as one unit.
+///
+/// The invite code (if any) is claimed and the user is inserted inside a single transaction, which
+/// is only committed once the vault file has been written. If anything fails the transaction is
+/// rolled back and the vault file is removed again, so neither a user without a vault nor a vault
+/// without a user is left behind.
+pub async fn provision_user(
+    conn: &DatabaseConnection,
+    vaults: &mut Vaults,
+    user: user::Model,
+    invite: Option<&str>,
+) -> Result<(), ProvisionError> {
+    provision_user_with(conn, vaults, user, invite, |t| t.commit()).await
+}
+
+/// [`provision_user`], with `commit` finishing the transaction so tests can make it fail.
+async fn provision_user_with<C, F>(
+    conn: &DatabaseConnection,
+    vaults: &mut Vaults,
+    user: user::Model,
+    invite: Option<&str>,
+    commit: C,
+) -> Result<(), ProvisionError>
+where
+    C: FnOnce(DatabaseTransaction) -> F,
+    F: Future<Output = Result<(), DbErr>>,
+{
+    let transaction = conn.begin().await.map_err(ProvisionError::Database)?;
+
+    if let Some(invite) = invite {
+        // Claiming the code with a conditional update makes sure it can only be used once
+        let claimed = InviteCode::update_many()
+            .col_expr(
+                invite_code::Column::UsedBy,
+                Expr::value(user.username.clone()),
             )
-                .into_response();
+            .filter(invite_code::Column::Code.eq(invite))
+            .filter(invite_code::Column::UsedBy.is_null())
+            .exec(&transaction)
+            .await;
+
+        match claimed {
+            Ok(result) if result.rows_affected == 1 => {}
+            Ok(_) => {
+                let _ = transaction.rollback().await;
+                return Err(ProvisionError::InvalidInvite);
+            }
+            Err(e) => {
+                let _ = transaction.rollback().await;
+                return Err(ProvisionError::Database(e));
+            }
         }
-    };
+    }
 
-    match user_model.into_active_model().insert(&conn).await {
-        Ok(model) => println!("Saved {:?}", model),
+    // Checked up front so we never touch the vault of an existing user. The unique constraint
+    // still catches a concurrent registration of the same name below.
+    match User::find_by_id(&user.username).one(&transaction).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            let _ = transaction.rollback().await;
+            return Err(ProvisionError::UsernameTaken);
+        }
         Err(e) => {
-            eprintln!("Failed to create user: {}", e);
-            // TODO: Handle this error
             let _ = transaction.rollback().await;
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response();
+            return Err(ProvisionError::Database(e));
         }
-    };
+    }
 
-    let vault = MeteenVault::new();
+    let vault_id = user.vault_id.clone();
 
-    let vaults = vaults.lock().await;
-    match vaults.save_vault(&name, &vault).await {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Error saving vault: {}", e);
-            // TODO: Handle this error
-            let _ = transaction.rollback().await;
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create vault").into_response();
+    if let Err(e) = user.into_active_model().insert(&transaction).await {
+        let _ = transaction.rollback().await;
+        return match e.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => Err(ProvisionError::UsernameTaken),
+            _ => Err(ProvisionError::Database(e)),
+        };
+    }
+
+    if let Err(e) = vaults.save_vault(&vault_id, &MeteenVault::new()).await {
+        let _ = transaction.rollback().await;
+        // The write may have failed halfway through
+        let _ = vaults.delete_vault(&vault_id).await;
+        return Err(ProvisionError::Vault(e));
+    }
+
+    if let Err(e) = commit(transaction).await {
+        if let Err(e) = vaults.delete_vault(&vault_id).await {
+            eprintln!("Failed to clean up vault \"{}\": {}", vault_id, e);
         }
+        return Err(ProvisionError::Database(e));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use axum::{extract::State, http::StatusCode, Json};
+    use entity::user;
+    use sea_orm::{
+        DatabaseBackend, DatabaseConnection, DbErr, MockDatabase, MockExecResult, RuntimeErr,
     };
+    use tempfile::TempDir;
+    use tokio::sync::Mutex;
 
-    match transaction.commit().await {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Failed to commit transaction: {}", e);
-            // TODO: Handle this error
-            let _ = vaults.delete_vault(&name).await;
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to finalize transaction",
-            )
-                .into_response();
+    use super::{create_user, provision_user, provision_user_with, CreateUser, ProvisionError};
+    use crate::cfg::{RegistrationMode, RegistrationPolicy};
+    use crate::vaults::Vaults;
+    use crate::AppState;
+
+    fn test_user() -> user::Model {
+        user::Model {
+            username: "jorik".into(),
+            password_hash: vec![1, 2, 3],
+            password_salt: "salt".into(),
+            vault_id: "jorik".into(),
+            is_admin: false,
+            approved: true,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            totp_failed_attempts: 0,
+            totp_locked_until: None,
         }
-    };
+    }
 
-    (StatusCode::OK, "OK").into_response()
+    /// Creates an empty data directory that is removed when dropped, optionally without the
+    /// `vaults` directory inside it so that saving a vault fails.
+    async fn data_dir(with_vaults: bool) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        if with_vaults {
+            tokio::fs::create_dir_all(dir.path().join("vaults"))
+                .await
+                .unwrap();
+        }
+        dir
+    }
+
+    fn vault_exists(dir: &Path) -> bool {
+        dir.join("vaults").join("jorik.mtvault").exists()
+    }
+
+    fn mock_error() -> DbErr {
+        DbErr::Query(RuntimeErr::Internal("simulated failure".into()))
+    }
+
+    async fn provision(
+        conn: &DatabaseConnection,
+        dir: &Path,
+        invite: Option<&str>,
+    ) -> Result<(), ProvisionError> {
+        let mut vaults = Vaults::new(dir.to_path_buf());
+        provision_user(conn, &mut vaults, test_user(), invite).await
+    }
+
+    #[tokio::test]
+    async fn creates_user_and_vault() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user::Model>::new(), vec![test_user()]])
+            .into_connection();
+        let dir = data_dir(true).await;
+
+        provision(&conn, dir.path(), None).await.unwrap();
+
+        assert!(vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn rejects_existing_username() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![test_user()]])
+            .into_connection();
+        let dir = data_dir(true).await;
+
+        let result = provision(&conn, dir.path(), None).await;
+
+        assert!(matches!(result, Err(ProvisionError::UsernameTaken)));
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn rejects_used_invite() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 0,
+            }])
+            .into_connection();
+        let dir = data_dir(true).await;
+
+        let result = provision(&conn, dir.path(), Some("used-code")).await;
+
+        assert!(matches!(result, Err(ProvisionError::InvalidInvite)));
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn insert_failure_creates_no_vault() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user::Model>::new()])
+            .append_query_errors([mock_error()])
+            .into_connection();
+        let dir = data_dir(true).await;
+
+        let result = provision(&conn, dir.path(), None).await;
+
+        assert!(matches!(result, Err(ProvisionError::Database(_))));
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn vault_failure_rolls_back() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user::Model>::new(), vec![test_user()]])
+            .into_connection();
+        let dir = data_dir(false).await;
+
+        let result = provision(&conn, dir.path(), None).await;
+        assert!(matches!(result, Err(ProvisionError::Vault(_))));
+
+        // The transaction was never committed
+        let log = format!("{:?}", conn.into_transaction_log());
+        assert!(!log.contains("COMMIT"));
+    }
+
+    fn policy(mode: RegistrationMode, require_approval: bool) -> RegistrationPolicy {
+        RegistrationPolicy {
+            mode,
+            require_approval,
+            min_password_length: 8,
+        }
+    }
+
+    /// Registers jorik through the route, returning the status code.
+    async fn register(
+        conn: DatabaseConnection,
+        dir: &Path,
+        registration: RegistrationPolicy,
+        invite: Option<&str>,
+    ) -> StatusCode {
+        let state = AppState {
+            conn: Arc::new(conn),
+            vaults: Arc::new(Mutex::new(Vaults::new(dir.to_path_buf()))),
+            registration,
+            trash_retention_days: 30,
+        };
+        let user = CreateUser {
+            name: "jorik".into(),
+            password: "correct horse".into(),
+            invite: invite.map(Into::into),
+        };
+        create_user(State(state), Json(user)).await.status()
+    }
+
+    fn provisioning_mock() -> MockDatabase {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user::Model>::new(), vec![test_user()]])
+    }
+
+    #[tokio::test]
+    async fn open_registration_creates_account() {
+        let dir = data_dir(true).await;
+        let conn = provisioning_mock().into_connection();
+
+        let status = register(
+            conn,
+            dir.path(),
+            policy(RegistrationMode::Open, false),
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn closed_registration_creates_nothing() {
+        let dir = data_dir(true).await;
+        let conn = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+        let status = register(
+            conn,
+            dir.path(),
+            policy(RegistrationMode::Closed, false),
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn invite_only_registration_requires_invite() {
+        let dir = data_dir(true).await;
+        let conn = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+        let status = register(
+            conn,
+            dir.path(),
+            policy(RegistrationMode::InviteOnly, false),
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn invite_is_claimed_by_registration() {
+        let dir = data_dir(true).await;
+        let conn = provisioning_mock()
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let state_conn = Arc::new(conn);
+        let state = AppState {
+            conn: state_conn.clone(),
+            vaults: Arc::new(Mutex::new(Vaults::new(dir.path().to_path_buf()))),
+            registration: policy(RegistrationMode::InviteOnly, false),
+            trash_retention_days: 30,
+        };
+        let user = CreateUser {
+            name: "jorik".into(),
+            password: "correct horse".into(),
+            invite: Some("code".into()),
+        };
+
+        let status = create_user(State(state), Json(user)).await.status();
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(vault_exists(dir.path()));
+        let log = format!(
+            "{:?}",
+            Arc::into_inner(state_conn).unwrap().into_transaction_log()
+        );
+        assert!(log.contains(r#"UPDATE \"invite_code\" SET \"used_by\""#));
+        assert!(log.contains(r#"\"used_by\" IS NULL"#));
+        assert!(log.contains("COMMIT"));
+    }
+
+    #[tokio::test]
+    async fn claimed_invite_cannot_be_reused() {
+        let dir = data_dir(true).await;
+        // The conditional update finds no unused code
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 0,
+            }])
+            .into_connection();
+
+        let status = register(
+            conn,
+            dir.path(),
+            policy(RegistrationMode::InviteOnly, false),
+            Some("code"),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(!vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn approval_leaves_account_pending() {
+        let dir = data_dir(true).await;
+        let conn = provisioning_mock().into_connection();
+
+        let status = register(conn, dir.path(), policy(RegistrationMode::Open, true), None).await;
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(vault_exists(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn commit_failure_removes_vault() {
+        let conn = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user::Model>::new(), vec![test_user()]])
+            .into_connection();
+        let dir = data_dir(true).await;
+        let mut vaults = Vaults::new(dir.path().to_path_buf());
+
+        let result = provision_user_with(&conn, &mut vaults, test_user(), None, |t| async {
+            let _ = t.rollback().await;
+            Err(mock_error())
+        })
+        .await;
+
+        assert!(matches!(result, Err(ProvisionError::Database(_))));
+        assert!(!vault_exists(dir.path()));
+    }
 }
@@ -0,0 +1,63 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use entity::prelude::*;
+use sea_orm::{prelude::*, TransactionTrait};
+
+use crate::{auth::check_login_headers, AppState};
+
+/// Deletes the authenticated user together with their vault.
+///
+/// The user row is removed inside a transaction that is only committed once the vault is gone,
+/// so a failure to delete the vault leaves the account intact instead of orphaning the file.
+pub async fn delete_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let AppState { conn, vaults, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    let transaction = conn.begin().await.map_err(|e| {
+        eprintln!("Failed to begin transaction: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to begin transaction",
+        )
+            .into_response()
+    })?;
+
+    if let Err(e) = User::delete_by_id(&user.username).exec(&transaction).await {
+        eprintln!("Failed to delete user \"{}\": {}", user.username, e);
+        let _ = transaction.rollback().await;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete user").into_response());
+    }
+
+    // Hold the lock until the transaction is done, so nobody can sync into a half-deleted vault
+    let mut vaults = vaults.lock().await;
+    let backup = vaults.get_vault(&user.vault_id).await.ok().cloned();
+
+    if let Err(e) = vaults.delete_vault(&user.vault_id).await {
+        eprintln!("Failed to delete vault of \"{}\": {}", user.username, e);
+        let _ = transaction.rollback().await;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete vault").into_response());
+    }
+
+    if let Err(e) = transaction.commit().await {
+        eprintln!("Failed to commit deletion of \"{}\": {}", user.username, e);
+        if let Some(backup) = backup {
+            if let Err(e) = vaults.save_vault(&user.vault_id, &backup).await {
+                eprintln!("Failed to restore vault of \"{}\": {}", user.username, e);
+            }
+        }
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to finalize transaction",
+        )
+            .into_response());
+    }
+
+    Ok((StatusCode::OK, "OK").into_response())
+}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use meteen_model::Database as MeteenVault;
+use serde::Serialize;
+
+use crate::{
+    auth::{check_auth_headers, Scope},
+    AppState,
+};
+
+#[derive(Serialize)]
+struct Export<'a> {
+    username: &'a str,
+    vault: &'a MeteenVault,
+    icalendar: String,
+}
+
+/// Downloads everything we store for the authenticated user: the vault as JSON and the same
+/// tasks as an iCalendar document, so the data can be taken to other tools.
+pub async fn export(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let AppState { conn, vaults, .. } = state;
+
+    let user = check_auth_headers(&conn, &headers, Scope::Read).await?;
+
+    let mut vaults = vaults.lock().await;
+    let vault = vaults.get_vault(&user.vault_id).await.map_err(|e| {
+        eprintln!("Failed to get vault: {}", e);
+        (StatusCode::NOT_FOUND, "Not found").into_response()
+    })?;
+
+    let export = Export {
+        username: &user.username,
+        vault,
+        icalendar: meteen_model::ical::export(vault, chrono::Utc::now()),
+    };
+
+    let disposition = format!(
+        "attachment; filename=\"meteen-export-{}.json\"",
+        user.username
+    );
+
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(export)).into_response())
+}
@@ -1,28 +1,31 @@
 use axum::{
-    extract::{Path, State},
+    extract::State,
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 
-use crate::{auth::check_auth_headers, AppState};
+use crate::{
+    auth::{check_auth_headers, Scope},
+    AppState,
+};
 
 pub async fn get_vault(
     //Path(id): Path<String>,
     State(state): State<AppState>,
     headers: HeaderMap,
 ) -> Result<Vec<u8>, Response> {
-    let AppState { vaults, conn } = state;
+    let AppState { vaults, conn, .. } = state;
 
-    let user = match check_auth_headers(&conn, &headers).await {
+    let user = match check_auth_headers(&conn, &headers, Scope::Read).await {
         Ok(user) => user,
         Err(r) => return Err(r),
     };
 
     let mut vaults = vaults.lock().await;
-    let vault = vaults.get_vault(&user.username).await.map_err(|e| {
+    let vault = vaults.get_vault(&user.vault_id).await.map_err(|e| {
         eprintln!("Failed to get vault: {}", e);
         (StatusCode::NOT_FOUND, "Not found").into_response()
     })?;
 
-    Ok(bincode::serialize(vault).unwrap().into())
+    Ok(meteen_model::format::encode(vault))
 }
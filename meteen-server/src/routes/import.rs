@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use meteen_model::{import::ImportFormat, Operation};
+use serde::Serialize;
+
+use crate::{
+    auth::{check_auth_headers, Scope},
+    AppState,
+};
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    /// How many tasks were added
+    pub imported: usize,
+    /// What was left out, see [`meteen_model::import::Import`]
+    pub unmapped: Vec<String>,
+}
+
+/// Reads the import format from parameters like `format=csv&summary=Title`, without a format
+/// the input is taken as iCalendar.
+pub fn parse_format(mut params: HashMap<String, String>) -> Result<ImportFormat, String> {
+    params.entry("format".into()).or_insert("ical".into());
+    serde_json::to_value(params)
+        .and_then(serde_json::from_value)
+        .map_err(|e| e.to_string())
+}
+
+/// Counts the tasks that the operations of an import create.
+pub fn count_tasks(operations: &[Operation]) -> usize {
+    operations
+        .iter()
+        .filter(|op| matches!(op, Operation::CreateTask { .. }))
+        .count()
+}
+
+/// Adds the tasks in the body to the vault, creating the projects and labels they need. The
+/// format is chosen by the query, see [`parse_format`].
+pub async fn import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    body: String,
+) -> Result<Json<ImportReport>, Response> {
+    let AppState { conn, vaults, .. } = state;
+
+    let user = check_auth_headers(&conn, &headers, Scope::Sync).await?;
+    let id = &user.vault_id;
+    let format = parse_format(params).map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+
+    let mut vaults = vaults.lock().await;
+    let report = {
+        let vault = vaults.get_vault_mut(id).await.map_err(|e| {
+            eprintln!("Couldn't get vault: {}", e);
+            (StatusCode::NOT_FOUND, "No vault associated with user").into_response()
+        })?;
+
+        let import = meteen_model::import::import(vault, &format, &body, Utc::now())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+        let imported = count_tasks(&import.operations);
+        vault.batch_operations(import.operations);
+
+        ImportReport {
+            imported,
+            unmapped: import.unmapped,
+        }
+    };
+
+    vaults.save_cached_vault(id).await.map_err(|e| {
+        eprintln!("Failed to save vault: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "failed to save vault").into_response()
+    })?;
+
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use meteen_model::import::{CsvLayout, ImportFormat};
+
+    #[test]
+    fn parse_format() {
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        assert_eq!(super::parse_format(params(&[])), Ok(ImportFormat::Ical));
+        assert_eq!(
+            super::parse_format(params(&[
+                ("format", "csv"),
+                ("summary", "Title"),
+                ("delimiter", ";")
+            ])),
+            Ok(ImportFormat::Csv(Box::new(CsvLayout {
+                summary: "Title".into(),
+                delimiter: ';',
+                ..Default::default()
+            })))
+        );
+        assert!(super::parse_format(params(&[("format", "todoist-csv")])).is_err());
+    }
+}
@@ -1,3 +1,11 @@
+pub mod admin;
+pub mod change_password;
 pub mod create_user;
+pub mod delete_account;
+pub mod export;
 pub mod get_vault;
+pub mod import;
+pub mod query_tasks;
 pub mod sync;
+pub mod tokens;
+pub mod two_factor;
@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::FixedOffset;
+use meteen_model::{Filter, Sort};
+use serde::Deserialize;
+
+use crate::{
+    auth::{check_auth_headers, Scope},
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct TaskQuery {
+    #[serde(default)]
+    filter: String,
+    #[serde(default)]
+    sort: String,
+    /// Minutes the timezone relative dates are taken in is ahead of UTC. It is used for every
+    /// date, so around a daylight saving change a time close to midnight can count for the day
+    /// next to it.
+    #[serde(default)]
+    utc_offset: i32,
+}
+
+/// Lists the tasks of the authenticated user matching a filter like
+/// `overdue & priority >= high`, see [`meteen_model::filter`].
+pub async fn query_tasks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TaskQuery>,
+) -> Result<Response, Response> {
+    let AppState { conn, vaults, .. } = state;
+
+    let user = check_auth_headers(&conn, &headers, Scope::Read).await?;
+
+    let bad_request =
+        |e: &dyn std::fmt::Display| (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    let filter: Filter = query.filter.parse().map_err(|e| bad_request(&e))?;
+    let sort = Sort::parse_list(&query.sort).map_err(|e| bad_request(&e))?;
+    let offset = query
+        .utc_offset
+        .checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .ok_or_else(|| bad_request(&"Invalid UTC offset"))?;
+
+    let mut vaults = vaults.lock().await;
+    let vault = vaults.get_vault(&user.vault_id).await.map_err(|e| {
+        eprintln!("Failed to get vault: {}", e);
+        (StatusCode::NOT_FOUND, "Not found").into_response()
+    })?;
+
+    let now = chrono::Utc::now().with_timezone(&offset);
+    Ok(Json(vault.query(&filter, &sort, &now)).into_response())
+}
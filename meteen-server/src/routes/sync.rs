@@ -4,22 +4,29 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use entity::prelude::*;
+use chrono::{Duration, Utc};
 use meteen_model::Operation;
-use sea_orm::prelude::*;
 
-use crate::{auth::check_auth_headers, AppState};
+use crate::{
+    auth::{check_auth_headers, Scope},
+    AppState,
+};
 
 pub async fn sync(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(operations): Json<Vec<Operation>>,
+    Json(mut operations): Json<Vec<Operation>>,
 ) -> Result<Vec<u8>, Response> {
     // TODO: ACID transactions
-    let AppState { conn, vaults } = state;
+    let AppState {
+        conn,
+        vaults,
+        trash_retention_days,
+        ..
+    } = state;
 
-    let user = check_auth_headers(&conn, &headers).await?;
-    let id = &user.username;
+    let user = check_auth_headers(&conn, &headers, Scope::Sync).await?;
+    let id = &user.vault_id;
 
     let mut vaults = vaults.lock().await;
     {
@@ -33,7 +40,16 @@ pub async fn sync(
             }
         };
 
+        // Dating deletions and purging here rather than on the clients keeps their clocks out
+        // of it, they get the purged vault back below
+        let now = Utc::now();
+        for operation in &mut operations {
+            operation.date_deletion(now);
+        }
         vault.batch_operations(operations);
+
+        let before = now - Duration::days(trash_retention_days.into());
+        vault.apply_operation(Operation::PurgeTrash { before });
     }
 
     vaults.save_cached_vault(id).await.map_err(|e| {
@@ -41,11 +57,7 @@ pub async fn sync(
         (StatusCode::INTERNAL_SERVER_ERROR, "failed to save vault").into_response()
     })?;
 
-    match bincode::serialize(vaults.get_vault(id).await.unwrap()) {
-        Ok(serialized) => Ok(serialized),
-        Err(e) => {
-            eprintln!("Error serializing vault: {}", e);
-            Err((StatusCode::INTERNAL_SERVER_ERROR, "Corrupted vault").into_response())
-        }
-    }
+    Ok(meteen_model::format::encode(
+        vaults.get_vault(id).await.unwrap(),
+    ))
 }
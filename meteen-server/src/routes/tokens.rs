@@ -0,0 +1,165 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use entity::{api_token, prelude::*};
+use sea_orm::{
+    prelude::*, ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{check_login_headers, hash_token, Scope},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreateToken {
+    name: String,
+    scope: Scope,
+    /// The token never expires when this is left out
+    expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreatedToken {
+    id: String,
+    /// The only time the secret is shown, we just store a hash of it
+    token: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TokenInfo {
+    id: String,
+    name: String,
+    scope: String,
+    created_at: DateTimeWithTimeZone,
+    expires_at: Option<DateTimeWithTimeZone>,
+    last_used_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<api_token::Model> for TokenInfo {
+    fn from(value: api_token::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            scope: value.scope,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            last_used_at: value.last_used_at,
+        }
+    }
+}
+
+fn internal_error(action: &str, e: DbErr) -> Response {
+    eprintln!("Failed to {}: {}", action, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+        .into_response()
+}
+
+/// Creates a named API token. The token is sent as `Authorization: Bearer <token>`.
+pub async fn create_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(CreateToken {
+        name,
+        scope,
+        expires_in_days,
+    }): Json<CreateToken>,
+) -> Result<Json<CreatedToken>, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    if scope == Scope::Admin && !user.is_admin {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only admins can create tokens with the admin scope",
+        )
+            .into_response());
+    }
+
+    let expires_at = expires_in_days
+        .map(|days| (chrono::Utc::now() + chrono::Duration::days(days.into())).fixed_offset());
+
+    issue_token(&conn, user.username, name, scope, expires_at)
+        .await
+        .map(Json)
+        .map_err(|e| internal_error("create token", e))
+}
+
+pub async fn issue_token(
+    conn: &DatabaseConnection,
+    username: String,
+    name: String,
+    scope: Scope,
+    expires_at: Option<DateTimeWithTimeZone>,
+) -> Result<CreatedToken, DbErr> {
+    let id: String = nanoid::nanoid!();
+    let secret: String = nanoid::nanoid!(48);
+
+    api_token::ActiveModel {
+        id: Set(id.clone()),
+        username: Set(username),
+        name: Set(name),
+        token_hash: Set(hash_token(&secret)),
+        scope: Set(scope.as_str().into()),
+        created_at: NotSet,
+        expires_at: Set(expires_at),
+        last_used_at: Set(None),
+    }
+    .insert(conn)
+    .await?;
+
+    Ok(CreatedToken {
+        token: format!("{}.{}", id, secret),
+        id,
+    })
+}
+
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<TokenInfo>>, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    let tokens = ApiToken::find()
+        .filter(api_token::Column::Username.eq(&user.username))
+        .order_by_asc(api_token::Column::CreatedAt)
+        .all(conn.as_ref())
+        .await
+        .map_err(|e| internal_error("list tokens", e))?;
+
+    Ok(Json(tokens.into_iter().map(Into::into).collect()))
+}
+
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    // Filtering on the username as well keeps users from revoking each other's tokens
+    let result = ApiToken::delete_many()
+        .filter(api_token::Column::Id.eq(&id))
+        .filter(api_token::Column::Username.eq(&user.username))
+        .exec(conn.as_ref())
+        .await
+        .map_err(|e| internal_error("revoke token", e))?;
+
+    if result.rows_affected == 0 {
+        return Err((StatusCode::NOT_FOUND, "No such token").into_response());
+    }
+
+    Ok((StatusCode::OK, "OK").into_response())
+}
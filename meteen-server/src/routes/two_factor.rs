@@ -0,0 +1,214 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use entity::{prelude::*, totp_recovery_code, user};
+use sea_orm::{prelude::*, IntoActiveModel, QueryFilter, Set, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{check_login_headers, hash_token, Scope},
+    routes::tokens::{issue_token, CreatedToken},
+    totp, AppState,
+};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: [char; 32] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'j', 'k', 'm', 'n', 'p', 'q', 'r', 's', 't', 'u', 'v',
+    'w', 'x', 'y', 'z', '2', '3', '4', '5', '6', '7', '8', '9', '0',
+];
+/// How long the credentials handed out by `/login` stay valid
+const SESSION_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Enrolment {
+    secret: String,
+    provisioning_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TotpCode {
+    code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+fn internal_error(action: &str, e: DbErr) -> Response {
+    eprintln!("Failed to {}: {}", action, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+        .into_response()
+}
+
+/// Starts enrolment by generating a new secret. Two-factor authentication is only turned on once
+/// a code for this secret has been confirmed through [`confirm_totp`].
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Enrolment>, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    if user.totp_enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled",
+        )
+            .into_response());
+    }
+
+    let secret = totp::generate_secret();
+    let provisioning_uri = totp::provisioning_uri(&secret, &user.username);
+
+    let mut user = user.into_active_model();
+    user.totp_secret = Set(Some(secret.clone()));
+    user.totp_last_step = Set(None);
+    user.update(conn.as_ref())
+        .await
+        .map_err(|e| internal_error("start enrolment", e))?;
+
+    Ok(Json(Enrolment {
+        secret,
+        provisioning_uri,
+    }))
+}
+
+/// Finishes enrolment and hands out the recovery codes. They are only shown this once.
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(TotpCode { code }): Json<TotpCode>,
+) -> Result<Json<RecoveryCodes>, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    if user.totp_enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled",
+        )
+            .into_response());
+    }
+
+    let secret = match &user.totp_secret {
+        Some(secret) => secret,
+        None => return Err((StatusCode::BAD_REQUEST, "Start the enrolment first").into_response()),
+    };
+
+    let step = match totp::verify(secret, &code, chrono::Utc::now().timestamp()) {
+        Some(step) => step,
+        None => return Err((StatusCode::BAD_REQUEST, "Two-factor code incorrect").into_response()),
+    };
+
+    let username = user.username.clone();
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| nanoid::nanoid!(10, &RECOVERY_CODE_ALPHABET))
+        .collect();
+
+    let failed = |e| internal_error("enable two-factor authentication", e);
+    let transaction = conn.begin().await.map_err(failed)?;
+
+    let mut user = user.into_active_model();
+    user.totp_enabled = Set(true);
+    user.totp_last_step = Set(Some(step));
+    user.update(&transaction).await.map_err(failed)?;
+
+    TotpRecoveryCode::delete_many()
+        .filter(totp_recovery_code::Column::Username.eq(&username))
+        .exec(&transaction)
+        .await
+        .map_err(failed)?;
+
+    TotpRecoveryCode::insert_many(recovery_codes.iter().map(|code| {
+        totp_recovery_code::ActiveModel {
+            id: Set(nanoid::nanoid!()),
+            username: Set(username.clone()),
+            code_hash: Set(hash_token(code)),
+        }
+    }))
+    .exec(&transaction)
+    .await
+    .map_err(failed)?;
+
+    transaction.commit().await.map_err(failed)?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Turns two-factor authentication off. Since the user has it enabled, logging in for this
+/// already required a valid code.
+pub async fn disable_totp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    disable_two_factor(&conn, &user.username)
+        .await
+        .map_err(|e| internal_error("disable two-factor authentication", e))?;
+
+    Ok((StatusCode::OK, "OK").into_response())
+}
+
+/// Clears the secret and recovery codes of a user, shared with the admin CLI.
+pub async fn disable_two_factor(conn: &DatabaseConnection, username: &str) -> Result<(), DbErr> {
+    let transaction = conn.begin().await?;
+
+    User::update_many()
+        .col_expr(user::Column::TotpEnabled, Expr::value(false))
+        .col_expr(
+            user::Column::TotpSecret,
+            Expr::value(Option::<String>::None),
+        )
+        .col_expr(user::Column::TotpLastStep, Expr::value(Option::<i64>::None))
+        .col_expr(user::Column::TotpFailedAttempts, Expr::value(0))
+        .col_expr(
+            user::Column::TotpLockedUntil,
+            Expr::value(Option::<DateTimeWithTimeZone>::None),
+        )
+        .filter(user::Column::Username.eq(username))
+        .exec(&transaction)
+        .await?;
+
+    TotpRecoveryCode::delete_many()
+        .filter(totp_recovery_code::Column::Username.eq(username))
+        .exec(&transaction)
+        .await?;
+
+    transaction.commit().await
+}
+
+/// Exchanges the `Login` header (and a two-factor code, if enabled) for a sync token, so clients
+/// don't need a fresh code for every request.
+pub async fn login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<CreatedToken>, Response> {
+    let AppState { conn, .. } = state;
+
+    let user = check_login_headers(&conn, &headers).await?;
+
+    let expires_at = (chrono::Utc::now() + chrono::Duration::days(SESSION_DAYS)).fixed_offset();
+
+    issue_token(
+        &conn,
+        user.username,
+        "Login session".into(),
+        Scope::Sync,
+        Some(expires_at),
+    )
+    .await
+    .map(Json)
+    .map_err(|e| internal_error("log in", e))
+}
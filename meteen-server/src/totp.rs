@@ -0,0 +1,203 @@
+//! Time-based one-time passwords (RFC 6238) as used by authenticator apps.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+const ISSUER: &str = "Meteen";
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// How many steps a code may be off, to allow for clock drift and slow typing
+const ALLOWED_DRIFT: i64 = 1;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a random 160 bit secret, base32 encoded like authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// The `otpauth://` URI to show as a QR code during enrolment.
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        issuer = ISSUER,
+        username = percent_encode(username),
+    )
+}
+
+/// Checks `code` against the codes around `unix_time` and returns the time step it belongs to.
+///
+/// Callers must store the returned step and reject codes for that step or earlier afterwards,
+/// so a code can't be used twice.
+pub fn verify(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let secret = base32_decode(secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current_step = unix_time / STEP_SECONDS;
+
+    // Every step is checked, so how long this takes doesn't tell which one matched
+    let mut matched = None;
+    for step in current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT {
+        let expected = format_code(hotp(&secret, step as u64, DIGITS));
+        if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) && matched.is_none() {
+            matched = Some(step);
+        }
+    }
+    matched
+}
+
+/// Formats a code the way authenticator apps show it, with leading zeros.
+fn format_code(code: u32) -> String {
+    format!("{:0width$}", code, width = DIGITS as usize)
+}
+
+/// The code an authenticator app shows at `unix_time`.
+#[cfg(test)]
+pub fn code_at(secret: &str, unix_time: i64) -> String {
+    let secret = base32_decode(secret).unwrap();
+    format_code(hotp(&secret, (unix_time / STEP_SECONDS) as u64, DIGITS))
+}
+
+/// HMAC-based one-time password (RFC 4226), which TOTP evaluates at the current time step.
+fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    truncated % 10u32.pow(digits)
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in data.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32_decode, base32_encode, hotp, verify};
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        // Appendix B of RFC 6238, SHA1 variant
+        for (time, expected) in [
+            (59i64, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ] {
+            assert_eq!(hotp(RFC_SECRET, (time / 30) as u64, 8), expected);
+        }
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        assert_eq!(
+            base32_encode(RFC_SECRET),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+        assert_eq!(
+            base32_decode("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap(),
+            RFC_SECRET
+        );
+    }
+
+    #[test]
+    fn verify_allows_drift() {
+        let secret = base32_encode(RFC_SECRET);
+        let code = format!("{:06}", hotp(RFC_SECRET, 1111111109 / 30, 6));
+
+        assert_eq!(verify(&secret, &code, 1111111109), Some(1111111109 / 30));
+        assert_eq!(
+            verify(&secret, &code, 1111111109 + 30),
+            Some(1111111109 / 30)
+        );
+        assert_eq!(verify(&secret, &code, 1111111109 + 90), None);
+    }
+
+    #[test]
+    fn verify_requires_exactly_six_digits() {
+        let secret = base32_encode(RFC_SECRET);
+        let time = 59;
+        let code = format!("{:06}", hotp(RFC_SECRET, (time / 30) as u64, 6));
+
+        assert_eq!(
+            verify(&secret, &format!(" {code}\n"), time),
+            Some(time / 30)
+        );
+        for wrong in [
+            format!("+{}", &code[1..]),
+            format!("0{code}"),
+            format!("000000{code}"),
+            code[1..].to_string(),
+            format!("{}-", &code[..5]),
+            String::new(),
+        ] {
+            assert_eq!(verify(&secret, &wrong, time), None, "accepted {wrong:?}");
+        }
+    }
+}
@@ -1,37 +1,61 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, fs::File, path::PathBuf};
 
 use meteen_model::Database as MeteenVault;
 
-type VaultIndex = usize;
-
 pub struct Vaults {
     base_path: PathBuf,
     cache: HashMap<String, MeteenVault>,
 }
 
+/// Exclusive access to the vaults on disk, released when dropped. See [`Vaults::lock`].
+pub struct VaultsLock {
+    _file: File,
+}
+
 impl Vaults {
     pub fn new(base_path: PathBuf) -> Vaults {
-        dbg!(&base_path);
         Vaults {
             base_path: base_path.join("vaults"),
             cache: HashMap::new(),
         }
     }
 
-    async fn load_vault(&self, id: &str) -> tokio::io::Result<MeteenVault> {
+    /// Takes the advisory lock file in the vaults directory, which fails while someone else holds
+    /// it. The server keeps the vaults it uses in memory and would overwrite what others write to
+    /// them, so it holds the lock while it runs, and the admin commands that change vaults hold it
+    /// until they are done.
+    pub fn lock(&self) -> tokio::io::Result<VaultsLock> {
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.base_path.join(".lock"))?;
+        file.try_lock().map_err(tokio::io::Error::from)?;
+        Ok(VaultsLock { _file: file })
+    }
+
+    /// Reads a vault from disk as it is stored, without repairing it.
+    pub async fn read_vault(&self, id: &str) -> tokio::io::Result<MeteenVault> {
         let vault_path = self.base_path.join(format!("{id}.mtvault"));
         let serialized: Vec<u8> = tokio::fs::read(vault_path).await?;
-        let vault: MeteenVault = bincode::deserialize(&serialized)
-            .map_err(|_| tokio::io::Error::new(tokio::io::ErrorKind::Other, "Unreadable vault"))?;
+        // Vaults written by a newer version are refused rather than loaded without what this
+        // version doesn't know about
+        meteen_model::format::decode(&serialized).map_err(tokio::io::Error::other)
+    }
+
+    /// Reads a vault and repairs whatever is wrong with it, the repairs are saved with the next
+    /// change.
+    async fn load_vault(&self, id: &str) -> tokio::io::Result<MeteenVault> {
+        let mut vault = self.read_vault(id).await?;
+        for problem in vault.repair() {
+            eprintln!("Repaired vault {}: {}", id, problem);
+        }
         Ok(vault)
     }
 
     pub async fn save_vault(&self, id: &str, vault: &MeteenVault) -> tokio::io::Result<()> {
         let vault_path = self.base_path.join(format!("{id}.mtvault"));
-        dbg!(&vault_path);
-        let serialized = bincode::serialize(vault).map_err(|_| {
-            tokio::io::Error::new(tokio::io::ErrorKind::Other, "Unserializable vault")
-        })?;
+        let serialized = meteen_model::format::encode(vault);
         tokio::fs::write(vault_path, serialized).await
     }
 
@@ -45,17 +69,21 @@ impl Vaults {
         let vault_path = self.base_path.join(format!("{id}.mtvault"));
         let vault = self.get_vault(id).await.unwrap();
 
-        dbg!(&vault_path);
-        let serialized = bincode::serialize(vault).map_err(|_| {
-            tokio::io::Error::new(tokio::io::ErrorKind::Other, "Unserializable vault")
-        })?;
-        tokio::fs::write(vault_path, serialized).await;
-        Ok(())
+        let serialized = meteen_model::format::encode(vault);
+        tokio::fs::write(vault_path, serialized).await
     }
 
-    pub async fn delete_vault(&self, id: &str) -> tokio::io::Result<()> {
+    /// Removes the vault from disk and evicts it from the cache. A vault that doesn't exist on
+    /// disk is not an error, so this can safely be used to clean up after a failed creation.
+    pub async fn delete_vault(&mut self, id: &str) -> tokio::io::Result<()> {
+        self.cache.remove(id);
+
         let vault_path = self.base_path.join(format!("{id}.mtvault"));
-        tokio::fs::remove_file(vault_path).await
+        match tokio::fs::remove_file(vault_path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
     }
 
     pub async fn get_vault(&mut self, id: &str) -> tokio::io::Result<&MeteenVault> {
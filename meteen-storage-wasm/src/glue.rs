@@ -1,4 +1,7 @@
-use chrono::{DateTime, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use std::convert::{TryFrom, TryInto};
+
+use chrono::DateTime;
+use meteen_model::RecurrenceParseError;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[derive(Clone)]
@@ -8,39 +11,97 @@ pub struct Project {
     pub project_id: String,
     pub parent_id: Option<String>,
     pub tasks: Vec<Task>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub description: Option<String>,
+    pub sort_key: String,
+    pub created_at_millis: Option<i64>,
+    pub updated_at_millis: Option<i64>,
 }
 
 #[wasm_bindgen]
 impl Project {
+    /// Without an id a new one is generated.
     #[wasm_bindgen(constructor)]
-    pub fn new(id: String) -> Self {
+    pub fn new(id: Option<String>) -> Self {
         Self {
             name: "".into(),
-            project_id: id,
+            project_id: id.unwrap_or_else(meteen_model::ids::new_id),
             parent_id: None,
             tasks: vec![],
+            color: None,
+            icon: None,
+            description: None,
+            sort_key: "".into(),
+            created_at_millis: None,
+            updated_at_millis: None,
         }
     }
 }
 
 impl From<meteen_model::Project> for Project {
     fn from(value: meteen_model::Project) -> Self {
+        // Tasks are handed out in their manual order
+        let tasks = value
+            .ordered_tasks()
+            .into_iter()
+            .cloned()
+            .map(Into::into)
+            .collect();
+
         Self {
             name: value.name,
             project_id: value.project_id,
             parent_id: value.parent_id,
-            tasks: value.tasks.into_iter().map(Into::into).collect(),
+            tasks,
+            color: value.color,
+            icon: value.icon,
+            description: value.description,
+            sort_key: value.sort_key,
+            created_at_millis: value.created_at.map(|at| at.timestamp_millis()),
+            updated_at_millis: value.updated_at.map(|at| at.timestamp_millis()),
         }
     }
 }
 
-impl From<Project> for meteen_model::Project {
-    fn from(value: Project) -> Self {
-        Self {
+/// Fails when one of the tasks has a recurrence that can't be parsed.
+impl TryFrom<Project> for meteen_model::Project {
+    type Error = RecurrenceParseError;
+
+    fn try_from(value: Project) -> Result<Self, Self::Error> {
+        Ok(Self {
             name: value.name,
             project_id: value.project_id,
             parent_id: value.parent_id,
-            tasks: value.tasks.into_iter().map(Into::into).collect(),
+            tasks: (value.tasks.into_iter())
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            color: value.color,
+            icon: value.icon,
+            description: value.description,
+            sort_key: value.sort_key,
+            created_at: value
+                .created_at_millis
+                .and_then(DateTime::from_timestamp_millis),
+            updated_at: value
+                .updated_at_millis
+                .and_then(DateTime::from_timestamp_millis),
+        })
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct ProjectNode {
+    pub project: Project,
+    pub children: Vec<ProjectNode>,
+}
+
+impl From<meteen_model::ProjectNode<'_>> for ProjectNode {
+    fn from(value: meteen_model::ProjectNode) -> Self {
+        Self {
+            project: value.project.clone().into(),
+            children: value.children.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -54,19 +115,40 @@ pub struct Task {
     pub scheduled: Option<DateOrDateTime>,
     pub deadline: Option<DateOrDateTime>,
     pub priority: Priority,
+    /// An RRULE like `FREQ=WEEKLY;BYDAY=MO`, or `X-AFTER-COMPLETION=<days>`
+    pub recurrence: Option<String>,
+    pub parent_task_id: Option<String>,
+    pub sort_key: String,
+    /// The ids of the labels of the task
+    pub labels: Vec<String>,
+    /// Notes in Markdown
+    pub description: Option<String>,
+    pub created_at_millis: Option<i64>,
+    pub updated_at_millis: Option<i64>,
+    /// When the task was last completed, for recurring tasks the last occurrence
+    pub completed_at_millis: Option<i64>,
 }
 
 #[wasm_bindgen]
 impl Task {
+    /// Without an id a new one is generated.
     #[wasm_bindgen(constructor)]
-    pub fn new(id: String) -> Self {
+    pub fn new(id: Option<String>) -> Self {
         Self {
-            task_id: id,
+            task_id: id.unwrap_or_else(meteen_model::ids::new_id),
             summary: "".into(),
             done: false,
             scheduled: None,
             deadline: None,
             priority: Priority { priority: 1 },
+            recurrence: None,
+            parent_task_id: None,
+            sort_key: "".into(),
+            labels: vec![],
+            description: None,
+            created_at_millis: None,
+            updated_at_millis: None,
+            completed_at_millis: None,
         }
     }
 }
@@ -80,20 +162,48 @@ impl From<meteen_model::Task> for Task {
             scheduled: value.scheduled.map(Into::into),
             deadline: value.deadline.map(Into::into),
             priority: value.priority.into(),
+            recurrence: value.recurrence.map(|recurrence| recurrence.to_string()),
+            parent_task_id: value.parent_task_id,
+            sort_key: value.sort_key,
+            labels: value.labels.into_iter().collect(),
+            description: value.description,
+            created_at_millis: value.created_at.map(|at| at.timestamp_millis()),
+            updated_at_millis: value.updated_at.map(|at| at.timestamp_millis()),
+            completed_at_millis: value.completed_at.map(|at| at.timestamp_millis()),
         }
     }
 }
 
-impl From<Task> for meteen_model::Task {
-    fn from(value: Task) -> Self {
-        Self {
+/// Fails when the recurrence can't be parsed.
+impl TryFrom<Task> for meteen_model::Task {
+    type Error = RecurrenceParseError;
+
+    fn try_from(value: Task) -> Result<Self, Self::Error> {
+        Ok(Self {
             task_id: value.task_id,
             summary: value.summary,
             done: value.done,
             scheduled: value.scheduled.map(Into::into),
             deadline: value.deadline.map(Into::into),
             priority: value.priority.into(),
-        }
+            recurrence: value
+                .recurrence
+                .map(|recurrence| recurrence.parse())
+                .transpose()?,
+            parent_task_id: value.parent_task_id,
+            sort_key: value.sort_key,
+            labels: value.labels.into_iter().collect(),
+            description: value.description,
+            created_at: value
+                .created_at_millis
+                .and_then(DateTime::from_timestamp_millis),
+            updated_at: value
+                .updated_at_millis
+                .and_then(DateTime::from_timestamp_millis),
+            completed_at: value
+                .completed_at_millis
+                .and_then(DateTime::from_timestamp_millis),
+        })
     }
 }
 
@@ -183,3 +293,156 @@ impl From<meteen_model::Priority> for Priority {
         }
     }
 }
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub enum DeleteMode {
+    Cascade,
+    MoveToInbox,
+    MoveToParent,
+    RefuseNonEmpty,
+}
+
+impl From<DeleteMode> for meteen_model::DeleteMode {
+    fn from(value: DeleteMode) -> Self {
+        match value {
+            DeleteMode::Cascade => meteen_model::DeleteMode::Cascade,
+            DeleteMode::MoveToInbox => meteen_model::DeleteMode::MoveToInbox,
+            DeleteMode::MoveToParent => meteen_model::DeleteMode::MoveToParent,
+            DeleteMode::RefuseNonEmpty => meteen_model::DeleteMode::RefuseNonEmpty,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct TrashedTask {
+    pub task: Task,
+    pub project_id: String,
+    /// The subtasks that were deleted along with the task
+    pub subtasks: Vec<Task>,
+    pub deleted_at_millis: i64,
+}
+
+impl From<meteen_model::TrashedTask> for TrashedTask {
+    fn from(value: meteen_model::TrashedTask) -> Self {
+        Self {
+            task: value.task.into(),
+            project_id: value.project_id,
+            subtasks: value.subtasks.into_iter().map(Into::into).collect(),
+            deleted_at_millis: value.deleted_at.timestamp_millis(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct TrashedProject {
+    pub project: Project,
+    /// The subprojects that were deleted along with the project
+    pub subprojects: Vec<Project>,
+    pub deleted_at_millis: i64,
+}
+
+impl From<meteen_model::TrashedProject> for TrashedProject {
+    fn from(value: meteen_model::TrashedProject) -> Self {
+        let mut projects = value.projects.into_iter().map(Into::into);
+        Self {
+            // Unwrap is safe because a trashed project always contains the deleted project
+            project: projects.next().unwrap(),
+            subprojects: projects.collect(),
+            deleted_at_millis: value.deleted_at.timestamp_millis(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct Label {
+    pub label_id: String,
+    pub name: String,
+    pub color: Option<String>,
+    /// How many open tasks have this label, only filled in by `get_labels`
+    pub open_tasks: u32,
+}
+
+#[wasm_bindgen]
+impl Label {
+    #[wasm_bindgen(constructor)]
+    pub fn new(id: String) -> Self {
+        Self {
+            label_id: id,
+            name: "".into(),
+            color: None,
+            open_tasks: 0,
+        }
+    }
+}
+
+impl From<meteen_model::Label> for Label {
+    fn from(value: meteen_model::Label) -> Self {
+        Self {
+            label_id: value.label_id,
+            name: value.name,
+            color: value.color,
+            open_tasks: 0,
+        }
+    }
+}
+
+impl From<Label> for meteen_model::Label {
+    fn from(value: Label) -> Self {
+        Self {
+            label_id: value.label_id,
+            name: value.name,
+            color: value.color,
+        }
+    }
+}
+
+/// A recognized part of a quick-add input, in UTF-16 code units like JavaScript strings
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct QuickAddSpan {
+    pub start: u32,
+    pub end: u32,
+    /// One of `scheduled`, `deadline`, `priority`, `project` and `label`
+    pub kind: String,
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct QuickAdd {
+    pub task: Task,
+    pub project_id: Option<String>,
+    pub spans: Vec<QuickAddSpan>,
+}
+
+impl QuickAdd {
+    /// Converts the byte offsets of the spans in `input` to UTF-16 offsets.
+    pub fn new(value: meteen_model::quickadd::QuickAdd, input: &str) -> Self {
+        let utf16 = |byte: usize| input[..byte].encode_utf16().count() as u32;
+        let spans = value
+            .spans
+            .into_iter()
+            .map(|span| QuickAddSpan {
+                start: utf16(span.range.start),
+                end: utf16(span.range.end),
+                kind: match span.kind {
+                    meteen_model::quickadd::SpanKind::Scheduled => "scheduled",
+                    meteen_model::quickadd::SpanKind::Deadline => "deadline",
+                    meteen_model::quickadd::SpanKind::Priority => "priority",
+                    meteen_model::quickadd::SpanKind::Project => "project",
+                    meteen_model::quickadd::SpanKind::Label => "label",
+                }
+                .into(),
+            })
+            .collect();
+
+        Self {
+            task: value.task.into(),
+            project_id: value.project_id,
+            spans,
+        }
+    }
+}
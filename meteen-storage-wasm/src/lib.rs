@@ -1,7 +1,9 @@
 mod glue;
 mod utils;
-use meteen_model::Operation;
+use chrono::Utc;
+use meteen_model::{format, Operation};
 use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
 use std::sync::{LazyLock, Mutex};
 
 use wasm_bindgen::prelude::*;
@@ -26,53 +28,173 @@ pub struct MeteenStorage {
     version: u32,
     data: meteen_model::Database,
     unsynced_operations: Vec<Operation>,
+    /// The inverses of recent changes, each entry undoes one user action
+    #[serde(skip)]
+    undo_stack: Vec<Vec<Operation>>,
+    #[serde(skip)]
+    redo_stack: Vec<Vec<Operation>>,
+}
+
+/// How many actions can be undone
+const UNDO_LIMIT: usize = 100;
+
+impl Default for MeteenStorage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MeteenStorage {
     pub fn new() -> MeteenStorage {
         MeteenStorage {
-            version: 0,
+            version: format::FORMAT_VERSION,
             data: meteen_model::Database::new(),
             unsynced_operations: vec![],
+            undo_stack: vec![],
+            redo_stack: vec![],
         }
     }
 
     fn apply_operation(&mut self, op: Operation) {
-        self.unsynced_operations.push(op.clone());
-        self.data.apply_operation(op);
+        self.apply_operations(vec![op]);
+    }
+
+    /// Applies the operations of a single user action, which is undone as a whole.
+    fn apply_operations(&mut self, ops: Vec<Operation>) {
+        let inverse = self.apply_recording_inverse(ops);
+        self.redo_stack.clear();
+        self.push_undo(inverse);
+    }
+
+    /// Applies the operations like any other change, so they get synced, and returns what
+    /// undoes them.
+    fn apply_recording_inverse(&mut self, ops: Vec<Operation>) -> Vec<Operation> {
+        let mut inverse = vec![];
+        for op in ops {
+            // Later operations have to be undone first
+            let mut op_inverse = self.data.inverse_of(&op, Utc::now());
+            op_inverse.append(&mut inverse);
+            inverse = op_inverse;
+
+            self.unsynced_operations.push(op.clone());
+            self.data.apply_operation(op);
+        }
+        inverse
+    }
+
+    fn push_undo(&mut self, inverse: Vec<Operation>) {
+        if inverse.is_empty() {
+            return;
+        }
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(inverse);
+    }
+
+    /// Returns false when there was nothing to undo.
+    fn undo(&mut self) -> bool {
+        let Some(ops) = self.undo_stack.pop() else {
+            return false;
+        };
+        let inverse = self.apply_recording_inverse(ops);
+        if !inverse.is_empty() {
+            self.redo_stack.push(inverse);
+        }
+        true
+    }
+
+    /// Returns false when there was nothing to redo.
+    fn redo(&mut self) -> bool {
+        let Some(ops) = self.redo_stack.pop() else {
+            return false;
+        };
+        let inverse = self.apply_recording_inverse(ops);
+        self.push_undo(inverse);
+        true
     }
 }
 
 #[wasm_bindgen]
 pub fn serialize() -> Vec<u8> {
     let db = DB.lock().unwrap();
-    bincode::serialize(&*db).unwrap()
+    format::encode(&*db)
 }
 
+/// Loads a vault written by [`serialize`], failing for vaults from a newer version, which
+/// would lose what this version doesn't know about.
 #[wasm_bindgen]
-pub fn deserialize(data: Vec<u8>) {
-    let db: MeteenStorage = bincode::deserialize(&data).unwrap();
+pub fn deserialize(data: Vec<u8>) -> Result<(), JsError> {
+    let db: MeteenStorage = format::decode_with::<_, LegacyStorage>(&data, upgrade_storage)?;
     *DB.lock().unwrap() = db;
+    Ok(())
 }
 
 #[wasm_bindgen]
 pub fn serialize_json() -> String {
-    let db = DB.lock().unwrap();
+    let mut db = DB.lock().unwrap();
+    db.version = format::FORMAT_VERSION;
     serde_json::to_string(&*db).unwrap()
 }
 
+/// Like [`deserialize`] for a vault written by [`serialize_json`].
 #[wasm_bindgen]
-pub fn deserialize_json(json: String) {
-    let db: MeteenStorage = serde_json::from_str(&json).unwrap();
+pub fn deserialize_json(json: String) -> Result<(), JsError> {
+    let mut value: serde_json::Value = serde_json::from_str(&json)?;
+    let version = value["version"]
+        .as_u64()
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(0);
+    if version > format::FORMAT_VERSION {
+        return Err(format::FormatError::NewerVersion(version).into());
+    }
+
+    upgrade_storage(&mut value, version);
+    let db: MeteenStorage = serde_json::from_value(value)?;
     *DB.lock().unwrap() = db;
+    Ok(())
+}
+
+/// [`MeteenStorage`] as it was stored before vaults had a format, see [`format::legacy`]. Like
+/// those types it must never change.
+#[derive(Deserialize)]
+struct LegacyStorage {
+    /// Always 0 back then
+    _version: u32,
+    data: format::legacy::Database,
+    unsynced_operations: Vec<format::legacy::Operation>,
+}
+
+impl From<LegacyStorage> for MeteenStorage {
+    fn from(legacy: LegacyStorage) -> Self {
+        MeteenStorage {
+            data: legacy.data.into(),
+            unsynced_operations: legacy
+                .unsynced_operations
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            ..MeteenStorage::new()
+        }
+    }
 }
 
+/// Migrates the database in a stored [`MeteenStorage`].
+fn upgrade_storage(storage: &mut serde_json::Value, version: u32) {
+    if let Some(data) = storage.get_mut("data") {
+        format::migrate(data, version);
+    }
+}
+
+/// Fails when the recurrence of `task` can't be parsed.
 #[wasm_bindgen]
-pub fn create_task(task: glue::Task, project_id: Option<String>) {
+pub fn create_task(task: glue::Task, project_id: Option<String>) -> Result<(), JsError> {
     DB.lock().unwrap().apply_operation(Operation::CreateTask {
-        task: task.into(),
+        task: task.try_into()?,
         project_id,
-    })
+        at: Some(Utc::now()),
+    });
+    Ok(())
 }
 
 #[wasm_bindgen]
@@ -87,12 +209,211 @@ pub fn get_all_projects() -> JsValue {
         .map(Into::into)
         .collect::<Vec<glue::Project>>();
 
-    return projects.clone().into();
+    projects.into()
+}
+
+#[wasm_bindgen]
+pub fn get_project_tree() -> JsValue {
+    let tree = DB
+        .lock()
+        .unwrap()
+        .data
+        .project_tree()
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<glue::ProjectNode>>();
+
+    tree.into()
+}
+
+#[wasm_bindgen]
+pub fn get_project_path(project_id: String) -> Vec<String> {
+    DB.lock()
+        .unwrap()
+        .data
+        .project_path(&project_id)
+        .into_iter()
+        .map(Into::into)
+        .collect()
+}
+
+#[wasm_bindgen]
+pub fn delete_task(task_id: String) {
+    let op = Operation::DeleteTask {
+        task_id,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn restore_task(task_id: String) {
+    let op = Operation::RestoreTask { task_id };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn get_trashed_tasks() -> JsValue {
+    let tasks = DB
+        .lock()
+        .unwrap()
+        .data
+        .trash
+        .tasks
+        .iter()
+        .cloned()
+        .map(Into::into)
+        .collect::<Vec<glue::TrashedTask>>();
+
+    tasks.into()
 }
 
 #[wasm_bindgen]
-pub fn update_task_done(task_id: String, done: bool) {
-    let op = Operation::UpdateTaskDone { task_id, done };
+pub fn get_trashed_projects() -> JsValue {
+    let projects = DB
+        .lock()
+        .unwrap()
+        .data
+        .trash
+        .projects
+        .iter()
+        .cloned()
+        .map(Into::into)
+        .collect::<Vec<glue::TrashedProject>>();
+
+    projects.into()
+}
+
+/// Permanently removes everything in the trash.
+#[wasm_bindgen]
+pub fn empty_trash() {
+    let op = Operation::PurgeTrash { before: Utc::now() };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn undo() -> bool {
+    DB.lock().unwrap().undo()
+}
+
+#[wasm_bindgen]
+pub fn redo() -> bool {
+    DB.lock().unwrap().redo()
+}
+
+/// Sets the notes of a task. `base` is the text the user started editing from, which lets edits
+/// from other devices in the meantime survive.
+#[wasm_bindgen]
+pub fn update_task_description(task_id: String, description: Option<String>, base: Option<String>) {
+    let op = Operation::UpdateTaskDescription {
+        task_id,
+        description,
+        base,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+/// Marks a task as done or not, with `with_subtasks` completing its subtasks as well.
+#[wasm_bindgen]
+pub fn update_task_done(task_id: String, done: bool, with_subtasks: Option<bool>) {
+    let mut db = DB.lock().unwrap();
+    let occurrence = db
+        .data
+        .get_task(&task_id)
+        .and_then(|task| task.scheduled.clone().or(task.deadline.clone()));
+    let op = Operation::UpdateTaskDone {
+        task_id,
+        done,
+        at: Some(Utc::now()),
+        with_subtasks: with_subtasks.unwrap_or(false),
+        occurrence,
+    };
+    db.apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn get_subtasks(task_id: String) -> JsValue {
+    let tasks = DB
+        .lock()
+        .unwrap()
+        .data
+        .subtasks_of(&task_id)
+        .into_iter()
+        .cloned()
+        .map(Into::into)
+        .collect::<Vec<glue::Task>>();
+
+    tasks.into()
+}
+
+#[wasm_bindgen]
+pub fn indent_task(task_id: String) {
+    let op = Operation::IndentTask {
+        task_id,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn outdent_task(task_id: String) {
+    let op = Operation::OutdentTask {
+        task_id,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+/// Puts a task right after another task with the same parent, or first for `None`.
+#[wasm_bindgen]
+pub fn place_task_after(task_id: String, after_task_id: Option<String>) {
+    let mut db = DB.lock().unwrap();
+    let ops = db.data.place_task_after(&task_id, after_task_id.as_deref());
+    db.apply_operations(ops);
+}
+
+/// Puts a project right after another project with the same parent, or first for `None`.
+#[wasm_bindgen]
+pub fn place_project_after(project_id: String, after_project_id: Option<String>) {
+    let mut db = DB.lock().unwrap();
+    let ops = db
+        .data
+        .place_project_after(&project_id, after_project_id.as_deref());
+    db.apply_operations(ops);
+}
+
+/// Nests a task under another task, or makes it a top-level task for `None`.
+#[wasm_bindgen]
+pub fn set_task_parent(task_id: String, parent_task_id: Option<String>) {
+    let op = Operation::SetTaskParent {
+        task_id,
+        parent_task_id,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+/// Sets or clears how a task repeats, failing when `recurrence` can't be parsed.
+#[wasm_bindgen]
+pub fn update_task_recurrence(task_id: String, recurrence: Option<String>) -> Result<(), JsError> {
+    let recurrence = recurrence.map(|rule| rule.parse()).transpose()?;
+    let op = Operation::UpdateTaskRecurrence {
+        task_id,
+        recurrence,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn update_task_priority(task_id: String, priority: glue::Priority) {
+    let op = Operation::UpdateTaskPriority {
+        task_id,
+        priority: priority.into(),
+        at: Some(Utc::now()),
+    };
     DB.lock().unwrap().apply_operation(op);
 }
 
@@ -101,10 +422,286 @@ pub fn create_new_db() -> MeteenStorage {
     MeteenStorage::new()
 }
 
+/// All labels sorted by name, with their number of open tasks.
 #[wasm_bindgen]
-pub fn create_project(project: glue::Project) {
-    let project: meteen_model::Project = project.into();
-    let op = Operation::CreateProject { project };
+pub fn get_labels() -> JsValue {
+    let db = DB.lock().unwrap();
+    let counts = db.data.label_counts();
+    let labels = db
+        .data
+        .all_labels()
+        .into_iter()
+        .map(|label| glue::Label {
+            open_tasks: counts.get(label.label_id.as_str()).copied().unwrap_or(0) as u32,
+            ..label.clone().into()
+        })
+        .collect::<Vec<glue::Label>>();
 
+    labels.into()
+}
+
+#[wasm_bindgen]
+pub fn get_tasks_with_label(label_id: String) -> JsValue {
+    let tasks = DB
+        .lock()
+        .unwrap()
+        .data
+        .tasks_with_label(&label_id)
+        .into_iter()
+        .cloned()
+        .map(Into::into)
+        .collect::<Vec<glue::Task>>();
+
+    tasks.into()
+}
+
+/// The tasks matching a filter like `overdue & priority >= high`, sorted by a comma separated
+/// list like `-priority,deadline`. Dates in the filter are relative to now in the timezone that
+/// is `utc_offset_minutes` ahead of UTC. That offset is used for every date, so around a
+/// daylight saving change a time close to midnight can count for the day next to it.
+#[wasm_bindgen]
+pub fn query_tasks(
+    filter: String,
+    sort: Option<String>,
+    utc_offset_minutes: i32,
+) -> Result<JsValue, JsError> {
+    let filter: meteen_model::Filter = filter.parse()?;
+    let sort = meteen_model::Sort::parse_list(sort.as_deref().unwrap_or(""))?;
+    let offset = utc_offset_minutes
+        .checked_mul(60)
+        .and_then(chrono::FixedOffset::east_opt)
+        .ok_or_else(|| JsError::new("Invalid UTC offset"))?;
+    let now = Utc::now().with_timezone(&offset);
+
+    let tasks = DB
+        .lock()
+        .unwrap()
+        .data
+        .query(&filter, &sort, &now)
+        .into_iter()
+        .cloned()
+        .map(Into::into)
+        .collect::<Vec<glue::Task>>();
+
+    Ok(tasks.into())
+}
+
+/// Adds the to-dos of an iCalendar document, with the projects and labels they need, as one
+/// action that can be undone. Returns what was left out, like alarms, so the user can be told.
+#[wasm_bindgen]
+pub fn import_ical(ics: String) -> Result<Vec<String>, JsError> {
+    let mut db = DB.lock().unwrap();
+    let import = meteen_model::ical::import(&db.data, &ics, Utc::now())?;
+    db.apply_operations(import.operations);
+    Ok(import.unmapped)
+}
+
+/// Reads what is typed into the quick-add field into a task and its project, without adding it.
+/// Dates are relative to now in the timezone that is `utc_offset_minutes` ahead of UTC.
+///
+/// The offset is that of now and is used for every date, so a time after the next daylight
+/// saving change comes out an hour off. To get those right, call this again with the offset of
+/// the date it found, `-new Date(date).getTimezoneOffset()`.
+#[wasm_bindgen]
+pub fn quick_add(input: String, utc_offset_minutes: i32) -> Result<glue::QuickAdd, JsError> {
+    let offset = utc_offset_minutes
+        .checked_mul(60)
+        .and_then(chrono::FixedOffset::east_opt)
+        .ok_or_else(|| JsError::new("Invalid UTC offset"))?;
+    let now = Utc::now().with_timezone(&offset);
+
+    let quick_add = DB.lock().unwrap().data.quick_add(&input, &now);
+    Ok(glue::QuickAdd::new(quick_add, &input))
+}
+
+#[wasm_bindgen]
+pub fn create_label(label: glue::Label) {
+    let op = Operation::CreateLabel {
+        label: label.into(),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn rename_label(label_id: String, name: String) {
+    let op = Operation::RenameLabel { label_id, name };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn update_label_color(label_id: String, color: Option<String>) {
+    let op = Operation::UpdateLabelColor { label_id, color };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn delete_label(label_id: String) {
+    let op = Operation::DeleteLabel { label_id };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn add_task_label(task_id: String, label_id: String) {
+    let op = Operation::AddTaskLabel {
+        task_id,
+        label_id,
+        at: Some(Utc::now()),
+    };
     DB.lock().unwrap().apply_operation(op);
 }
+
+#[wasm_bindgen]
+pub fn remove_task_label(task_id: String, label_id: String) {
+    let op = Operation::RemoveTaskLabel {
+        task_id,
+        label_id,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+/// Fails when the recurrence of one of the tasks in `project` can't be parsed.
+#[wasm_bindgen]
+pub fn create_project(project: glue::Project) -> Result<(), JsError> {
+    let project: meteen_model::Project = project.try_into()?;
+    let op = Operation::CreateProject {
+        project,
+        at: Some(Utc::now()),
+    };
+
+    DB.lock().unwrap().apply_operation(op);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn delete_project(project_id: String, mode: glue::DeleteMode) {
+    let op = Operation::DeleteProject {
+        project_id,
+        mode: mode.into(),
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn restore_project(project_id: String) {
+    let op = Operation::RestoreProject { project_id };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn move_project(project_id: String, parent_id_to: Option<String>) {
+    let op = Operation::MoveProject {
+        project_id,
+        parent_id_to,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn rename_project(project_id: String, name: String) {
+    let op = Operation::RenameProject {
+        project_id,
+        name,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn update_project_color(project_id: String, color: Option<String>) {
+    let op = Operation::UpdateProjectColor {
+        project_id,
+        color,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn update_project_icon(project_id: String, icon: Option<String>) {
+    let op = Operation::UpdateProjectIcon {
+        project_id,
+        icon,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[wasm_bindgen]
+pub fn update_project_description(project_id: String, description: Option<String>) {
+    let op = Operation::UpdateProjectDescription {
+        project_id,
+        description,
+        at: Some(Utc::now()),
+    };
+    DB.lock().unwrap().apply_operation(op);
+}
+
+#[cfg(test)]
+mod tests {
+    use meteen_model::{Operation, Priority, Task};
+
+    use crate::MeteenStorage;
+
+    fn task(task_id: &str) -> Task {
+        Task {
+            task_id: task_id.into(),
+            summary: "".into(),
+            done: false,
+            scheduled: None,
+            deadline: None,
+            priority: Priority::Standard,
+            recurrence: None,
+            parent_task_id: None,
+            sort_key: String::new(),
+            labels: Default::default(),
+            description: None,
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_queue_operations() {
+        let mut storage = MeteenStorage::new();
+        storage.apply_operation(Operation::CreateTask {
+            task: task("mytask"),
+            project_id: None,
+            at: None,
+        });
+        storage.apply_operation(Operation::UpdateTaskDone {
+            task_id: "mytask".into(),
+            done: true,
+            at: None,
+            with_subtasks: false,
+            occurrence: None,
+        });
+        let done = storage.data.clone();
+
+        assert!(storage.undo());
+        assert!(!storage.data.get_task("mytask").unwrap().done);
+
+        assert!(storage.redo());
+        assert_eq!(storage.data, done);
+        assert!(!storage.redo());
+
+        assert!(storage.undo());
+        assert!(storage.undo());
+        assert!(storage.data.get_task("mytask").is_none());
+        assert!(!storage.undo());
+
+        // Undoing is synced like any other change
+        assert_eq!(storage.unsynced_operations.len(), 6);
+
+        // A new change makes the undone ones impossible to redo
+        storage.apply_operation(Operation::CreateTask {
+            task: task("other"),
+            project_id: None,
+            at: None,
+        });
+        assert!(!storage.redo());
+    }
+}